@@ -0,0 +1,92 @@
+use blul_core::domain::{
+    dtos::blast_builder::BlastBuilder,
+    entities::execute_blastn::{ExecuteBlastn, ExecutionResponse},
+};
+use mycelium_base::utils::errors::{execution_err, MappedErrors};
+use std::{collections::HashMap, fs::read_to_string, path::Path};
+
+/// A fake blastn replaying canned blast tabular outputs
+///
+/// Rows are keyed by the query identifier, the first column of the blast
+/// tabular output. Each run returns the rows of the submitted queries, in the
+/// submission order, so queries without rows are reported without blast
+/// results. The subject reads are not read, then no blast database is
+/// required.
+///
+/// Queries listed as failing are reported as a blast failure of the whole
+/// chunk, as blastn does on unexpected errors.
+#[derive(Debug, Default)]
+pub struct ExecuteBlastnMockRepository {
+    responses: HashMap<String, Vec<String>>,
+    failing_queries: Vec<String>,
+}
+
+impl ExecuteBlastnMockRepository {
+    /// Load the canned rows from a blast tabular output file
+    pub fn from_blast_output(path: &Path) -> Result<Self, MappedErrors> {
+        let content = read_to_string(path).map_err(|err| {
+            execution_err(format!("Unexpected error on read {:?}: {err}", path))
+        })?;
+
+        Ok(Self::default().with_rows(content.lines()))
+    }
+
+    /// Add the canned rows, keyed by the query of each row
+    pub fn with_rows<'a>(
+        mut self,
+        rows: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        for row in rows {
+            if let Some((query, _)) = row.split_once('\t') {
+                self.responses
+                    .entry(query.to_owned())
+                    .or_default()
+                    .push(row.to_owned());
+            }
+        }
+
+        self
+    }
+
+    /// Set the queries whose chunks should fail
+    pub fn with_failing_queries(mut self, queries: Vec<String>) -> Self {
+        self.failing_queries = queries;
+        self
+    }
+}
+
+impl ExecuteBlastn for ExecuteBlastnMockRepository {
+    fn requires_blast_database(&self) -> bool {
+        false
+    }
+
+    fn run(
+        &self,
+        query_sequences: String,
+        _: BlastBuilder,
+        _: usize,
+    ) -> Result<ExecutionResponse, MappedErrors> {
+        let queries = query_sequences
+            .lines()
+            .filter_map(|line| line.strip_prefix('>'))
+            .map(|header| header.split_whitespace().next().unwrap_or_default())
+            .collect::<Vec<&str>>();
+
+        if let Some(query) = queries.iter().find(|query| {
+            self.failing_queries.iter().any(|item| item == *query)
+        }) {
+            return Ok(ExecutionResponse::Fail(format!(
+                "Mock failure of query {query}"
+            )));
+        }
+
+        Ok(ExecutionResponse::Success(
+            queries
+                .iter()
+                .filter_map(|query| self.responses.get(*query))
+                .flatten()
+                .map(|row| format!("{row}\n"))
+                .collect(),
+        ))
+    }
+}
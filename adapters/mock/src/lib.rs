@@ -0,0 +1 @@
+pub mod execute_blast;
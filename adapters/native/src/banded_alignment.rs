@@ -0,0 +1,390 @@
+use blul_core::domain::utils::blastn_statistics::{
+    BLASTN_GAP_EXTEND as GAP_EXTEND, BLASTN_GAP_OPEN as GAP_OPEN,
+    BLASTN_MATCH_SCORE as MATCH_SCORE, BLASTN_MISMATCH_SCORE as MISMATCH_SCORE,
+};
+
+/// Scores lower than any reachable alignment score
+const NEG_INF: i32 = i32::MIN / 4;
+
+/// A local alignment between a query and a subject
+///
+/// Positions are zero based, with inclusive starts and exclusive ends.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Alignment {
+    pub(crate) score: i32,
+    pub(crate) length: usize,
+    pub(crate) identities: usize,
+    pub(crate) mismatches: usize,
+    pub(crate) gap_openings: usize,
+    pub(crate) q_start: usize,
+    pub(crate) q_end: usize,
+    pub(crate) s_start: usize,
+    pub(crate) s_end: usize,
+}
+
+/// The matrix of the traceback state
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    /// Aligned bases
+    Match,
+
+    /// Subject bases aligned to a query gap
+    SubjectGap,
+
+    /// Query bases aligned to a subject gap
+    QueryGap,
+}
+
+/// The dynamic programming matrices restricted to a diagonal band
+///
+/// Row `i` stores the subject columns `i + diagonal - band` to
+/// `i + diagonal + band`.
+struct BandedMatrices {
+    diagonal: i64,
+    band: usize,
+    width: usize,
+    columns: usize,
+    h: Vec<i32>,
+    e: Vec<i32>,
+    f: Vec<i32>,
+}
+
+impl BandedMatrices {
+    fn new(rows: usize, columns: usize, diagonal: i64, band: usize) -> Self {
+        let width = 2 * band + 1;
+        let size = (rows + 1) * width;
+
+        BandedMatrices {
+            diagonal,
+            band,
+            width,
+            columns,
+            h: vec![NEG_INF; size],
+            e: vec![NEG_INF; size],
+            f: vec![NEG_INF; size],
+        }
+    }
+
+    /// The storage index of the cell, if inside the band and the matrix
+    fn index(&self, i: usize, j: usize) -> Option<usize> {
+        if j > self.columns {
+            return None;
+        }
+
+        let offset = j as i64 - (i as i64 + self.diagonal - self.band as i64);
+
+        match offset >= 0 && offset < self.width as i64 {
+            true => Some(i * self.width + offset as usize),
+            false => None,
+        }
+    }
+
+    fn get(&self, matrix: &[i32], i: usize, j: usize) -> i32 {
+        match self.index(i, j) {
+            Some(index) => matrix[index],
+            None => NEG_INF,
+        }
+    }
+
+    /// The subject columns of the row inside the band and the matrix
+    fn row_columns(&self, i: usize) -> std::ops::RangeInclusive<usize> {
+        let start = i as i64 + self.diagonal - self.band as i64;
+        let end = i as i64 + self.diagonal + self.band as i64;
+
+        (start.max(1) as usize)..=(end.clamp(0, self.columns as i64) as usize)
+    }
+}
+
+fn substitution_score(query_base: u8, subject_base: u8) -> i32 {
+    match query_base == subject_base && query_base != b'N' {
+        true => MATCH_SCORE,
+        false => MISMATCH_SCORE,
+    }
+}
+
+/// Align the query to the subject using a banded Smith-Waterman with affine
+/// gap penalties
+///
+/// The band is centered in the diagonal given by `subject - query`
+/// positions. Returns `None` if no positive scoring alignment is found.
+pub(crate) fn align(
+    query: &[u8],
+    subject: &[u8],
+    diagonal: i64,
+    band: usize,
+) -> Option<Alignment> {
+    let mut matrices =
+        BandedMatrices::new(query.len(), subject.len(), diagonal, band);
+
+    let (mut best_score, mut best_cell) = (0, (0, 0));
+
+    // ? -----------------------------------------------------------------------
+    // ? Fill the matrices
+    // ? -----------------------------------------------------------------------
+
+    for i in 1..=query.len() {
+        for j in matrices.row_columns(i) {
+            let index = match matrices.index(i, j) {
+                Some(index) => index,
+                None => continue,
+            };
+
+            let e =
+                (matrices.get(&matrices.h, i, j - 1) - GAP_OPEN - GAP_EXTEND)
+                    .max(matrices.get(&matrices.e, i, j - 1) - GAP_EXTEND);
+
+            let f =
+                (matrices.get(&matrices.h, i - 1, j) - GAP_OPEN - GAP_EXTEND)
+                    .max(matrices.get(&matrices.f, i - 1, j) - GAP_EXTEND);
+
+            let diagonal_score =
+                match matrices.index(i - 1, j - 1) {
+                    Some(previous) => matrices.h[previous].max(0),
+                    None => 0,
+                } + substitution_score(query[i - 1], subject[j - 1]);
+
+            let h = diagonal_score.max(e).max(f).max(0);
+
+            matrices.e[index] = e;
+            matrices.f[index] = f;
+            matrices.h[index] = h;
+
+            if h > best_score {
+                best_score = h;
+                best_cell = (i, j);
+            }
+        }
+    }
+
+    if best_score <= 0 {
+        return None;
+    }
+
+    // ? -----------------------------------------------------------------------
+    // ? Trace back the best local alignment
+    // ? -----------------------------------------------------------------------
+
+    let (mut i, mut j) = best_cell;
+    let mut state = State::Match;
+
+    let mut alignment = Alignment {
+        score: best_score,
+        length: 0,
+        identities: 0,
+        mismatches: 0,
+        gap_openings: 0,
+        q_start: i,
+        q_end: i,
+        s_start: j,
+        s_end: j,
+    };
+
+    while i > 0 && j > 0 {
+        match state {
+            State::Match => {
+                let h = matrices.get(&matrices.h, i, j);
+
+                if h <= 0 {
+                    break;
+                }
+
+                let previous = match i > 1 && j > 1 {
+                    true => matrices.get(&matrices.h, i - 1, j - 1).max(0),
+                    false => 0,
+                };
+
+                if h == previous
+                    + substitution_score(query[i - 1], subject[j - 1])
+                {
+                    alignment.length += 1;
+
+                    match substitution_score(query[i - 1], subject[j - 1]) {
+                        MATCH_SCORE => alignment.identities += 1,
+                        _ => alignment.mismatches += 1,
+                    }
+
+                    alignment.q_start = i - 1;
+                    alignment.s_start = j - 1;
+                    i -= 1;
+                    j -= 1;
+
+                    //
+                    // Local alignments end where the previous score is not
+                    // positive.
+                    //
+                    if previous <= 0 {
+                        break;
+                    }
+                } else if h == matrices.get(&matrices.e, i, j) {
+                    state = State::SubjectGap;
+                } else {
+                    state = State::QueryGap;
+                }
+            }
+            State::SubjectGap => {
+                alignment.length += 1;
+
+                if matrices.get(&matrices.e, i, j)
+                    == matrices.get(&matrices.h, i, j - 1)
+                        - GAP_OPEN
+                        - GAP_EXTEND
+                {
+                    alignment.gap_openings += 1;
+                    state = State::Match;
+                }
+
+                j -= 1;
+            }
+            State::QueryGap => {
+                alignment.length += 1;
+
+                if matrices.get(&matrices.f, i, j)
+                    == matrices.get(&matrices.h, i - 1, j)
+                        - GAP_OPEN
+                        - GAP_EXTEND
+                {
+                    alignment.gap_openings += 1;
+                    state = State::Match;
+                }
+
+                i -= 1;
+            }
+        }
+    }
+
+    Some(alignment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A reproducible pseudo random DNA sequence
+    fn random_sequence(length: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+
+        (0..length)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+
+                b"ACGT"[(state >> 33) as usize % 4]
+            })
+            .collect()
+    }
+
+    fn complement(base: u8) -> u8 {
+        match base {
+            b'A' => b'T',
+            b'C' => b'G',
+            b'G' => b'C',
+            _ => b'A',
+        }
+    }
+
+    #[test]
+    fn exact_matches_are_fully_aligned() {
+        let query = random_sequence(60, 1);
+        let subject = [random_sequence(20, 2), query.to_owned()].concat();
+
+        assert_eq!(
+            align(&query, &subject, 20, 8),
+            Some(Alignment {
+                score: 60 * MATCH_SCORE,
+                length: 60,
+                identities: 60,
+                mismatches: 0,
+                gap_openings: 0,
+                q_start: 0,
+                q_end: 60,
+                s_start: 20,
+                s_end: 80,
+            })
+        );
+    }
+
+    #[test]
+    fn mismatches_are_counted() {
+        let subject = random_sequence(60, 3);
+        let mut query = subject.to_owned();
+        query[30] = complement(query[30]);
+
+        assert_eq!(
+            align(&query, &subject, 0, 8),
+            Some(Alignment {
+                score: 59 * MATCH_SCORE + MISMATCH_SCORE,
+                length: 60,
+                identities: 59,
+                mismatches: 1,
+                gap_openings: 0,
+                q_start: 0,
+                q_end: 60,
+                s_start: 0,
+                s_end: 60,
+            })
+        );
+    }
+
+    #[test]
+    fn query_deletions_open_a_single_gap() {
+        let subject = random_sequence(60, 4);
+        let query = [&subject[..30], &subject[31..]].concat();
+
+        assert_eq!(
+            align(&query, &subject, 0, 8),
+            Some(Alignment {
+                score: 59 * MATCH_SCORE - GAP_OPEN - GAP_EXTEND,
+                length: 60,
+                identities: 59,
+                mismatches: 0,
+                gap_openings: 1,
+                q_start: 0,
+                q_end: 59,
+                s_start: 0,
+                s_end: 60,
+            })
+        );
+    }
+
+    #[test]
+    fn query_insertions_open_a_single_gap() {
+        let subject = random_sequence(60, 5);
+        let query =
+            [&subject[..30], &[complement(subject[29])], &subject[30..]]
+                .concat();
+
+        assert_eq!(
+            align(&query, &subject, 0, 8),
+            Some(Alignment {
+                score: 60 * MATCH_SCORE - GAP_OPEN - GAP_EXTEND,
+                length: 61,
+                identities: 60,
+                mismatches: 0,
+                gap_openings: 1,
+                q_start: 0,
+                q_end: 61,
+                s_start: 0,
+                s_end: 60,
+            })
+        );
+    }
+
+    #[test]
+    fn alignments_out_of_the_band_are_not_found() {
+        let query = random_sequence(60, 6);
+        let subject = [random_sequence(100, 7), query.to_owned()].concat();
+
+        let full = align(&query, &subject, 100, 4).unwrap();
+        assert_eq!((full.identities, full.s_start), (60, 100));
+
+        //
+        // Bands far from the diagonal of the query only reach chance hits.
+        //
+        assert!(align(&query, &subject, 0, 4)
+            .is_none_or(|alignment| alignment.score < full.score / 2));
+
+        assert_eq!(align(&query, &subject, 1000, 4), None);
+        assert_eq!(align(&query, &subject, -1000, 4), None);
+    }
+}
@@ -0,0 +1,492 @@
+use crate::{
+    banded_alignment::{align, Alignment},
+    reference_index::{normalize_sequence, ReferenceIndex},
+};
+
+use blul_core::domain::{
+    dtos::{
+        blast_builder::{BlastBuilder, Strand},
+        blast_tabular_format::{BlastField, BlastTabularFormat},
+    },
+    entities::execute_blastn::{ExecuteBlastn, ExecutionResponse},
+    utils::blastn_statistics::{
+        blastn_bit_score, blastn_e_value, format_bit_score, format_e_value,
+    },
+};
+use mycelium_base::utils::errors::{execution_err, MappedErrors};
+use std::sync::{Arc, Mutex};
+
+/// The smallest kmer size accepted by the native aligner
+const MIN_WORD_SIZE: usize = 8;
+
+/// The largest kmer size accepted by the native aligner
+const MAX_WORD_SIZE: usize = 32;
+
+/// The smallest number of prefilter candidates aligned for each query
+const MIN_CANDIDATES: usize = 50;
+
+/// A pure Rust aligner running against a FASTA reference
+///
+/// Queries are prefiltered by the kmers shared with the reference sequences,
+/// and the best candidates are aligned using a banded Smith-Waterman with the
+/// `blastn` task scores. Hits are reported as the BLAST tabular rows of the
+/// configured output format, so no ncbi-blast+ installation is required.
+///
+/// The subject reads should be a FASTA file instead of a blast database. The
+/// reference index is built once and reused by all chunks of the run.
+#[derive(Debug, Default)]
+pub struct ExecuteBlastnNativeRepository {
+    index: Mutex<Option<Arc<ReferenceIndex>>>,
+}
+
+/// A hit of a query against a reference sequence
+struct Hit {
+    subject: usize,
+    alignment: Alignment,
+    minus_strand: bool,
+    e_value: f64,
+    bit_score: f64,
+}
+
+impl ExecuteBlastnNativeRepository {
+    /// Get the index of the subject reads, building it on the first call
+    fn reference_index(
+        &self,
+        blast_config: &BlastBuilder,
+    ) -> Result<Arc<ReferenceIndex>, MappedErrors> {
+        let word_size = (blast_config.word_size.max(0) as usize)
+            .clamp(MIN_WORD_SIZE, MAX_WORD_SIZE);
+
+        let mut index = self.index.lock().map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on lock the reference index: {err}"
+            ))
+        })?;
+
+        match index.as_ref() {
+            Some(res)
+                if res.subject_reads == blast_config.subject_reads
+                    && res.word_size == word_size =>
+            {
+                Ok(res.to_owned())
+            }
+            _ => {
+                let res = Arc::new(ReferenceIndex::load(
+                    &blast_config.subject_reads,
+                    word_size,
+                )?);
+
+                *index = Some(res.to_owned());
+
+                Ok(res)
+            }
+        }
+    }
+}
+
+impl ExecuteBlastn for ExecuteBlastnNativeRepository {
+    fn requires_blast_database(&self) -> bool {
+        false
+    }
+
+    fn run(
+        &self,
+        query_sequences: String,
+        blast_config: BlastBuilder,
+        threads: usize,
+    ) -> Result<ExecutionResponse, MappedErrors> {
+        let columns =
+            match blast_config.out_format.parse::<BlastTabularFormat>() {
+                Ok(res) => res,
+                Err(err) => return Ok(ExecutionResponse::Fail(err)),
+            };
+
+        if let Some(specifier) =
+            columns.fields().iter().find_map(|field| match field {
+                BlastField::Other(specifier)
+                    if !OTHER_SPECIFIERS.contains(&specifier.as_str()) =>
+                {
+                    Some(specifier)
+                }
+                _ => None,
+            })
+        {
+            return Ok(ExecutionResponse::Fail(format!(
+                "The native aligner does not support the `{specifier}` output field"
+            )));
+        }
+
+        let index = self.reference_index(&blast_config)?;
+        let queries = parse_fasta_queries(&query_sequences);
+
+        //
+        // Queries are split among the threads given to the chunk, keeping the
+        // input order in the output rows.
+        //
+        let chunk_size = queries.len().div_ceil(threads.max(1)).max(1);
+
+        let rows = std::thread::scope(|scope| {
+            queries
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(|| {
+                        chunk
+                            .iter()
+                            .flat_map(|(header, sequence)| {
+                                search_query(&index, sequence, &blast_config)
+                                    .into_iter()
+                                    .map(|hit| {
+                                        format_row(
+                                            &columns, &index, header, sequence,
+                                            &hit,
+                                        )
+                                    })
+                                    .collect::<Vec<String>>()
+                            })
+                            .collect::<Vec<String>>()
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|handle| handle.join())
+                .collect::<Result<Vec<Vec<String>>, _>>()
+        })
+        .map_err(|_| execution_err("Unexpected error on align queries"))?;
+
+        Ok(ExecutionResponse::Success(
+            rows.into_iter()
+                .flatten()
+                .map(|row| row + "\n")
+                .collect::<String>(),
+        ))
+    }
+}
+
+/// The `Other` BLAST format specifiers computed by the native aligner
+const OTHER_SPECIFIERS: [&str; 5] =
+    ["qlen", "slen", "nident", "score", "qcovhsp"];
+
+/// Parse the FASTA queries as `(identifier, sequence)` pairs
+///
+/// The identifier is the first word of the header, as used by blast.
+fn parse_fasta_queries(content: &str) -> Vec<(String, Vec<u8>)> {
+    let mut queries = Vec::<(String, Vec<u8>)>::new();
+
+    for line in content.lines() {
+        match line.strip_prefix('>') {
+            Some(header) => queries.push((
+                header
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .to_owned(),
+                Vec::new(),
+            )),
+            None => {
+                if let Some((_, sequence)) = queries.last_mut() {
+                    sequence.extend(normalize_sequence(line));
+                }
+            }
+        }
+    }
+
+    queries
+}
+
+fn reverse_complement(sequence: &[u8]) -> Vec<u8> {
+    sequence
+        .iter()
+        .rev()
+        .map(|base| match base {
+            b'A' => b'T',
+            b'C' => b'G',
+            b'G' => b'C',
+            b'T' => b'A',
+            _ => b'N',
+        })
+        .collect()
+}
+
+/// Search the query against the reference, returning the best hit of each
+/// subject sorted by bit score
+fn search_query(
+    index: &ReferenceIndex,
+    query: &[u8],
+    blast_config: &BlastBuilder,
+) -> Vec<Hit> {
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let max_target_seqs = blast_config.max_target_seqs.max(1) as usize;
+    let limit = (max_target_seqs * 5).max(MIN_CANDIDATES);
+    let band = 32 + query.len() / 50;
+
+    let reverse = reverse_complement(query);
+    let strands: Vec<(bool, &[u8])> = match blast_config.strand {
+        Strand::Both => vec![(false, query), (true, &reverse)],
+        Strand::Plus => vec![(false, query)],
+        Strand::Minus => vec![(true, &reverse)],
+    };
+
+    let mut hits = Vec::<Hit>::new();
+
+    for (minus_strand, sequence) in strands {
+        for candidate in index.candidates(sequence, limit) {
+            let alignment = match align(
+                sequence,
+                &index.sequences[candidate.subject].sequence,
+                candidate.diagonal,
+                band,
+            ) {
+                Some(res) => res,
+                None => continue,
+            };
+
+            let perc_identity =
+                100.0 * alignment.identities as f64 / alignment.length as f64;
+
+            let query_cov = 100.0
+                * (alignment.q_end - alignment.q_start) as f64
+                / query.len() as f64;
+
+            let e_value = blastn_e_value(
+                alignment.score,
+                query.len(),
+                index.total_length,
+            );
+
+            if perc_identity < blast_config.perc_identity as f64
+                || query_cov < blast_config.query_cov as f64
+                || e_value > blast_config.e_value as f64
+            {
+                continue;
+            }
+
+            let bit_score = blastn_bit_score(alignment.score);
+
+            //
+            // Only the best hit of each subject is kept, as the blast
+            // `max_target_seqs` counts subjects.
+            //
+            match hits.iter_mut().find(|hit| hit.subject == candidate.subject) {
+                Some(hit) if hit.alignment.score >= alignment.score => {}
+                Some(hit) => {
+                    *hit = Hit {
+                        subject: candidate.subject,
+                        alignment,
+                        minus_strand,
+                        e_value,
+                        bit_score,
+                    }
+                }
+                None => hits.push(Hit {
+                    subject: candidate.subject,
+                    alignment,
+                    minus_strand,
+                    e_value,
+                    bit_score,
+                }),
+            }
+        }
+    }
+
+    hits.sort_by(|a, b| {
+        b.alignment
+            .score
+            .cmp(&a.alignment.score)
+            .then(a.subject.cmp(&b.subject))
+    });
+
+    hits.truncate(max_target_seqs);
+    hits
+}
+
+/// Format the hit as a BLAST tabular row
+///
+/// Coordinates are one based. Minus strand hits are reported with the query
+/// coordinates in the original orientation and the subject start greater than
+/// the subject end, as blast does.
+fn format_row(
+    columns: &BlastTabularFormat,
+    index: &ReferenceIndex,
+    query_id: &str,
+    query: &[u8],
+    hit: &Hit,
+) -> String {
+    let subject = &index.sequences[hit.subject];
+    let alignment = &hit.alignment;
+
+    let (q_start, q_end, s_start, s_end) = match hit.minus_strand {
+        false => (
+            alignment.q_start + 1,
+            alignment.q_end,
+            alignment.s_start + 1,
+            alignment.s_end,
+        ),
+        true => (
+            query.len() - alignment.q_end + 1,
+            query.len() - alignment.q_start,
+            alignment.s_end,
+            alignment.s_start + 1,
+        ),
+    };
+
+    columns
+        .fields()
+        .iter()
+        .map(|field| match field {
+            BlastField::Query => query_id.to_owned(),
+            BlastField::SubjectAccession => subject.accession.to_owned(),
+            BlastField::SubjectTaxid => subject.taxid.to_string(),
+            BlastField::PercIdentity => format!(
+                "{:.3}",
+                100.0 * alignment.identities as f64 / alignment.length as f64
+            ),
+            BlastField::AlignLength => alignment.length.to_string(),
+            BlastField::Mismatches => alignment.mismatches.to_string(),
+            BlastField::GapOpenings => alignment.gap_openings.to_string(),
+            BlastField::QStart => q_start.to_string(),
+            BlastField::QEnd => q_end.to_string(),
+            BlastField::SStart => s_start.to_string(),
+            BlastField::SEnd => s_end.to_string(),
+            BlastField::EValue => format_e_value(hit.e_value),
+            BlastField::BitScore => format_bit_score(hit.bit_score),
+            BlastField::Other(specifier) => match specifier.as_str() {
+                "qlen" => query.len().to_string(),
+                "slen" => subject.sequence.len().to_string(),
+                "nident" => alignment.identities.to_string(),
+                "score" => alignment.score.to_string(),
+                _ => format!(
+                    "{:.0}",
+                    100.0 * (alignment.q_end - alignment.q_start) as f64
+                        / query.len() as f64
+                ),
+            },
+        })
+        .collect::<Vec<String>>()
+        .join("\t")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference_index::ReferenceSequence;
+    use blul_core::domain::dtos::taxon::Taxon;
+
+    /// A reproducible pseudo random DNA sequence
+    fn random_sequence(length: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+
+        (0..length)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+
+                b"ACGT"[(state >> 33) as usize % 4]
+            })
+            .collect()
+    }
+
+    fn reference_index(subject: Vec<u8>) -> ReferenceIndex {
+        ReferenceIndex::new(
+            "reference.fasta",
+            15,
+            vec![ReferenceSequence {
+                accession: "NR_000001.1".to_owned(),
+                taxid: 562,
+                sequence: subject,
+            }],
+        )
+    }
+
+    fn blast_config(strand: Strand) -> BlastBuilder {
+        let mut config =
+            BlastBuilder::default("reference.fasta", Taxon::Bacteria);
+        config.strand = strand;
+        config
+    }
+
+    /// Search the query returning the `qstart qend sstart send` fields of
+    /// each row
+    fn search_coordinates(
+        index: &ReferenceIndex,
+        query: &[u8],
+        strand: Strand,
+    ) -> Vec<String> {
+        let columns = "6 qseqid sstart send qstart qend"
+            .parse::<BlastTabularFormat>()
+            .unwrap();
+
+        search_query(index, query, &blast_config(strand))
+            .iter()
+            .map(|hit| format_row(&columns, index, "query", query, hit))
+            .collect()
+    }
+
+    #[test]
+    fn plus_strand_hits_use_one_based_coordinates() {
+        let subject = random_sequence(300, 1);
+        let query = [b"NNNNNNNNNN".as_slice(), &subject[100..160]].concat();
+
+        assert_eq!(
+            search_coordinates(&reference_index(subject), &query, Strand::Both),
+            vec!["query\t101\t160\t11\t70"]
+        );
+    }
+
+    #[test]
+    fn minus_strand_hits_follow_the_blastn_conventions() {
+        let subject = random_sequence(300, 2);
+
+        //
+        // The query holds the reverse complement of the subject positions 101
+        // to 160, between 10 and 5 ambiguous bases.
+        //
+        let query = [
+            b"NNNNNNNNNN".as_slice(),
+            &reverse_complement(&subject[100..160]),
+            b"NNNNN",
+        ]
+        .concat();
+
+        let index = reference_index(subject);
+
+        //
+        // Query coordinates are given in the query orientation, and the
+        // subject start is greater than the subject end.
+        //
+        assert_eq!(
+            search_coordinates(&index, &query, Strand::Both),
+            vec!["query\t160\t101\t11\t70"]
+        );
+
+        assert_eq!(
+            search_coordinates(&index, &query, Strand::Minus),
+            vec!["query\t160\t101\t11\t70"]
+        );
+
+        assert!(search_coordinates(&index, &query, Strand::Plus).is_empty());
+    }
+
+    #[test]
+    fn rows_follow_the_output_format() {
+        let subject = random_sequence(300, 3);
+        let query = subject[50..250].to_vec();
+        let index = reference_index(subject);
+
+        let columns = "6 qseqid saccver staxid pident length mismatch gapopen qstart qend sstart send nident qlen slen qcovhsp"
+            .parse::<BlastTabularFormat>()
+            .unwrap();
+
+        let hits = search_query(&index, &query, &blast_config(Strand::Both));
+
+        assert_eq!(hits.len(), 1);
+
+        assert_eq!(
+            format_row(&columns, &index, "query", &query, &hits[0]),
+            "query\tNR_000001.1\t562\t100.000\t200\t0\t0\t1\t200\t51\t250\t200\t200\t300\t100"
+        );
+    }
+}
@@ -0,0 +1,4 @@
+mod banded_alignment;
+mod reference_index;
+
+pub mod execute_blast;
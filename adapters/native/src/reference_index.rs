@@ -0,0 +1,413 @@
+use mycelium_base::utils::errors::{execution_err, use_case_err, MappedErrors};
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+use tracing::{info, warn};
+
+/// The extensions tried when the subject reads are given without extension
+const FASTA_EXTENSIONS: [&str; 3] = ["fasta", "fa", "fna"];
+
+/// Kmers found more than this number of times are ignored during the
+/// candidates search, avoiding low complexity regions to flood the hits
+const MAX_KMER_OCCURRENCES: usize = 10_000;
+
+/// A sequence of the reference FASTA file
+pub(crate) struct ReferenceSequence {
+    pub(crate) accession: String,
+    pub(crate) taxid: u64,
+    pub(crate) sequence: Vec<u8>,
+}
+
+/// A subject selected by the kmers prefilter
+pub(crate) struct Candidate {
+    pub(crate) subject: usize,
+    pub(crate) diagonal: i64,
+}
+
+/// A kmer index of a FASTA reference
+///
+/// Kmers are stored sorted as `(kmer, subject, position)` tuples, so the
+/// occurrences of a query kmer are found using binary search.
+pub(crate) struct ReferenceIndex {
+    pub(crate) subject_reads: String,
+    pub(crate) word_size: usize,
+    pub(crate) sequences: Vec<ReferenceSequence>,
+    pub(crate) total_length: u64,
+    kmers: Vec<(u64, u32, u32)>,
+}
+
+impl ReferenceIndex {
+    /// Load the FASTA reference and build the kmer index
+    ///
+    /// The reference is the subject reads path itself, or the first existing
+    /// path with the `fasta`, `fa`, or `fna` extension. Taxids are read from
+    /// the `taxid_map.txt` file beside the reference, as written by the
+    /// `build-db subset` command. Accessions without taxid are reported with
+    /// the `0` taxid, which is resolved by the consensus using the Blutils
+    /// database.
+    pub(crate) fn load(
+        subject_reads: &str,
+        word_size: usize,
+    ) -> Result<ReferenceIndex, MappedErrors> {
+        let fasta_path = match resolve_fasta_path(subject_reads) {
+            Some(path) => path,
+            None => {
+                return use_case_err(format!(
+                    "Invalid reference path: {subject_reads}. The native aligner expects a FASTA file."
+                ))
+                .as_error()
+            }
+        };
+
+        let taxids =
+            load_taxid_map(&fasta_path.with_extension("taxid_map.txt"))?;
+        let sequences = load_fasta_sequences(&fasta_path, &taxids)?;
+
+        if sequences.is_empty() {
+            return use_case_err(format!(
+                "No sequences found in the reference {:?}",
+                fasta_path
+            ))
+            .as_error();
+        }
+
+        if taxids.is_empty() {
+            warn!(
+                "Taxid map not found for {:?}. Taxids will be resolved from the Blutils database accessions.",
+                fasta_path
+            );
+        }
+
+        let index = Self::new(subject_reads, word_size, sequences);
+
+        info!(
+            "{} reference sequences indexed from {:?} using {word_size}-mers",
+            index.sequences.len(),
+            fasta_path
+        );
+
+        Ok(index)
+    }
+
+    /// Build the kmer index of the reference sequences
+    pub(crate) fn new(
+        subject_reads: &str,
+        word_size: usize,
+        sequences: Vec<ReferenceSequence>,
+    ) -> ReferenceIndex {
+        let mut kmers = Vec::<(u64, u32, u32)>::new();
+
+        for (subject, item) in sequences.iter().enumerate() {
+            for_each_kmer(&item.sequence, word_size, |position, kmer| {
+                kmers.push((kmer, subject as u32, position as u32));
+            });
+        }
+
+        kmers.sort_unstable();
+
+        let total_length = sequences
+            .iter()
+            .map(|item| item.sequence.len() as u64)
+            .sum();
+
+        ReferenceIndex {
+            subject_reads: subject_reads.to_owned(),
+            word_size,
+            sequences,
+            total_length,
+            kmers,
+        }
+    }
+
+    /// Find the subjects sharing more kmers with the query
+    ///
+    /// Candidates are returned with the diagonal shared by most of its kmer
+    /// hits, used to center the banded alignment.
+    pub(crate) fn candidates(
+        &self,
+        query: &[u8],
+        limit: usize,
+    ) -> Vec<Candidate> {
+        let mut hits = Vec::<(u32, i64)>::new();
+
+        for_each_kmer(query, self.word_size, |query_position, kmer| {
+            let start = self.kmers.partition_point(|item| item.0 < kmer);
+            let end = self.kmers.partition_point(|item| item.0 <= kmer);
+
+            if end - start > MAX_KMER_OCCURRENCES {
+                return;
+            }
+
+            for (_, subject, position) in &self.kmers[start..end] {
+                hits.push((*subject, *position as i64 - query_position as i64));
+            }
+        });
+
+        hits.sort_unstable();
+
+        //
+        // Hits are grouped by subject, counting the hits of the subject and
+        // of its most frequent diagonal.
+        //
+        let mut candidates = Vec::<(usize, Candidate)>::new();
+
+        for subject_hits in hits.chunk_by(|a, b| a.0 == b.0) {
+            let (diagonal, _) = subject_hits
+                .chunk_by(|a, b| a.1 == b.1)
+                .map(|diagonal_hits| (diagonal_hits[0].1, diagonal_hits.len()))
+                .max_by_key(|(_, count)| *count)
+                .unwrap_or((0, 0));
+
+            candidates.push((
+                subject_hits.len(),
+                Candidate {
+                    subject: subject_hits[0].0 as usize,
+                    diagonal,
+                },
+            ));
+        }
+
+        candidates.sort_by_key(|(count, _)| std::cmp::Reverse(*count));
+        candidates.truncate(limit);
+        candidates
+            .into_iter()
+            .map(|(_, candidate)| candidate)
+            .collect()
+    }
+}
+
+impl fmt::Debug for ReferenceIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReferenceIndex")
+            .field("subject_reads", &self.subject_reads)
+            .field("word_size", &self.word_size)
+            .field("sequences", &self.sequences.len())
+            .finish()
+    }
+}
+
+/// Call the visitor with the position and the 2-bit encoded value of each
+/// kmer of the sequence
+///
+/// Kmers containing ambiguous bases are skipped.
+pub(crate) fn for_each_kmer(
+    sequence: &[u8],
+    word_size: usize,
+    mut visitor: impl FnMut(usize, u64),
+) {
+    let mask = match word_size {
+        32 => u64::MAX,
+        size => (1u64 << (2 * size)) - 1,
+    };
+
+    let mut kmer = 0u64;
+    let mut valid = 0usize;
+
+    for (position, base) in sequence.iter().enumerate() {
+        let code = match base {
+            b'A' => 0,
+            b'C' => 1,
+            b'G' => 2,
+            b'T' => 3,
+            _ => {
+                valid = 0;
+                continue;
+            }
+        };
+
+        kmer = ((kmer << 2) | code) & mask;
+        valid += 1;
+
+        if valid >= word_size {
+            visitor(position + 1 - word_size, kmer);
+        }
+    }
+}
+
+/// Normalize a sequence to uppercase DNA bases
+pub(crate) fn normalize_sequence(sequence: &str) -> Vec<u8> {
+    sequence
+        .bytes()
+        .filter(|base| !base.is_ascii_whitespace())
+        .map(|base| match base.to_ascii_uppercase() {
+            b'U' => b'T',
+            base => base,
+        })
+        .collect()
+}
+
+fn resolve_fasta_path(subject_reads: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(subject_reads);
+
+    if path.is_file() {
+        return Some(path);
+    }
+
+    FASTA_EXTENSIONS
+        .iter()
+        .map(|extension| PathBuf::from(format!("{subject_reads}.{extension}")))
+        .find(|path| path.is_file())
+}
+
+/// Load the `accession taxid` lines of the taxid map, if exists
+fn load_taxid_map(path: &Path) -> Result<HashMap<String, u64>, MappedErrors> {
+    let mut taxids = HashMap::<String, u64>::new();
+
+    if !path.is_file() {
+        return Ok(taxids);
+    }
+
+    for line in open_reader(path)?.lines() {
+        let line = line.map_err(|err| read_error(path, err))?;
+        let mut fields = line.split_whitespace();
+
+        if let (Some(accession), Some(taxid)) = (fields.next(), fields.next()) {
+            match taxid.parse::<u64>() {
+                Ok(taxid) => taxids.insert(accession.to_owned(), taxid),
+                Err(_) => {
+                    return use_case_err(format!(
+                        "Invalid taxid `{taxid}` of accession `{accession}` in {:?}",
+                        path
+                    ))
+                    .as_error()
+                }
+            };
+        }
+    }
+
+    Ok(taxids)
+}
+
+fn load_fasta_sequences(
+    path: &Path,
+    taxids: &HashMap<String, u64>,
+) -> Result<Vec<ReferenceSequence>, MappedErrors> {
+    let mut sequences = Vec::<ReferenceSequence>::new();
+
+    for line in open_reader(path)?.lines() {
+        let line = line.map_err(|err| read_error(path, err))?;
+
+        if let Some(header) = line.strip_prefix('>') {
+            let accession =
+                header.split_whitespace().next().unwrap_or_default();
+
+            sequences.push(ReferenceSequence {
+                accession: accession.to_owned(),
+                taxid: taxids.get(accession).copied().unwrap_or(0),
+                sequence: Vec::new(),
+            });
+
+            continue;
+        }
+
+        match sequences.last_mut() {
+            Some(item) => item.sequence.extend(normalize_sequence(&line)),
+            None if line.trim().is_empty() => continue,
+            None => {
+                return use_case_err(format!(
+                    "Invalid FASTA file {:?}: sequence found before the first header",
+                    path
+                ))
+                .as_error()
+            }
+        }
+    }
+
+    Ok(sequences)
+}
+
+fn open_reader(path: &Path) -> Result<BufReader<File>, MappedErrors> {
+    File::open(path).map(BufReader::new).map_err(|err| {
+        execution_err(format!("Unexpected error on open {:?}: {err}", path))
+    })
+}
+
+fn read_error(path: &Path, err: std::io::Error) -> MappedErrors {
+    execution_err(format!("Unexpected error on read {:?}: {err}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect_kmers(sequence: &[u8], word_size: usize) -> Vec<(usize, u64)> {
+        let mut kmers = Vec::<(usize, u64)>::new();
+
+        for_each_kmer(sequence, word_size, |position, kmer| {
+            kmers.push((position, kmer))
+        });
+
+        kmers
+    }
+
+    fn reference_sequence(
+        accession: &str,
+        sequence: &str,
+    ) -> ReferenceSequence {
+        ReferenceSequence {
+            accession: accession.to_owned(),
+            taxid: 0,
+            sequence: sequence.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn kmers_are_two_bit_encoded() {
+        assert_eq!(
+            collect_kmers(b"ACGTA", 3),
+            vec![(0, 0b00_01_10), (1, 0b01_10_11), (2, 0b10_11_00)]
+        );
+    }
+
+    #[test]
+    fn kmers_with_ambiguous_bases_are_skipped() {
+        assert_eq!(
+            collect_kmers(b"ACNGTAR", 2),
+            vec![(0, 0b00_01), (3, 0b10_11), (4, 0b11_00)]
+        );
+
+        assert!(collect_kmers(b"ACG", 4).is_empty());
+    }
+
+    #[test]
+    fn kmers_of_word_size_32_use_the_full_word() {
+        let sequence = [b"T".repeat(32), b"A".to_vec()].concat();
+
+        assert_eq!(
+            collect_kmers(&sequence, 32),
+            vec![(0, u64::MAX), (1, u64::MAX << 2)]
+        );
+
+        let sequence = [b"A".repeat(31), b"C".to_vec()].concat();
+        assert_eq!(collect_kmers(&sequence, 32), vec![(0, 1)]);
+    }
+
+    #[test]
+    fn candidates_are_sorted_by_shared_kmers() {
+        let index = ReferenceIndex::new(
+            "reference.fasta",
+            8,
+            vec![
+                reference_sequence("partial", "ACGTTGCAAGGCTTAA"),
+                reference_sequence("full", "TTTTACGTTGCAAGGCTTAACCGGATCCAT"),
+                reference_sequence("unrelated", "CCCCCCCCCCCCCCCCCCCC"),
+            ],
+        );
+
+        let candidates = index.candidates(b"ACGTTGCAAGGCTTAACCGGATCC", 10);
+
+        assert_eq!(
+            candidates
+                .iter()
+                .map(|candidate| (candidate.subject, candidate.diagonal))
+                .collect::<Vec<(usize, i64)>>(),
+            vec![(1, 4), (0, 0)]
+        );
+
+        assert_eq!(index.candidates(b"ACGTTGCAAGGCTTAACCGGATCC", 1).len(), 1);
+    }
+}
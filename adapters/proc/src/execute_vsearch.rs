@@ -0,0 +1,425 @@
+use blul_core::domain::{
+    dtos::{
+        blast_builder::{BlastBuilder, Strand},
+        blast_tabular_format::{BlastField, BlastTabularFormat},
+    },
+    entities::execute_blastn::{ExecuteBlastn, ExecutionResponse},
+    utils::blastn_statistics::{
+        blastn_bit_score, blastn_e_value, blastn_raw_score, format_bit_score,
+        format_e_value,
+    },
+};
+use mycelium_base::utils::errors::{execution_err, MappedErrors};
+use subprocess::{Exec, Redirection};
+
+/// The `--userout` fields requested to vsearch, in the order parsed by
+/// `VsearchHit::parse`
+const USER_FIELDS: &str =
+    "query+target+ids+mism+gaps+opens+qilo+qihi+tilo+tihi+ql+tl+qstrand";
+
+/// The `Other` BLAST format specifiers computed from the vsearch hits
+const OTHER_SPECIFIERS: [&str; 5] =
+    ["qlen", "slen", "nident", "score", "qcovhsp"];
+
+/// Run `vsearch --usearch_global` reporting hits as blast tabular rows
+///
+/// The subject reads should be a FASTA or UDB file indexed for vsearch. The
+/// `--maxaccepts`, `--id`, and `--query_cov` vsearch options are mapped from
+/// the max target sequences, the percentage of identity, and the query
+/// coverage of the blast configuration. Rejected targets are not limited
+/// (`--maxrejects 0`), so the search does not stop before the accepted hits
+/// are found, as the blast search of the whole subject.
+///
+/// Hits are scored with the `blastn` task scores, so bit scores are
+/// comparable among the hits of a query. E-values are computed using the
+/// length of each target as the search space, then they are not comparable
+/// among the hits of a query nor with the blast e-values. The blast e-value
+/// option is not applied and should be rejected by callers.
+///
+/// Taxids are read from the target headers, given as the
+/// `{taxid}-{oid}-{accession}` identifiers of the `build-db sintax` and
+/// `build-db utax` files, as `;taxid=<taxid>;` annotations, or as the
+/// `kraken:taxid|<taxid>|` prefix of the Kraken2 files. Targets without taxid
+/// are reported with the `0` taxid, which is resolved by the consensus using
+/// the accession map or the Blutils database.
+#[derive(Debug)]
+pub struct ExecuteVsearchProcRepository {}
+
+impl ExecuteBlastn for ExecuteVsearchProcRepository {
+    fn requires_blast_database(&self) -> bool {
+        false
+    }
+
+    fn run(
+        &self,
+        query_sequences: String,
+        blast_config: BlastBuilder,
+        threads: usize,
+    ) -> Result<ExecutionResponse, MappedErrors> {
+        let columns =
+            match blast_config.out_format.parse::<BlastTabularFormat>() {
+                Ok(res) => res,
+                Err(err) => return Ok(ExecutionResponse::Fail(err)),
+            };
+
+        if let Some(specifier) =
+            columns.fields().iter().find_map(|field| match field {
+                BlastField::Other(specifier)
+                    if !OTHER_SPECIFIERS.contains(&specifier.as_str()) =>
+                {
+                    Some(specifier)
+                }
+                _ => None,
+            })
+        {
+            return Ok(ExecutionResponse::Fail(format!(
+                "The vsearch aligner does not support the `{specifier}` output field"
+            )));
+        }
+
+        //
+        // vsearch only searches the plus strand or both strands, so minus
+        // strand hits are filtered from the both strands search.
+        //
+        let strand = match blast_config.strand {
+            Strand::Plus => "plus",
+            Strand::Both | Strand::Minus => "both",
+        };
+
+        let vsearch_response = match Exec::cmd("vsearch")
+            .stdin(&*query_sequences)
+            .arg("--usearch_global")
+            .arg("-")
+            .arg("--db")
+            .arg(&blast_config.subject_reads)
+            .arg("--id")
+            .arg((blast_config.perc_identity as f64 / 100.0).to_string())
+            .arg("--query_cov")
+            .arg((blast_config.query_cov as f64 / 100.0).to_string())
+            .arg("--maxaccepts")
+            .arg(blast_config.max_target_seqs.to_string())
+            .arg("--maxrejects")
+            .arg("0")
+            .arg("--strand")
+            .arg(strand)
+            .arg("--userout")
+            .arg("-")
+            .arg("--userfields")
+            .arg(USER_FIELDS)
+            .arg("--threads")
+            .arg(threads.to_string())
+            .arg("--quiet")
+            .stdout(Redirection::Pipe)
+            .stderr(Redirection::Pipe)
+            .capture()
+        {
+            Err(err) => {
+                return execution_err(format!(
+                    "Unexpected error detected on execute vsearch: {err}"
+                ))
+                .as_error()
+            }
+            Ok(res) => res,
+        };
+
+        if !vsearch_response.success() {
+            return Ok(ExecutionResponse::Fail(vsearch_response.stderr_str()));
+        }
+
+        let mut rows = String::new();
+
+        for line in vsearch_response.stdout_str().lines() {
+            if line.is_empty() {
+                continue;
+            }
+
+            let hit = match VsearchHit::parse(line) {
+                Some(res) => res,
+                None => {
+                    return Ok(ExecutionResponse::Fail(format!(
+                        "Unexpected vsearch output line: {line}"
+                    )))
+                }
+            };
+
+            if let Strand::Minus = blast_config.strand {
+                if !hit.minus_strand {
+                    continue;
+                }
+            }
+
+            rows.push_str(&hit.to_blast_row(&columns));
+            rows.push('\n');
+        }
+
+        Ok(ExecutionResponse::Success(rows))
+    }
+}
+
+/// A `--userout` line of vsearch
+struct VsearchHit {
+    query: String,
+    target: String,
+    identities: usize,
+    mismatches: usize,
+    gaps: usize,
+    gap_openings: usize,
+    q_low: usize,
+    q_high: usize,
+    t_low: usize,
+    t_high: usize,
+    query_length: usize,
+    target_length: usize,
+    minus_strand: bool,
+}
+
+impl VsearchHit {
+    fn parse(line: &str) -> Option<VsearchHit> {
+        let fields = line.split('\t').collect::<Vec<&str>>();
+
+        if fields.len() != USER_FIELDS.split('+').count() {
+            return None;
+        }
+
+        let number = |index: usize| fields[index].trim().parse::<usize>().ok();
+
+        Some(VsearchHit {
+            query: fields[0].to_owned(),
+            target: fields[1].to_owned(),
+            identities: number(2)?,
+            mismatches: number(3)?,
+            gaps: number(4)?,
+            gap_openings: number(5)?,
+            q_low: number(6)?,
+            q_high: number(7)?,
+            t_low: number(8)?,
+            t_high: number(9)?,
+            query_length: number(10)?,
+            target_length: number(11)?,
+            minus_strand: fields[12].trim() == "-",
+        })
+    }
+
+    /// Format the hit as a blast tabular row
+    ///
+    /// The alignment length ignores terminal gaps, as in blast. Minus strand
+    /// hits are reported with the query coordinates in the original
+    /// orientation and the subject start greater than the subject end.
+    fn to_blast_row(&self, columns: &BlastTabularFormat) -> String {
+        let (accession, taxid) = parse_target_label(&self.target);
+        let length = self.identities + self.mismatches + self.gaps;
+        let score = blastn_raw_score(
+            self.identities,
+            self.mismatches,
+            self.gap_openings,
+            self.gaps,
+        );
+
+        let (q_start, q_end, s_start, s_end) = match self.minus_strand {
+            false => (self.q_low, self.q_high, self.t_low, self.t_high),
+            true => (
+                self.query_length + 1 - self.q_high,
+                self.query_length + 1 - self.q_low,
+                self.t_high,
+                self.t_low,
+            ),
+        };
+
+        columns
+            .fields()
+            .iter()
+            .map(|field| match field {
+                BlastField::Query => self.query.to_owned(),
+                BlastField::SubjectAccession => accession.to_owned(),
+                BlastField::SubjectTaxid => taxid.to_string(),
+                BlastField::PercIdentity => format!(
+                    "{:.3}",
+                    100.0 * self.identities as f64 / length.max(1) as f64
+                ),
+                BlastField::AlignLength => length.to_string(),
+                BlastField::Mismatches => self.mismatches.to_string(),
+                BlastField::GapOpenings => self.gap_openings.to_string(),
+                BlastField::QStart => q_start.to_string(),
+                BlastField::QEnd => q_end.to_string(),
+                BlastField::SStart => s_start.to_string(),
+                BlastField::SEnd => s_end.to_string(),
+                BlastField::EValue => format_e_value(blastn_e_value(
+                    score,
+                    self.query_length,
+                    self.target_length as u64,
+                )),
+                BlastField::BitScore => {
+                    format_bit_score(blastn_bit_score(score))
+                }
+                BlastField::Other(specifier) => match specifier.as_str() {
+                    "qlen" => self.query_length.to_string(),
+                    "slen" => self.target_length.to_string(),
+                    "nident" => self.identities.to_string(),
+                    "score" => score.to_string(),
+                    _ => format!(
+                        "{:.0}",
+                        100.0 * (self.q_high + 1 - self.q_low) as f64
+                            / self.query_length.max(1) as f64
+                    ),
+                },
+            })
+            .collect::<Vec<String>>()
+            .join("\t")
+    }
+}
+
+/// Split the target label into the accession and the taxid
+///
+/// Labels without taxid are returned with the `0` taxid.
+fn parse_target_label(label: &str) -> (&str, u64) {
+    if let Some((taxid, accession)) = label
+        .strip_prefix("kraken:taxid|")
+        .and_then(|label| label.split_once('|'))
+    {
+        return (accession, taxid.parse::<u64>().unwrap_or(0));
+    }
+
+    let mut annotations = label.split(';');
+    let identifier = annotations.next().unwrap_or_default();
+
+    let annotated_taxid = annotations
+        .filter_map(|annotation| annotation.strip_prefix("taxid="))
+        .find_map(|taxid| taxid.trim().parse::<u64>().ok());
+
+    //
+    // Identifiers of the Blutils SINTAX and UTAX exports are written as
+    // `{taxid}-{oid}-{accession}`.
+    //
+    let mut parts = identifier.splitn(3, '-');
+
+    if let (Some(taxid), Some(oid), Some(accession)) =
+        (parts.next(), parts.next(), parts.next())
+    {
+        if let (Ok(taxid), Ok(_)) = (taxid.parse::<u64>(), oid.parse::<u64>()) {
+            return (accession, annotated_taxid.unwrap_or(taxid));
+        }
+    }
+
+    (identifier, annotated_taxid.unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLUMNS: &str = "6 qseqid saccver staxid pident length mismatch gapopen qstart qend sstart send nident qlen slen score qcovhsp";
+
+    #[test]
+    fn target_labels_are_split_into_accession_and_taxid() {
+        for (label, expected) in [
+            // Blutils SINTAX and UTAX identifiers
+            ("562-3-NR_024570.1", ("NR_024570.1", 562)),
+            ("562-3-NR_024570.1;tax=d:bacteria", ("NR_024570.1", 562)),
+            (
+                "562-3-accession-with-dashes",
+                ("accession-with-dashes", 562),
+            ),
+            // Taxid annotations
+            ("NR_024570.1;taxid=562;", ("NR_024570.1", 562)),
+            ("NR_024570.1;size=3;taxid= 562", ("NR_024570.1", 562)),
+            ("1-3-NR_024570.1;taxid=562;", ("NR_024570.1", 562)),
+            ("NR_024570.1;taxid=unknown;", ("NR_024570.1", 0)),
+            // Kraken2 headers
+            ("kraken:taxid|562|NR_024570.1", ("NR_024570.1", 562)),
+            ("kraken:taxid|unknown|NR_024570.1", ("NR_024570.1", 0)),
+            // Labels without taxid
+            ("NR_024570.1", ("NR_024570.1", 0)),
+            ("bacteria-3-NR_024570.1", ("bacteria-3-NR_024570.1", 0)),
+            ("562-oid-NR_024570.1", ("562-oid-NR_024570.1", 0)),
+        ] {
+            assert_eq!(parse_target_label(label), expected, "{label}");
+        }
+    }
+
+    #[test]
+    fn userout_lines_are_parsed() {
+        let hit = VsearchHit::parse(
+            "q1\tNR_1\t95\t2\t3\t1\t1\t100\t11\t110\t120\t300\t-",
+        )
+        .unwrap();
+
+        assert_eq!((hit.query.as_str(), hit.target.as_str()), ("q1", "NR_1"));
+
+        assert_eq!(
+            (hit.identities, hit.mismatches, hit.gaps, hit.gap_openings),
+            (95, 2, 3, 1)
+        );
+
+        assert_eq!(
+            (hit.q_low, hit.q_high, hit.t_low, hit.t_high),
+            (1, 100, 11, 110)
+        );
+
+        assert_eq!((hit.query_length, hit.target_length), (120, 300));
+        assert!(hit.minus_strand);
+
+        for line in [
+            "",
+            "q1\tNR_1\t95\t2\t3\t1\t1\t100\t11\t110\t120\t300",
+            "q1\tNR_1\t95\t2\t3\t1\t1\t100\t11\t110\t120\t300\t+\textra",
+            "q1\tNR_1\t95.0\t2\t3\t1\t1\t100\t11\t110\t120\t300\t+",
+            "q1\tNR_1\t95\t2\t3\t1\t1\t100\t11\t110\t120\t*\t+",
+        ] {
+            assert!(VsearchHit::parse(line).is_none(), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn hits_are_formatted_as_blast_rows() {
+        let columns = COLUMNS.parse::<BlastTabularFormat>().unwrap();
+
+        for (line, expected) in [
+            (
+                "q1\t562-3-NR_1\t98\t2\t0\t0\t1\t100\t11\t110\t120\t300\t+",
+                "q1\tNR_1\t562\t98.000\t100\t2\t0\t1\t100\t11\t110\t98\t120\t300\t190\t83",
+            ),
+            //
+            // Gaps are counted in the alignment length and penalized by the
+            // blastn task scores.
+            //
+            (
+                "q1\tNR_1;taxid=562;\t95\t2\t3\t1\t21\t117\t1\t100\t120\t300\t+",
+                "q1\tNR_1\t562\t95.000\t100\t2\t1\t21\t117\t1\t100\t95\t120\t300\t173\t81",
+            ),
+            //
+            // Minus strand query coordinates are converted to the original
+            // orientation, and the subject start is greater than the end.
+            //
+            (
+                "q1\tkraken:taxid|562|NR_1\t100\t0\t0\t0\t6\t105\t11\t110\t120\t300\t-",
+                "q1\tNR_1\t562\t100.000\t100\t0\t0\t16\t115\t110\t11\t100\t120\t300\t200\t83",
+            ),
+        ] {
+            assert_eq!(
+                VsearchHit::parse(line).unwrap().to_blast_row(&columns),
+                expected,
+                "{line}"
+            );
+        }
+    }
+
+    #[test]
+    fn scores_follow_the_blastn_statistics() {
+        let columns =
+            "6 evalue bitscore".parse::<BlastTabularFormat>().unwrap();
+
+        let hit = VsearchHit::parse(
+            "q1\tNR_1\t95\t2\t3\t1\t21\t117\t1\t100\t120\t300\t+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            hit.to_blast_row(&columns),
+            format!(
+                "{}\t{}",
+                format_e_value(blastn_e_value(173, 120, 300)),
+                format_bit_score(blastn_bit_score(173))
+            )
+        );
+    }
+}
@@ -1 +1,2 @@
 pub mod execute_blast;
+pub mod execute_vsearch;
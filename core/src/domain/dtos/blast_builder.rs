@@ -1,8 +1,12 @@
-use super::taxon::Taxon;
+use super::{
+    blast_tabular_format::BlastTabularFormat, cutoff_registry::CutoffPreset,
+    taxon::Taxon,
+};
 
 use md5;
+use mycelium_base::utils::errors::{invalid_arg_err, MappedErrors};
 use serde::{Deserialize, Serialize};
-use std::fmt;
+use std::{fmt, str::FromStr};
 use uuid::Uuid;
 
 // ? --------------------------------------------------------------------------
@@ -37,6 +41,9 @@ impl QuerySequence {
 // ? Wrapper for Blast Builder
 // ? --------------------------------------------------------------------------
 
+/// The default BLAST tabular output format used by blutils
+pub const DEFAULT_BLAST_OUT_FORMAT: &str = "6 qseqid saccver staxid pident length mismatch gapopen qstart qend sstart send evalue bitscore";
+
 #[derive(Clone, Debug, Serialize, clap::ValueEnum, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Strand {
@@ -55,6 +62,53 @@ impl fmt::Display for Strand {
     }
 }
 
+/// A parallel execution setting defined by the user or automatically
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ScheduleSetting {
+    /// Picked from the query count and the available threads
+    Auto,
+
+    /// Fixed by the user
+    Fixed(usize),
+}
+
+impl FromStr for ScheduleSetting {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<ScheduleSetting, Self::Err> {
+        match input.trim().to_lowercase().as_str() {
+            "auto" => Ok(ScheduleSetting::Auto),
+            value => match value.parse::<usize>() {
+                Ok(0) | Err(_) => Err(format!(
+                    "Invalid value `{input}`. Expected `auto` or a positive integer."
+                )),
+                Ok(value) => Ok(ScheduleSetting::Fixed(value)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ScheduleSetting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScheduleSetting::Auto => write!(f, "auto"),
+            ScheduleSetting::Fixed(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+/// The default number of query sequences submitted to each blast process
+pub const DEFAULT_CHUNK_SIZE: usize = 50;
+
+fn default_chunk_size() -> ScheduleSetting {
+    ScheduleSetting::Fixed(DEFAULT_CHUNK_SIZE)
+}
+
+fn default_blast_threads() -> ScheduleSetting {
+    ScheduleSetting::Auto
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BlastBuilder {
@@ -66,6 +120,10 @@ pub struct BlastBuilder {
     pub subject_reads: String,
     pub taxon: Taxon,
 
+    /// The cutoffs preset resolved from the taxon
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub taxon_cutoffs: Option<CutoffPreset>,
+
     // ? BlastN configuration related parameters
     pub out_format: String,
     pub max_target_seqs: i32,
@@ -74,6 +132,16 @@ pub struct BlastBuilder {
     pub strand: Strand,
     pub e_value: f32,
     pub word_size: i32,
+
+    // ? Parallel execution related parameters
+    #[serde(default = "default_chunk_size")]
+    pub chunk_size: ScheduleSetting,
+    #[serde(default = "default_blast_threads")]
+    pub blast_threads: ScheduleSetting,
+
+    // ? Blast output related parameters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub out_checksum: Option<String>,
 }
 
 impl BlastBuilder {
@@ -84,16 +152,25 @@ impl BlastBuilder {
             blutils_version: env!("CARGO_PKG_VERSION").to_string(),
             subject_reads: subject_reads.to_string(),
             taxon,
-            out_format: "6 qseqid saccver staxid pident length mismatch gapopen qstart qend sstart send evalue bitscore".to_string(),
+            taxon_cutoffs: None,
+            out_format: DEFAULT_BLAST_OUT_FORMAT.to_string(),
             max_target_seqs: 10,
             perc_identity: 80,
             query_cov: 80,
             strand: Strand::Both,
             e_value: 0.001,
             word_size: 15,
+            chunk_size: default_chunk_size(),
+            blast_threads: default_blast_threads(),
+            out_checksum: None,
         }
     }
 
+    pub fn with_out_format(mut self, out_format: BlastTabularFormat) -> Self {
+        self.out_format = out_format.to_string();
+        self
+    }
+
     pub fn with_max_target_seqs(mut self, max_target_seqs: i32) -> Self {
         self.max_target_seqs = max_target_seqs;
         self
@@ -123,4 +200,145 @@ impl BlastBuilder {
         self.word_size = word_size;
         self
     }
+
+    pub fn with_chunk_size(mut self, chunk_size: ScheduleSetting) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    pub fn with_blast_threads(
+        mut self,
+        blast_threads: ScheduleSetting,
+    ) -> Self {
+        self.blast_threads = blast_threads;
+        self
+    }
+
+    /// Set the cutoffs preset resolved from the taxon
+    pub fn with_taxon_cutoffs(mut self, taxon_cutoffs: CutoffPreset) -> Self {
+        self.taxon_cutoffs = Some(taxon_cutoffs);
+        self
+    }
+
+    /// Set the MD5 checksum of the blast output file
+    pub fn with_out_checksum(mut self, out_checksum: Option<String>) -> Self {
+        self.out_checksum = out_checksum;
+        self
+    }
+
+    /// Resolve the chunk size and the number of threads of each blast process
+    ///
+    /// Automatic chunk sizes target four chunks per worker to balance the
+    /// load, bounded between 10 and 500 queries. Automatic blast threads split
+    /// the available threads among the chunks that could run concurrently.
+    /// The number of concurrent blast processes should be the available
+    /// threads divided by the blast threads, avoiding CPU oversubscription.
+    ///
+    /// Fixed settings could bypass the `FromStr` validation when built or
+    /// deserialized directly, then zero values are rejected here.
+    pub(crate) fn resolve_schedule(
+        &self,
+        queries: usize,
+        threads: usize,
+    ) -> Result<(usize, usize), MappedErrors> {
+        let threads = threads.max(1);
+
+        let chunk_size = match self.chunk_size {
+            ScheduleSetting::Fixed(0) => {
+                return invalid_arg_err("The chunk size should be positive")
+                    .as_error()
+            }
+            ScheduleSetting::Fixed(size) => size,
+            ScheduleSetting::Auto => {
+                queries.div_ceil(threads * 4).clamp(10, 500)
+            }
+        };
+
+        let blast_threads = match self.blast_threads {
+            ScheduleSetting::Fixed(0) => {
+                return invalid_arg_err(
+                    "The number of blast threads should be positive",
+                )
+                .as_error()
+            }
+            ScheduleSetting::Fixed(blast_threads) => blast_threads,
+            ScheduleSetting::Auto => {
+                let concurrent_chunks =
+                    queries.div_ceil(chunk_size).clamp(1, threads);
+
+                (threads / concurrent_chunks).max(1)
+            }
+        };
+
+        Ok((chunk_size, blast_threads))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(
+        chunk_size: ScheduleSetting,
+        blast_threads: ScheduleSetting,
+    ) -> BlastBuilder {
+        BlastBuilder::default("subject.fasta", Taxon::Bacteria)
+            .with_chunk_size(chunk_size)
+            .with_blast_threads(blast_threads)
+    }
+
+    #[test]
+    fn auto_chunk_size_is_clamped() {
+        let config = config(ScheduleSetting::Auto, ScheduleSetting::Auto);
+
+        assert_eq!(config.resolve_schedule(5, 4).unwrap().0, 10);
+        assert_eq!(config.resolve_schedule(1_600, 4).unwrap().0, 100);
+        assert_eq!(config.resolve_schedule(100_000, 4).unwrap().0, 500);
+    }
+
+    #[test]
+    fn auto_blast_threads_split_available_threads() {
+        let config = config(ScheduleSetting::Auto, ScheduleSetting::Auto);
+
+        //
+        // A single chunk could use all available threads, while enough chunks
+        // to feed all threads get a single blast thread each.
+        //
+        assert_eq!(config.resolve_schedule(5, 8).unwrap(), (10, 8));
+        assert_eq!(config.resolve_schedule(1_600, 8).unwrap(), (50, 1));
+    }
+
+    #[test]
+    fn fixed_settings_are_kept() {
+        let config =
+            config(ScheduleSetting::Fixed(7), ScheduleSetting::Fixed(3));
+
+        assert_eq!(config.resolve_schedule(1_000, 8).unwrap(), (7, 3));
+    }
+
+    #[test]
+    fn blast_threads_exceeding_available_threads_are_kept() {
+        let config =
+            config(ScheduleSetting::Fixed(7), ScheduleSetting::Fixed(16));
+
+        assert_eq!(config.resolve_schedule(1_000, 4).unwrap(), (7, 16));
+    }
+
+    #[test]
+    fn zero_settings_are_rejected() {
+        assert!(config(ScheduleSetting::Fixed(0), ScheduleSetting::Auto)
+            .resolve_schedule(100, 4)
+            .is_err());
+
+        assert!(config(ScheduleSetting::Auto, ScheduleSetting::Fixed(0))
+            .resolve_schedule(100, 4)
+            .is_err());
+
+        let deserialized =
+            serde_json::from_str::<ScheduleSetting>(r#"{"fixed":0}"#).unwrap();
+
+        assert!(config(deserialized, ScheduleSetting::Auto)
+            .resolve_schedule(100, 4)
+            .is_err());
+    }
 }
@@ -107,9 +107,10 @@ impl BlastResultRow {
                 .collect::<Vec<TaxonomyBean>>();
 
             if parsed_taxonomy.len() != splitted_taxonomy.len() {
-                return invalid_arg_err(
-                    "Unexpected error on parse taxonomy".to_string(),
-                )
+                return invalid_arg_err(format!(
+                    "Unexpected error on parse taxonomy `{}` of subject {} (taxid {})",
+                    res, self.subject_accession, self.subject_taxid
+                ))
                 .as_error();
             }
 
@@ -125,4 +126,8 @@ impl BlastResultRow {
 pub(crate) struct BlastQueryResult {
     pub query: String,
     pub results: Option<Vec<BlastResultRow>>,
+
+    /// The reason of a query with malformed blast result rows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
 }
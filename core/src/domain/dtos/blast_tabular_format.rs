@@ -0,0 +1,317 @@
+use super::blast_builder::DEFAULT_BLAST_OUT_FORMAT;
+
+use mycelium_base::utils::errors::{invalid_arg_err, MappedErrors};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Subject identifiers which values are not plain accessions, as the
+/// `gi|...|gb|ACC.1|` sequence ids or the GI numbers
+///
+/// Such values never match the accessions of the taxonomy database, so they
+/// are not accepted as the subject accession field.
+const NON_ACCESSION_SUBJECT_IDS: [&str; 4] =
+    ["sseqid", "sallseqid", "sallacc", "sgi"];
+
+/// A field of the BLAST tabular output (`-outfmt 6`)
+///
+/// Fields used by the consensus algorithm are mapped to named variants. Any
+/// other BLAST format specifier is kept as `Other` and ignored during the
+/// consensus generation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BlastField {
+    Query,
+    SubjectAccession,
+    SubjectTaxid,
+    PercIdentity,
+    AlignLength,
+    Mismatches,
+    GapOpenings,
+    QStart,
+    QEnd,
+    SStart,
+    SEnd,
+    EValue,
+    BitScore,
+    Other(String),
+}
+
+impl BlastField {
+    /// The fields that should be present in any BLAST tabular output
+    pub(crate) const REQUIRED: [BlastField; 4] = [
+        BlastField::Query,
+        BlastField::SubjectAccession,
+        BlastField::PercIdentity,
+        BlastField::BitScore,
+    ];
+
+    /// The named fields in the order used by the consensus algorithm
+    pub(crate) const NAMED: [BlastField; 13] = [
+        BlastField::Query,
+        BlastField::SubjectAccession,
+        BlastField::SubjectTaxid,
+        BlastField::PercIdentity,
+        BlastField::AlignLength,
+        BlastField::Mismatches,
+        BlastField::GapOpenings,
+        BlastField::QStart,
+        BlastField::QEnd,
+        BlastField::SStart,
+        BlastField::SEnd,
+        BlastField::EValue,
+        BlastField::BitScore,
+    ];
+
+    /// The BLAST format specifier of the field
+    pub(crate) fn specifier(&self) -> &str {
+        match self {
+            BlastField::Query => "qseqid",
+            BlastField::SubjectAccession => "saccver",
+            BlastField::SubjectTaxid => "staxid",
+            BlastField::PercIdentity => "pident",
+            BlastField::AlignLength => "length",
+            BlastField::Mismatches => "mismatch",
+            BlastField::GapOpenings => "gapopen",
+            BlastField::QStart => "qstart",
+            BlastField::QEnd => "qend",
+            BlastField::SStart => "sstart",
+            BlastField::SEnd => "send",
+            BlastField::EValue => "evalue",
+            BlastField::BitScore => "bitscore",
+            BlastField::Other(specifier) => specifier,
+        }
+    }
+
+    /// The column name used to load the field into a dataframe
+    pub(crate) fn column_name(&self) -> &str {
+        match self {
+            BlastField::Query => "query",
+            BlastField::SubjectAccession => "subject_accession",
+            BlastField::SubjectTaxid => "subject_taxid",
+            BlastField::PercIdentity => "perc_identity",
+            BlastField::AlignLength => "align_length",
+            BlastField::Mismatches => "mismatches",
+            BlastField::GapOpenings => "gap_openings",
+            BlastField::QStart => "q_start",
+            BlastField::QEnd => "q_end",
+            BlastField::SStart => "s_start",
+            BlastField::SEnd => "s_end",
+            BlastField::EValue => "e_value",
+            BlastField::BitScore => "bit_score",
+            BlastField::Other(specifier) => specifier,
+        }
+    }
+}
+
+impl FromStr for BlastField {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<BlastField, Self::Err> {
+        match input.trim() {
+            "" => Err("Empty BLAST format specifier".to_string()),
+            "qseqid" | "qacc" | "qaccver" | "qgi" => Ok(BlastField::Query),
+            "saccver" | "sacc" => Ok(BlastField::SubjectAccession),
+            "staxid" | "staxids" => Ok(BlastField::SubjectTaxid),
+            "pident" => Ok(BlastField::PercIdentity),
+            "length" => Ok(BlastField::AlignLength),
+            "mismatch" => Ok(BlastField::Mismatches),
+            "gapopen" => Ok(BlastField::GapOpenings),
+            "qstart" => Ok(BlastField::QStart),
+            "qend" => Ok(BlastField::QEnd),
+            "sstart" => Ok(BlastField::SStart),
+            "send" => Ok(BlastField::SEnd),
+            "evalue" => Ok(BlastField::EValue),
+            "bitscore" => Ok(BlastField::BitScore),
+            other => Ok(BlastField::Other(other.to_string())),
+        }
+    }
+}
+
+/// The column layout of a BLAST tabular output
+///
+/// Should be created from the same string passed to the `-outfmt` BLAST
+/// argument, e.g. `6 qseqid saccver staxid pident ... bitscore`. The leading
+/// `6` format identifier is optional.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlastTabularFormat {
+    specifiers: Vec<String>,
+    fields: Vec<BlastField>,
+}
+
+impl BlastTabularFormat {
+    pub fn fields(&self) -> &Vec<BlastField> {
+        &self.fields
+    }
+
+    pub fn specifiers(&self) -> &Vec<String> {
+        &self.specifiers
+    }
+
+    /// The column index of the first occurrence of the field
+    pub(crate) fn position(&self, field: &BlastField) -> Option<usize> {
+        self.fields.iter().position(|item| item == field)
+    }
+
+    /// Check if all fields required by the consensus algorithm are present
+    pub fn validate(&self) -> Result<(), MappedErrors> {
+        let missing = BlastField::REQUIRED
+            .iter()
+            .filter(|field| !self.fields.contains(field))
+            .map(|field| field.specifier().to_string())
+            .collect::<Vec<String>>();
+
+        if !missing.is_empty() {
+            let hint = match self.specifiers.iter().find(|specifier| {
+                NON_ACCESSION_SUBJECT_IDS.contains(&specifier.as_str())
+            }) {
+                Some(specifier) => format!(
+                    ". The `{specifier}` values are not accessions, use `saccver` or `sacc` instead"
+                ),
+                None => String::new(),
+            };
+
+            return invalid_arg_err(format!(
+                "Missing required fields in BLAST tabular format `{}`: {}{hint}",
+                self,
+                missing.join(", ")
+            ))
+            .as_error();
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for BlastTabularFormat {
+    fn default() -> Self {
+        DEFAULT_BLAST_OUT_FORMAT
+            .parse()
+            .expect("Invalid default BLAST tabular format")
+    }
+}
+
+impl FromStr for BlastTabularFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<BlastTabularFormat, Self::Err> {
+        let mut specifiers = input
+            .split_whitespace()
+            .map(|specifier| specifier.trim_matches('"').to_string())
+            .filter(|specifier| !specifier.is_empty())
+            .collect::<Vec<String>>();
+
+        if let Some(first) = specifiers.first() {
+            if first.chars().all(|c| c.is_ascii_digit()) {
+                if first != "6" {
+                    return Err(format!(
+                        "Only the BLAST tabular format (6) is supported: {input}"
+                    ));
+                }
+
+                specifiers.remove(0);
+            }
+        }
+
+        if specifiers.is_empty() {
+            return Err(format!("No BLAST format specifiers found: {input}"));
+        }
+
+        let fields = specifiers
+            .iter()
+            .map(|specifier| specifier.parse::<BlastField>())
+            .collect::<Result<Vec<BlastField>, String>>()?;
+
+        Ok(BlastTabularFormat { specifiers, fields })
+    }
+}
+
+impl fmt::Display for BlastTabularFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "6 {}", self.specifiers.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn specifiers_are_mapped_by_name() {
+        let format = "6 qaccver sacc pident qcovs bitscore staxids"
+            .parse::<BlastTabularFormat>()
+            .unwrap();
+
+        assert_eq!(
+            format.fields(),
+            &vec![
+                BlastField::Query,
+                BlastField::SubjectAccession,
+                BlastField::PercIdentity,
+                BlastField::Other("qcovs".to_string()),
+                BlastField::BitScore,
+                BlastField::SubjectTaxid,
+            ]
+        );
+
+        assert_eq!(format.position(&BlastField::BitScore), Some(4));
+        assert_eq!(format.position(&BlastField::EValue), None);
+        assert!(format.validate().is_ok());
+    }
+
+    #[test]
+    fn format_identifier_is_optional() {
+        let with_identifier =
+            "6 qseqid saccver pident bitscore".parse::<BlastTabularFormat>();
+        let without_identifier =
+            "qseqid saccver pident bitscore".parse::<BlastTabularFormat>();
+
+        assert_eq!(with_identifier, without_identifier);
+        assert_eq!(
+            with_identifier.unwrap().to_string(),
+            "6 qseqid saccver pident bitscore"
+        );
+    }
+
+    #[test]
+    fn default_format_is_valid() {
+        let format = BlastTabularFormat::default();
+
+        assert_eq!(format.to_string(), DEFAULT_BLAST_OUT_FORMAT);
+        assert_eq!(format.fields().len(), BlastField::NAMED.len());
+        assert!(format.validate().is_ok());
+    }
+
+    #[test]
+    fn invalid_formats_are_rejected() {
+        for input in ["", "6", "7 qseqid saccver pident bitscore"] {
+            assert!(input.parse::<BlastTabularFormat>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn missing_required_fields_are_reported() {
+        let format = "6 qseqid pident".parse::<BlastTabularFormat>().unwrap();
+        let err = format.validate().unwrap_err().to_string();
+
+        assert!(err.contains("saccver"));
+        assert!(err.contains("bitscore"));
+    }
+
+    #[test]
+    fn non_accession_subject_ids_are_not_subject_accessions() {
+        for specifier in NON_ACCESSION_SUBJECT_IDS {
+            let format = format!("6 qseqid {specifier} pident bitscore")
+                .parse::<BlastTabularFormat>()
+                .unwrap();
+
+            assert_eq!(
+                format.fields()[1],
+                BlastField::Other(specifier.to_string())
+            );
+
+            let err = format.validate().unwrap_err().to_string();
+
+            assert!(err.contains("use `saccver` or `sacc` instead"), "{err}");
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use super::{
     blast_builder::BlastBuilder, consensus_result::QueryWithConsensus,
+    cutoff_registry::CutoffPreset,
 };
 
 use serde::{Deserialize, Serialize};
@@ -9,6 +10,12 @@ use serde::{Deserialize, Serialize};
 pub struct BlutilsOutput {
     pub results: Vec<QueryWithConsensus>,
     pub config: Option<BlastBuilder>,
+
+    /// The cutoffs of outputs built without a blast config
+    ///
+    /// Outputs with a blast config record the cutoffs in the config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub taxon_cutoffs: Option<CutoffPreset>,
 }
 
 // Implements Default for BlutilsOutput
@@ -17,6 +24,14 @@ impl Default for BlutilsOutput {
         BlutilsOutput {
             results: Vec::new(),
             config: None,
+            taxon_cutoffs: None,
         }
     }
 }
+
+/// The first JSONL record of outputs built without a blast config
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TaxonCutoffsRecord {
+    pub(crate) taxon_cutoffs: CutoffPreset,
+}
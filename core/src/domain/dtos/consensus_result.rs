@@ -10,12 +10,29 @@ pub struct QueryWithConsensus {
     pub run_id: Option<Uuid>,
     pub query: String,
     pub taxon: Option<TaxonomyBean>,
+
+    /// The number of reads represented by the query sequence
+    ///
+    /// Populated from size annotations of dereplicated query headers (e.g.
+    /// `;size=123`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abundance: Option<u64>,
+
+    /// The reason of the consensus generation failure
+    ///
+    /// Populated only for queries which the consensus could not be evaluated,
+    /// being the `taxon` field empty in such case.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct QueryWithoutConsensus {
     pub query: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abundance: Option<u64>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -32,6 +49,45 @@ pub enum ConsensusResult {
     /// This option should be used when the consensus checking process found an
     /// appropriate taxonomy.
     ConsensusFound(QueryWithConsensus),
+
+    /// Failed option
+    ///
+    /// This option should be used when the consensus checking process failed
+    /// for the query, e.g. given malformed blast results or taxonomies. The
+    /// remaining queries should be evaluated normally.
+    Failed(QueryWithFailure),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryWithFailure {
+    pub query: String,
+    pub reason: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abundance: Option<u64>,
+}
+
+impl ConsensusResult {
+    pub(crate) fn with_abundance(self, abundance: Option<u64>) -> Self {
+        match self {
+            ConsensusResult::NoConsensusFound(res) => {
+                ConsensusResult::NoConsensusFound(QueryWithoutConsensus {
+                    abundance,
+                    ..res
+                })
+            }
+            ConsensusResult::ConsensusFound(res) => {
+                ConsensusResult::ConsensusFound(QueryWithConsensus {
+                    abundance,
+                    ..res
+                })
+            }
+            ConsensusResult::Failed(res) => {
+                ConsensusResult::Failed(QueryWithFailure { abundance, ..res })
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -0,0 +1,224 @@
+use super::{
+    consensus_result::QueryWithConsensus, linnaean_ranks::LinnaeanRank,
+};
+use crate::domain::utils::round;
+
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, str::FromStr};
+
+#[derive(Clone, Debug, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "camelCase")]
+pub enum SummaryFormat {
+    /// Tab separated values format
+    Tsv,
+
+    /// JSON format
+    Json,
+}
+
+/// Consensus results statistics of a single taxon
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaxonSummary {
+    pub identifier: String,
+    pub queries: usize,
+    pub abundance: u64,
+    pub mutated_share: f64,
+    pub single_match_share: f64,
+    pub mean_perc_identity: f64,
+}
+
+/// Consensus results statistics of a single Linnaean rank
+///
+/// Queries are counted as assigned when the consensus taxonomy includes the
+/// rank. Otherwise, including queries without consensus, they are counted as
+/// unassigned.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RankSummary {
+    pub rank: LinnaeanRank,
+    pub assigned: usize,
+    pub unassigned: usize,
+    pub unassigned_abundance: u64,
+    pub taxa: Vec<TaxonSummary>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsensusSummary {
+    pub queries: usize,
+    pub with_consensus: usize,
+    pub without_consensus: usize,
+    pub ranks: Vec<RankSummary>,
+}
+
+impl ConsensusSummary {
+    /// Build the per-rank summary from the consensus results
+    ///
+    /// Only default Linnaean ranks are summarized, from domain to species.
+    pub fn from_results(results: &[QueryWithConsensus]) -> Self {
+        let ranks = LinnaeanRank::ordered_iter(Some(true))
+            .map(|rank| Self::summarize_rank(rank, results))
+            .collect::<Vec<RankSummary>>();
+
+        let with_consensus = results
+            .iter()
+            .filter(|result| result.taxon.is_some())
+            .count();
+
+        ConsensusSummary {
+            queries: results.len(),
+            with_consensus,
+            without_consensus: results.len() - with_consensus,
+            ranks,
+        }
+    }
+
+    /// Flatten the summary as a tab separated table
+    ///
+    /// Each rank produces one row per taxon followed by a row for the
+    /// unassigned queries.
+    pub fn to_tsv(&self) -> String {
+        let null = "null";
+
+        let mut lines = vec![[
+            "rank",
+            "identifier",
+            "queries",
+            "abundance",
+            "mutated-share",
+            "single-match-share",
+            "mean-perc-identity",
+        ]
+        .join("\t")];
+
+        for rank in &self.ranks {
+            let rank_name = rank.rank.as_full_rank_string();
+
+            for taxon in &rank.taxa {
+                lines.push(format!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    rank_name,
+                    taxon.identifier,
+                    taxon.queries,
+                    taxon.abundance,
+                    taxon.mutated_share,
+                    taxon.single_match_share,
+                    taxon.mean_perc_identity,
+                ));
+            }
+
+            lines.push(format!(
+                "{}\tunassigned\t{}\t{}\t{null}\t{null}\t{null}",
+                rank_name, rank.unassigned, rank.unassigned_abundance,
+            ));
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    fn summarize_rank(
+        rank: &LinnaeanRank,
+        results: &[QueryWithConsensus],
+    ) -> RankSummary {
+        let mut unassigned = 0;
+        let mut unassigned_abundance = 0;
+
+        let grouped_results = results.iter().fold(
+            HashMap::<String, Vec<&QueryWithConsensus>>::new(),
+            |mut acc, result| {
+                match Self::get_rank_identifier(rank, result) {
+                    Some(identifier) => {
+                        acc.entry(identifier).or_default().push(result)
+                    }
+                    None => {
+                        unassigned += 1;
+                        unassigned_abundance += result.abundance.unwrap_or(1);
+                    }
+                };
+
+                acc
+            },
+        );
+
+        let mut taxa = grouped_results
+            .into_iter()
+            .map(|(identifier, results)| {
+                let queries = results.len();
+                let beans = results
+                    .iter()
+                    .filter_map(|result| result.taxon.as_ref())
+                    .collect::<Vec<_>>();
+
+                TaxonSummary {
+                    identifier,
+                    queries,
+                    abundance: results
+                        .iter()
+                        .map(|result| result.abundance.unwrap_or(1))
+                        .sum(),
+                    mutated_share: round(
+                        beans.iter().filter(|bean| bean.mutated).count() as f64
+                            / queries as f64,
+                        3,
+                    ),
+                    single_match_share: round(
+                        beans.iter().filter(|bean| bean.single_match).count()
+                            as f64
+                            / queries as f64,
+                        3,
+                    ),
+                    mean_perc_identity: round(
+                        beans
+                            .iter()
+                            .map(|bean| bean.perc_identity)
+                            .sum::<f64>()
+                            / queries as f64,
+                        3,
+                    ),
+                }
+            })
+            .collect::<Vec<TaxonSummary>>();
+
+        taxa.sort_by(|a, b| {
+            b.queries
+                .cmp(&a.queries)
+                .then(a.identifier.cmp(&b.identifier))
+        });
+
+        RankSummary {
+            rank: rank.to_owned(),
+            assigned: results.len() - unassigned,
+            unassigned,
+            unassigned_abundance,
+            taxa,
+        }
+    }
+
+    /// Find the taxon identifier of the query lineage at the given rank
+    fn get_rank_identifier(
+        rank: &LinnaeanRank,
+        result: &QueryWithConsensus,
+    ) -> Option<String> {
+        let bean = result.taxon.as_ref()?;
+
+        if &bean.reached_rank == rank {
+            return Some(bean.identifier.to_owned());
+        }
+
+        bean.taxonomy
+            .as_ref()?
+            .split(';')
+            .filter_map(|element| element.split_once("__"))
+            .find_map(
+                |(element_rank, identifier)| match LinnaeanRank::from_str(
+                    element_rank,
+                ) {
+                    Ok(element_rank) if &element_rank == rank => {
+                        Some(identifier.to_string())
+                    }
+                    _ => None,
+                },
+            )
+    }
+}
@@ -0,0 +1,417 @@
+use super::taxon::{CustomTaxon, Taxon};
+
+use mycelium_base::utils::errors::{use_case_err, MappedErrors};
+use serde::{Deserialize, Serialize};
+use std::{fs::read_to_string, path::Path, sync::OnceLock};
+
+/// The cutoff registry shipped with Blutils
+const SHIPPED_REGISTRY: &str =
+    include_str!("../../../assets/cutoff-registry.yaml");
+
+static SHIPPED_REGISTRY_CACHE: OnceLock<CutoffRegistry> = OnceLock::new();
+
+/// A named set of per-rank identity cutoffs
+///
+/// Presets are usually targeted to a marker gene, and should reference the
+/// literature which the cutoffs were collected from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CutoffPreset {
+    pub name: String,
+
+    /// Alternative names resolving to the preset, as marker subregions
+    /// sharing the same cutoffs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub marker: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub references: Vec<String>,
+
+    pub cutoffs: CustomTaxon,
+
+    /// The version of the registry which the preset was loaded from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry_version: Option<String>,
+}
+
+impl CutoffPreset {
+    /// Build the preset of the user provided custom cutoffs
+    pub fn from_custom_taxon(cutoffs: CustomTaxon) -> Self {
+        CutoffPreset {
+            name: Taxon::Custom.to_string(),
+            aliases: Vec::new(),
+            marker: None,
+            description: None,
+            references: Vec::new(),
+            cutoffs,
+            registry_version: None,
+        }
+    }
+
+    /// Iterate over the preset name and aliases
+    fn names(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.name).chain(self.aliases.iter())
+    }
+
+    /// Check that the cutoffs are between 0 and 100 and do not decrease from
+    /// the most general to the most specific rank
+    fn validate(&self) -> Result<(), MappedErrors> {
+        let cutoffs = self.cutoffs.cutoffs();
+
+        if let Some(level) = cutoffs
+            .iter()
+            .find(|level| !(0.0..=100.0).contains(&level.identity()))
+        {
+            return use_case_err(format!(
+                "Invalid cutoff `{level}` of the preset `{}`. Cutoffs should be between 0 and 100",
+                self.name
+            ))
+            .as_error();
+        }
+
+        if let Some(pair) = cutoffs
+            .windows(2)
+            .find(|pair| pair[1].identity() < pair[0].identity())
+        {
+            return use_case_err(format!(
+                "Invalid cutoffs of the preset `{}`: `{}` is lower than the more general `{}`",
+                self.name, pair[1], pair[0]
+            ))
+            .as_error();
+        }
+
+        Ok(())
+    }
+}
+
+/// A versioned collection of cutoff presets
+///
+/// The registry shipped with Blutils contains the presets of the default taxon
+/// values and of the common marker genes. Users may extend the registry with
+/// YAML or JSON files of the same layout, overriding presets of the same
+/// name.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CutoffRegistry {
+    pub version: String,
+    pub presets: Vec<CutoffPreset>,
+}
+
+impl CutoffRegistry {
+    /// Get the registry shipped with Blutils
+    pub fn shipped() -> Result<&'static CutoffRegistry, MappedErrors> {
+        if let Some(registry) = SHIPPED_REGISTRY_CACHE.get() {
+            return Ok(registry);
+        }
+
+        let registry = Self::from_yaml_str(SHIPPED_REGISTRY, "shipped")?;
+
+        Ok(SHIPPED_REGISTRY_CACHE.get_or_init(|| registry))
+    }
+
+    /// Load a registry from a YAML or JSON file
+    pub fn from_file(path: &Path) -> Result<CutoffRegistry, MappedErrors> {
+        let content = match read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => {
+                return use_case_err(format!(
+                    "Could not read the cutoff registry {:?}: {err}",
+                    path
+                ))
+                .as_error()
+            }
+        };
+
+        let source = path.to_string_lossy();
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => {
+                let registry: CutoffRegistry =
+                    match serde_json::from_str(&content) {
+                        Ok(registry) => registry,
+                        Err(err) => {
+                            return use_case_err(format!(
+                                "Could not parse the cutoff registry {source}: {err}"
+                            ))
+                            .as_error()
+                        }
+                    };
+
+                registry.validated()
+            }
+            Some("yaml") | Some("yml") => {
+                Self::from_yaml_str(&content, &source)
+            }
+            _ => use_case_err(format!(
+                "Invalid cutoff registry {source}. The registry should be a YAML or JSON file"
+            ))
+            .as_error(),
+        }
+    }
+
+    fn from_yaml_str(
+        content: &str,
+        source: &str,
+    ) -> Result<CutoffRegistry, MappedErrors> {
+        let registry: CutoffRegistry = match serde_yaml::from_str(content) {
+            Ok(registry) => registry,
+            Err(err) => {
+                return use_case_err(format!(
+                    "Could not parse the {source} cutoff registry: {err}"
+                ))
+                .as_error()
+            }
+        };
+
+        registry.validated()
+    }
+
+    /// Validate the presets, recording the registry version on each one
+    fn validated(mut self) -> Result<CutoffRegistry, MappedErrors> {
+        for preset in self.presets.iter_mut() {
+            preset.name = normalize_preset_name(&preset.name);
+
+            preset.aliases = preset
+                .aliases
+                .iter()
+                .map(|alias| normalize_preset_name(alias))
+                .collect();
+
+            preset.validate()?;
+
+            if preset
+                .names()
+                .any(|name| *name == Taxon::Custom.to_string())
+            {
+                return use_case_err(
+                    "The `custom` preset name is reserved for the custom taxon cutoff files",
+                )
+                .as_error();
+            }
+
+            preset.registry_version = Some(self.version.to_owned());
+        }
+
+        for (index, preset) in self.presets.iter().enumerate() {
+            if let Some(name) = preset.names().find(|name| {
+                self.presets[..index].iter().any(|previous| {
+                    previous.names().any(|other| other == *name)
+                })
+            }) {
+                return use_case_err(format!(
+                    "Duplicated preset `{name}` in the cutoff registry"
+                ))
+                .as_error();
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Extend the registry with the presets of other registry
+    ///
+    /// Presets of the other registry override the presets of the same name.
+    pub fn with_registry(mut self, other: CutoffRegistry) -> Self {
+        for preset in other.presets {
+            match self
+                .presets
+                .iter_mut()
+                .find(|current| current.name == preset.name)
+            {
+                Some(current) => *current = preset,
+                None => self.presets.push(preset),
+            }
+        }
+
+        self
+    }
+
+    /// Get a preset by name or alias
+    ///
+    /// Preset names take precedence over aliases, allowing user presets to
+    /// override a single alias of a shipped preset.
+    pub fn get(&self, name: &str) -> Option<&CutoffPreset> {
+        let name = normalize_preset_name(name);
+
+        self.presets
+            .iter()
+            .find(|preset| preset.name == name)
+            .or_else(|| {
+                self.presets
+                    .iter()
+                    .find(|preset| preset.aliases.contains(&name))
+            })
+    }
+
+    /// Resolve the cutoffs of a taxon
+    ///
+    /// The custom taxon uses the user provided cutoffs. Other taxa are
+    /// resolved as the registry preset of the same name.
+    pub fn resolve(
+        &self,
+        taxon: &Taxon,
+        custom_taxon_values: Option<CustomTaxon>,
+    ) -> Result<CutoffPreset, MappedErrors> {
+        if let Taxon::Custom = taxon {
+            return match custom_taxon_values {
+                Some(cutoffs) => {
+                    let preset = CutoffPreset::from_custom_taxon(cutoffs);
+                    preset.validate()?;
+                    Ok(preset)
+                }
+                None => use_case_err(
+                    "Custom taxon values are required when the custom taxon option is selected",
+                )
+                .as_error(),
+            };
+        }
+
+        match self.get(&taxon.to_string()) {
+            Some(preset) => Ok(preset.to_owned()),
+            None => use_case_err(format!(
+                "Unknown cutoff preset `{taxon}`. Available presets: {}",
+                self.presets
+                    .iter()
+                    .map(|preset| preset.name.to_owned())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+            .as_error(),
+        }
+    }
+}
+
+/// Normalize preset names to lowercase slugs, as `16S V4` to `16s-v4`
+pub(crate) fn normalize_preset_name(name: &str) -> String {
+    name.trim()
+        .to_lowercase()
+        .split(|char: char| char.is_whitespace() || char == '_')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_registry_is_valid() {
+        let registry = CutoffRegistry::shipped().unwrap();
+
+        for name in [
+            "fungi",
+            "bacteria",
+            "eukaryotes",
+            "its",
+            "its1",
+            "its2",
+            "16s-v4",
+            "18s",
+            "coi",
+            "rbcl",
+        ] {
+            let preset = registry.get(name).unwrap();
+
+            assert_eq!(
+                preset.registry_version.as_deref(),
+                Some(registry.version.as_str())
+            );
+        }
+
+        for preset in &registry.presets {
+            if !["fungi", "bacteria", "eukaryotes"]
+                .contains(&preset.name.as_str())
+            {
+                assert!(!preset.references.is_empty(), "{}", preset.name);
+            }
+        }
+    }
+
+    #[test]
+    fn aliases_resolve_to_the_preset() {
+        let registry = CutoffRegistry::shipped().unwrap();
+
+        for alias in ["its1", "its2"] {
+            let preset = registry
+                .resolve(&alias.parse::<Taxon>().unwrap(), None)
+                .unwrap();
+
+            assert_eq!(preset.name, "its");
+        }
+    }
+
+    #[test]
+    fn preset_names_are_normalized() {
+        let registry = CutoffRegistry::shipped().unwrap();
+
+        assert!(registry.get("16S V4").is_some());
+        assert!(registry.get("ITS2").is_some());
+    }
+
+    #[test]
+    fn user_presets_override_shipped_presets() {
+        let user = CutoffRegistry::from_yaml_str(
+            "
+version: user-1
+presets:
+  - name: ITS2
+    cutoffs: { domain: 50, species: 99 }
+  - name: trnL
+    cutoffs: { domain: 50, genus: 95, species: 98 }
+",
+            "user",
+        )
+        .unwrap();
+
+        let registry = CutoffRegistry::shipped().unwrap().to_owned();
+        let presets = registry.presets.len();
+        let registry = registry.with_registry(user);
+
+        //
+        // The user `its2` preset overrides the alias of the shipped `its`
+        // preset, which remains available by name and by the `its1` alias.
+        //
+        assert_eq!(registry.presets.len(), presets + 2);
+        assert_eq!(registry.get("its1").unwrap().name, "its");
+
+        let its2 = registry
+            .resolve(&"its2".parse::<Taxon>().unwrap(), None)
+            .unwrap();
+
+        assert_eq!(its2.registry_version.as_deref(), Some("user-1"));
+        assert_eq!(its2.cutoffs.cutoffs().len(), 2);
+        assert!(registry.get("trnl").is_some());
+    }
+
+    #[test]
+    fn invalid_presets_are_rejected() {
+        for content in [
+            "{ version: '1', presets: [{ name: a, cutoffs: { domain: 90, species: 80 } }] }",
+            "{ version: '1', presets: [{ name: a, cutoffs: { domain: 50, species: 101 } }] }",
+            "{ version: '1', presets: [{ name: custom, cutoffs: { domain: 50, species: 99 } }] }",
+            "{ version: '1', presets: [{ name: a, cutoffs: { domain: 50, species: 99 } }, { name: A, cutoffs: { domain: 50, species: 99 } }] }",
+            "{ version: '1', presets: [{ name: a, cutoffs: { domain: 50, species: 99 } }, { name: b, aliases: [A], cutoffs: { domain: 50, species: 99 } }] }",
+            "{ version: '1', presets: [{ name: a, aliases: [custom], cutoffs: { domain: 50, species: 99 } }] }",
+        ] {
+            assert!(CutoffRegistry::from_yaml_str(content, "test").is_err());
+        }
+    }
+
+    #[test]
+    fn unknown_presets_are_reported() {
+        let registry = CutoffRegistry::shipped().unwrap();
+
+        assert!(registry
+            .resolve(&"unknown".parse::<Taxon>().unwrap(), None)
+            .is_err());
+
+        assert!(registry.resolve(&Taxon::Custom, None).is_err());
+    }
+}
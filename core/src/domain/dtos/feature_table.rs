@@ -0,0 +1,181 @@
+use super::{blutils_output::BlutilsOutput, linnaean_ranks::LinnaeanRank};
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, str::FromStr};
+
+/// The feature identifier used for queries not assigned at the target rank
+pub const UNASSIGNED_FEATURE: &str = "Unassigned";
+
+#[derive(Clone, Debug, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "camelCase")]
+pub enum FeatureTableFormat {
+    /// Tab separated values format
+    Tsv,
+
+    /// BIOM 1.0 JSON format
+    Biom,
+}
+
+/// A taxon-by-sample count matrix
+///
+/// Features are the consensus taxonomies truncated at the target rank. Counts
+/// are the sum of the query abundances of each sample, being queries without
+/// abundance annotation counted as a single read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeatureTable {
+    pub rank: LinnaeanRank,
+    pub samples: Vec<String>,
+    pub features: BTreeMap<String, Vec<u64>>,
+}
+
+impl FeatureTable {
+    pub fn new(rank: LinnaeanRank) -> Self {
+        FeatureTable {
+            rank,
+            samples: Vec::new(),
+            features: BTreeMap::new(),
+        }
+    }
+
+    /// Append a sample column to the table
+    pub fn add_sample(&mut self, sample: String, output: &BlutilsOutput) {
+        let sample_index = self.samples.len();
+        self.samples.push(sample);
+
+        for counts in self.features.values_mut() {
+            counts.push(0);
+        }
+
+        for result in &output.results {
+            let feature = result
+                .taxon
+                .as_ref()
+                .and_then(|bean| bean.taxonomy.as_ref())
+                .and_then(|taxonomy| self.truncate_taxonomy(taxonomy))
+                .unwrap_or(UNASSIGNED_FEATURE.to_string());
+
+            let counts =
+                self.features
+                    .entry(feature)
+                    .or_insert(vec![0; sample_index + 1]);
+
+            counts[sample_index] += result.abundance.unwrap_or(1);
+        }
+    }
+
+    /// Truncate the taxonomy at the table rank
+    ///
+    /// Returns `None` if the taxonomy does not reach the table rank.
+    fn truncate_taxonomy(&self, taxonomy: &str) -> Option<String> {
+        let elements = taxonomy.split(';').collect::<Vec<&str>>();
+
+        let position = elements.iter().position(|element| {
+            match element.split_once("__") {
+                Some((rank, _)) => LinnaeanRank::from_str(rank)
+                    .map(|rank| rank == self.rank)
+                    .unwrap_or(false),
+                None => false,
+            }
+        })?;
+
+        Some(elements[..=position].join(";"))
+    }
+
+    pub fn to_tsv(&self) -> String {
+        let mut lines = vec![format!("#OTU ID\t{}", self.samples.join("\t"))];
+
+        for (feature, counts) in &self.features {
+            lines.push(format!(
+                "{}\t{}",
+                feature,
+                counts
+                    .iter()
+                    .map(|count| count.to_string())
+                    .collect::<Vec<String>>()
+                    .join("\t")
+            ));
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    pub fn to_biom(&self) -> BiomTable {
+        BiomTable {
+            id: None,
+            format: "Biological Observation Matrix 1.0.0".to_string(),
+            format_url: "http://biom-format.org".to_string(),
+            table_type: "OTU table".to_string(),
+            generated_by: format!("blutils {}", env!("CARGO_PKG_VERSION")),
+            date: chrono::Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+            rows: self
+                .features
+                .keys()
+                .map(|feature| BiomEntry {
+                    id: feature.to_owned(),
+                    metadata: Some(BiomTaxonomy {
+                        taxonomy: feature
+                            .split(';')
+                            .map(|element| element.to_string())
+                            .collect(),
+                    }),
+                })
+                .collect(),
+            columns: self
+                .samples
+                .iter()
+                .map(|sample| BiomEntry {
+                    id: sample.to_owned(),
+                    metadata: None,
+                })
+                .collect(),
+            matrix_type: "sparse".to_string(),
+            matrix_element_type: "int".to_string(),
+            shape: [self.features.len(), self.samples.len()],
+            data: self
+                .features
+                .values()
+                .enumerate()
+                .flat_map(|(row, counts)| {
+                    counts
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, count)| **count > 0)
+                        .map(move |(column, count)| {
+                            [row as u64, column as u64, *count]
+                        })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The BIOM 1.0 (JSON) representation of a feature table
+///
+/// See http://biom-format.org/documentation/format_versions/biom-1.0.html
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BiomTable {
+    pub id: Option<String>,
+    pub format: String,
+    pub format_url: String,
+    #[serde(rename = "type")]
+    pub table_type: String,
+    pub generated_by: String,
+    pub date: String,
+    pub rows: Vec<BiomEntry>,
+    pub columns: Vec<BiomEntry>,
+    pub matrix_type: String,
+    pub matrix_element_type: String,
+    pub shape: [usize; 2],
+    pub data: Vec<[u64; 3]>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BiomEntry {
+    pub id: String,
+    pub metadata: Option<BiomTaxonomy>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BiomTaxonomy {
+    pub taxonomy: Vec<String>,
+}
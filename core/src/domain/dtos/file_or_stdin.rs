@@ -5,13 +5,13 @@
 /// implementation.
 ///
 ///
-use std::io::{self, BufRead, Read};
+use std::io::{self, BufRead, BufReader, Read};
 use std::marker::PhantomData;
 use std::str::FromStr;
 use std::sync::atomic::AtomicBool;
 use thiserror::Error;
 
-use super::blutils_output::BlutilsOutput;
+use super::blutils_output::{BlutilsOutput, TaxonCutoffsRecord};
 use super::consensus_result::QueryWithConsensus;
 
 static STDIN_HAS_BEEN_USED: AtomicBool = AtomicBool::new(false);
@@ -24,6 +24,8 @@ pub enum StdinError {
     StdIn(#[from] io::Error),
     #[error("unable to parse from_str: {0}")]
     FromStr(String),
+    #[error("invalid sequence content at line {line}: {message}")]
+    InvalidSequence { line: usize, message: String },
 }
 
 /// Source of the value contents will be either from `stdin` or a CLI arg provided value
@@ -72,9 +74,20 @@ pub struct FileOrStdin<T = String> {
 pub struct Sequence {
     header: String,
     sequence: String,
+    abundance: Option<u64>,
 }
 
 impl Sequence {
+    pub fn new(header: String, sequence: String) -> Self {
+        let abundance = Self::parse_abundance(&header);
+
+        Sequence {
+            header,
+            sequence,
+            abundance,
+        }
+    }
+
     pub fn header(&self) -> &str {
         &self.header
     }
@@ -87,6 +100,44 @@ impl Sequence {
         &self.sequence
     }
 
+    pub fn abundance(&self) -> Option<u64> {
+        self.abundance
+    }
+
+    /// Parse the abundance annotation from a fasta header
+    ///
+    /// Dereplication and denoising tools store the number of reads collapsed
+    /// into a sequence at the header. The following annotations are accepted:
+    ///
+    /// - `seq1;size=123;` (usearch, vsearch and DADA2 `uniquesToFasta`)
+    /// - `seq1;count=123` (DADA2 counts)
+    /// - `seq1_size_123` (`;size=` replaced by `_size_` by the book pipeline)
+    ///
+    /// Returns `None` if the header contains no abundance annotation.
+    pub fn parse_abundance(header: &str) -> Option<u64> {
+        for token in header
+            .split(|c: char| c == ';' || c.is_whitespace())
+            .filter(|token| !token.is_empty())
+        {
+            if let Some(value) = token
+                .strip_prefix("size=")
+                .or_else(|| token.strip_prefix("count="))
+            {
+                if let Ok(abundance) = value.parse::<u64>() {
+                    return Some(abundance);
+                }
+            }
+
+            if let Some((_, value)) = token.rsplit_once("_size_") {
+                if let Ok(abundance) = value.parse::<u64>() {
+                    return Some(abundance);
+                }
+            }
+        }
+
+        None
+    }
+
     pub fn to_fasta(&self) -> String {
         format!(">{}\n{}\n", self.header, self.sequence)
     }
@@ -136,6 +187,7 @@ impl FileOrStdin {
         let mut output = BlutilsOutput {
             results: content.to_owned(),
             config: None,
+            taxon_cutoffs: None,
         };
 
         for line in reader.lines() {
@@ -157,6 +209,19 @@ impl FileOrStdin {
                 };
 
                 output.config = Some(config);
+            } else if line.starts_with("{\"taxonCutoffs\"") {
+                let record: TaxonCutoffsRecord =
+                    match serde_json::from_str(&line) {
+                        Ok(value) => value,
+                        Err(err) => {
+                            return Err(StdinError::FromStr(format!(
+                                "unable to parse line as JSON: {}",
+                                err
+                            )));
+                        }
+                    };
+
+                output.taxon_cutoffs = Some(record.taxon_cutoffs);
             } else {
                 let value = match serde_json::from_str(&line) {
                     Ok(value) => value,
@@ -177,47 +242,17 @@ impl FileOrStdin {
 
     /// Read content and build a fasta sequence
     ///
-    /// Content should be a multi fasta file. Each fasta record can contain a
-    /// fasta header starting with `>` and a sequence of a single line or
-    /// multiline sequence.
+    /// Content should be a multi fasta or a fastq file, optionally compressed
+    /// with gzip, bzip2, or zstd. Compression is detected from the content
+    /// itself, so compressed content is also accepted from stdin.
+    ///
+    /// Each fasta record should contain a header starting with `>` followed by
+    /// a single line or multiline sequence. Fastq records should contain the
+    /// four lines format, being qualities validated and dropped. Sequences
+    /// should contain IUPAC nucleotide codes only. Records without sequence
+    /// are rejected.
     pub fn sequence_content(self) -> Result<Vec<Sequence>, StdinError> {
-        let reader = self.into_chunked_reader()?;
-
-        let mut sequences = Vec::<Sequence>::new();
-        let mut header = String::new();
-        let mut sequence = String::new();
-
-        for line in reader.lines() {
-            let line = line?;
-
-            if line.is_empty() {
-                continue;
-            }
-
-            if line.starts_with('>') {
-                if !header.is_empty() {
-                    sequences.push(Sequence {
-                        header: header.clone(),
-                        sequence: sequence.clone(),
-                    });
-                    sequence.clear();
-                } else if !sequence.is_empty() {
-                    return Err(StdinError::FromStr(
-                        "unexpected sequence without header".to_owned(),
-                    ));
-                }
-
-                header = line.replace(">", "");
-            } else {
-                sequence.push_str(&line);
-            }
-        }
-
-        if !header.is_empty() && !sequence.is_empty() {
-            sequences.push(Sequence { header, sequence });
-        }
-
-        Ok(sequences)
+        parse_sequences(self.into_chunked_reader()?)
     }
 
     fn into_reader(&self) -> Result<impl std::io::Read, StdinError> {
@@ -245,6 +280,227 @@ impl FileOrStdin {
     }
 }
 
+// ? ---------------------------------------------------------------------------
+// ? Sequence parsing helpers
+// ? ---------------------------------------------------------------------------
+
+/// Nucleotide codes accepted in query sequences (IUPAC and gaps)
+const NUCLEOTIDE_CODES: &[u8] = b"ACGTURYSWKMBDHVN-";
+
+/// Wrap the reader into a decoder if the content is compressed
+///
+/// Compression formats are detected by the magic number at the beginning of
+/// the content: gzip (`1f 8b`), bzip2 (`BZh`), and zstd (`28 b5 2f fd`).
+/// Multi member gzip files (e.g. produced by `bgzip`) are fully decoded.
+fn decompress(
+    mut reader: impl BufRead + 'static,
+) -> Result<Box<dyn BufRead>, StdinError> {
+    let magic = reader.fill_buf()?;
+
+    let reader: Box<dyn BufRead> = if magic.starts_with(&[0x1f, 0x8b]) {
+        Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader)))
+    } else if magic.starts_with(b"BZh") {
+        Box::new(BufReader::new(bzip2::bufread::MultiBzDecoder::new(reader)))
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(
+            reader,
+        )?))
+    } else {
+        Box::new(reader)
+    };
+
+    Ok(reader)
+}
+
+/// Parse fasta or fastq records, decompressing the content if needed
+fn parse_sequences(
+    reader: impl BufRead + 'static,
+) -> Result<Vec<Sequence>, StdinError> {
+    let reader = decompress(reader)?;
+
+    let mut lines = reader
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            line.map(|line| (index + 1, line.trim_end().to_owned()))
+        })
+        .filter(|line| !matches!(line, Ok((_, line)) if line.is_empty()))
+        .peekable();
+
+    match lines.peek() {
+        None => Ok(Vec::new()),
+        Some(Err(_)) => Err(lines.next().unwrap().unwrap_err().into()),
+        Some(Ok((_, line))) if line.starts_with('>') => parse_fasta(lines),
+        Some(Ok((_, line))) if line.starts_with('@') => parse_fastq(lines),
+        Some(Ok((line, _))) => Err(StdinError::InvalidSequence {
+            line: *line,
+            message: "expected a fasta (`>`) or fastq (`@`) header".to_owned(),
+        }),
+    }
+}
+
+/// Check if the sequence line contains only nucleotide codes
+fn validate_nucleotides(
+    line_number: usize,
+    line: &str,
+    header: &str,
+) -> Result<(), StdinError> {
+    match line.char_indices().find(|(_, code)| {
+        !code.is_ascii()
+            || !NUCLEOTIDE_CODES.contains(&(code.to_ascii_uppercase() as u8))
+    }) {
+        None => Ok(()),
+        Some((column, code)) => Err(StdinError::InvalidSequence {
+            line: line_number,
+            message: format!(
+                "invalid nucleotide `{code}` at column {} of record `{header}`",
+                column + 1
+            ),
+        }),
+    }
+}
+
+fn empty_record_error(line: usize, header: &str) -> StdinError {
+    StdinError::InvalidSequence {
+        line,
+        message: format!("record `{header}` contains no sequence"),
+    }
+}
+
+/// Parse multi fasta records
+fn parse_fasta(
+    lines: impl Iterator<Item = io::Result<(usize, String)>>,
+) -> Result<Vec<Sequence>, StdinError> {
+    let mut sequences = Vec::<Sequence>::new();
+    let mut record: Option<(usize, String)> = None;
+    let mut sequence = String::new();
+
+    for line in lines {
+        let (line_number, line) = line?;
+
+        if let Some(header) = line.strip_prefix('>') {
+            if let Some((header_line, header)) = record.take() {
+                if sequence.is_empty() {
+                    return Err(empty_record_error(header_line, &header));
+                }
+
+                sequences.push(Sequence::new(header, sequence.clone()));
+                sequence.clear();
+            }
+
+            if header.trim().is_empty() {
+                return Err(StdinError::InvalidSequence {
+                    line: line_number,
+                    message: "empty fasta header".to_owned(),
+                });
+            }
+
+            record = Some((line_number, header.to_owned()));
+            continue;
+        }
+
+        match &record {
+            Some((_, header)) => {
+                validate_nucleotides(line_number, &line, header)?;
+                sequence.push_str(&line);
+            }
+            None => {
+                return Err(StdinError::InvalidSequence {
+                    line: line_number,
+                    message: "unexpected sequence without header".to_owned(),
+                })
+            }
+        }
+    }
+
+    if let Some((header_line, header)) = record {
+        if sequence.is_empty() {
+            return Err(empty_record_error(header_line, &header));
+        }
+
+        sequences.push(Sequence::new(header, sequence));
+    }
+
+    Ok(sequences)
+}
+
+/// Parse fastq records dropping the qualities
+///
+/// Records should follow the four lines format: the `@` header, the sequence,
+/// the `+` separator, and the qualities with the same length of the sequence.
+fn parse_fastq(
+    mut lines: impl Iterator<Item = io::Result<(usize, String)>>,
+) -> Result<Vec<Sequence>, StdinError> {
+    let mut sequences = Vec::<Sequence>::new();
+
+    while let Some(line) = lines.next() {
+        let (header_line, line) = line?;
+
+        let header = match line.strip_prefix('@') {
+            Some(header) if !header.trim().is_empty() => header.to_owned(),
+            Some(_) => {
+                return Err(StdinError::InvalidSequence {
+                    line: header_line,
+                    message: "empty fastq header".to_owned(),
+                })
+            }
+            None => {
+                return Err(StdinError::InvalidSequence {
+                    line: header_line,
+                    message: "expected a fastq header starting with `@`"
+                        .to_owned(),
+                })
+            }
+        };
+
+        let mut next_line = |expected: &str| match lines.next() {
+            Some(line) => Ok(line?),
+            None => Err(StdinError::InvalidSequence {
+                line: header_line,
+                message: format!(
+                    "truncated fastq record `{header}`: missing {expected}"
+                ),
+            }),
+        };
+
+        let (sequence_line, sequence) = next_line("sequence")?;
+
+        if sequence.starts_with('+') {
+            return Err(empty_record_error(header_line, &header));
+        }
+
+        validate_nucleotides(sequence_line, &sequence, &header)?;
+
+        let (separator_line, separator) = next_line("`+` separator")?;
+
+        if !separator.starts_with('+') {
+            return Err(StdinError::InvalidSequence {
+                line: separator_line,
+                message: format!(
+                    "expected the `+` separator of record `{header}`"
+                ),
+            });
+        }
+
+        let (quality_line, quality) = next_line("qualities")?;
+
+        if quality.len() != sequence.len() {
+            return Err(StdinError::InvalidSequence {
+                line: quality_line,
+                message: format!(
+                    "qualities length ({}) differs from sequence length ({}) of record `{header}`",
+                    quality.len(),
+                    sequence.len()
+                ),
+            });
+        }
+
+        sequences.push(Sequence::new(header, sequence));
+    }
+
+    Ok(sequences)
+}
+
 impl<T> FromStr for FileOrStdin<T> {
     type Err = StdinError;
 
@@ -256,3 +512,147 @@ impl<T> FromStr for FileOrStdin<T> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    const FASTA: &str = ">seq1 size=3\nACGT\nacgt\n\nNNRY\n>seq2\nACGU-\n";
+
+    fn parse(content: Vec<u8>) -> Result<Vec<Sequence>, StdinError> {
+        parse_sequences(Cursor::new(content))
+    }
+
+    fn gzip(content: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        );
+
+        encoder.write_all(content).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn invalid_line(result: Result<Vec<Sequence>, StdinError>) -> usize {
+        match result {
+            Err(StdinError::InvalidSequence { line, .. }) => line,
+            other => panic!("Expected an invalid sequence error: {other:?}"),
+        }
+    }
+
+    fn assert_fasta_records(sequences: Vec<Sequence>) {
+        assert_eq!(sequences.len(), 2);
+        assert_eq!(sequences[0].header(), "seq1 size=3");
+        assert_eq!(sequences[0].blast_header(), "seq1");
+        assert_eq!(sequences[0].sequence(), "ACGTacgtNNRY");
+        assert_eq!(sequences[0].abundance(), Some(3));
+        assert_eq!(sequences[1].sequence(), "ACGU-");
+    }
+
+    #[test]
+    fn multiline_fasta_records_are_joined() {
+        assert_fasta_records(parse(FASTA.as_bytes().to_vec()).unwrap());
+    }
+
+    #[test]
+    fn plain_content_is_not_decompressed() {
+        let mut reader =
+            decompress(Cursor::new(FASTA.as_bytes().to_vec())).unwrap();
+
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+
+        assert_eq!(content, FASTA);
+    }
+
+    #[test]
+    fn gzip_content_is_decompressed() {
+        assert_fasta_records(parse(gzip(FASTA.as_bytes())).unwrap());
+    }
+
+    #[test]
+    fn multi_member_gzip_content_is_fully_decompressed() {
+        let (first, second) = FASTA.split_at(FASTA.find(">seq2").unwrap());
+
+        let mut content = gzip(first.as_bytes());
+        content.extend(gzip(second.as_bytes()));
+
+        assert_fasta_records(parse(content).unwrap());
+    }
+
+    #[test]
+    fn zstd_content_is_decompressed() {
+        let content = zstd::encode_all(FASTA.as_bytes(), 0).unwrap();
+
+        assert_fasta_records(parse(content).unwrap());
+    }
+
+    #[test]
+    fn fastq_records_drop_qualities() {
+        let sequences = parse(
+            b"@read1\nACGT\n+\nIIII\n\n@read2\nGGCC\n+read2\n!!!!\n".to_vec(),
+        )
+        .unwrap();
+
+        assert_eq!(sequences.len(), 2);
+        assert_eq!(sequences[0].header(), "read1");
+        assert_eq!(sequences[0].sequence(), "ACGT");
+        assert_eq!(sequences[1].sequence(), "GGCC");
+    }
+
+    #[test]
+    fn truncated_fastq_records_are_rejected() {
+        for content in [
+            "@read1\nACGT\n+\nIIII\n@read2\n",
+            "@read1\nACGT\n+\nIIII\n@read2\nACGT\n",
+            "@read1\nACGT\n+\nIIII\n@read2\nACGT\n+\n",
+        ] {
+            let result = parse(content.as_bytes().to_vec());
+
+            assert!(
+                result.as_ref().is_err_and(|err| err
+                    .to_string()
+                    .contains("truncated fastq record `read2`")),
+                "{content:?}"
+            );
+
+            assert_eq!(invalid_line(result), 5);
+        }
+    }
+
+    #[test]
+    fn fastq_qualities_should_match_the_sequence_length() {
+        assert_eq!(invalid_line(parse(b"@read1\nACGT\n+\nIII\n".to_vec())), 4);
+    }
+
+    #[test]
+    fn invalid_nucleotides_report_the_line_number() {
+        let result = parse(b">seq1\nACGT\n\nACXT\n".to_vec())
+            .map_err(|err| err.to_string());
+
+        assert_eq!(
+            result.unwrap_err(),
+            "invalid sequence content at line 4: invalid nucleotide `X` at column 3 of record `seq1`"
+        );
+
+        assert_eq!(
+            invalid_line(parse(
+                b"@read1\nACGT\n+\nIIII\n@read2\nAC.T\n+\nIIII\n".to_vec()
+            )),
+            6
+        );
+
+        assert_eq!(
+            invalid_line(parse(gzip(b">seq1\nACGT\n>seq2\nAC GT\n"))),
+            4
+        );
+    }
+
+    #[test]
+    fn records_without_sequence_are_rejected() {
+        assert_eq!(invalid_line(parse(b">seq1\n>seq2\nACGT\n".to_vec())), 1);
+        assert_eq!(invalid_line(parse(b"@read1\n+\n\n".to_vec())), 1);
+        assert_eq!(invalid_line(parse(b"ACGT\n".to_vec())), 1);
+    }
+}
@@ -4,10 +4,9 @@ use super::taxonomy_bean::TaxonomyBean;
 use crate::domain::utils::round;
 
 use core::fmt;
-use mycelium_base::utils::errors::MappedErrors;
+use mycelium_base::utils::errors::{use_case_err, MappedErrors};
 use serde::{Deserialize, Serialize};
 use slugify::slugify;
-use std::collections::HashMap;
 use std::slice::Iter;
 use std::str::FromStr;
 
@@ -113,6 +112,16 @@ pub(crate) enum RankedLinnaeanIdentity {
     NonDefaultRank(String, f64),
 }
 
+impl RankedLinnaeanIdentity {
+    /// Get the identity percentage of the rank
+    pub(crate) fn identity(&self) -> f64 {
+        match self {
+            DefaultRank(_, identity) => *identity,
+            NonDefaultRank(_, identity) => *identity,
+        }
+    }
+}
+
 impl fmt::Display for RankedLinnaeanIdentity {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -217,6 +226,17 @@ impl InterpolatedIdentity {
     /// This function should be used to interpolate the identity percentage to
     /// provide identity cutoffs to non-linnaean ranks, such as strains,
     /// serovars, etc. Such ranks are stored as Other in the `LinnaeanRank` enum.
+    ///
+    /// The taxonomy vector should be ordered from the most general rank to the
+    /// most specific rank. Runs of consecutive non-default ranks are linearly
+    /// interpolated between the neighbouring default ranks. Runs without a
+    /// previous default rank start from the lowest cutoff of the backbone, and
+    /// runs without a next default rank end at 100 percent of identity.
+    ///
+    /// The interpolation is validated before return. Identities should be
+    /// non-decreasing along the lineage, thus bounded by the neighbouring
+    /// default ranks, and the interpolation length should be equal to the
+    /// taxonomy length. Violations are returned as errors.
     fn interpolate_identities(
         taxon: Taxon,
         taxonomy: Vec<LinnaeanRank>,
@@ -228,157 +248,384 @@ impl InterpolatedIdentity {
         // The backbone is a vector of `RankedLinnaeanIdentity` that contains
         // the identity cutoffs for the default Linnaeus taxon ranks.
         //
-        let backbone = taxon.get_taxon_cutoff(custom_taxon_values);
+        let backbone = taxon.get_taxon_cutoff(custom_taxon_values)?;
+
+        let lowest_cutoff = backbone
+            .iter()
+            .map(|level| level.identity())
+            .reduce(f64::min)
+            .unwrap_or(0.0);
         //
         // Map taxonomies to the backbone
         //
-        // It is important to note that the taxonomy vector should be ordered
-        // from the most specific rank to the most general rank. Here ranks non
-        // mapped to the backbone vector should be interpolated.
+        // Ranks mapped to the backbone receive the backbone cutoff. Ranks non
+        // mapped to the backbone vector are initialized without identity and
+        // should be interpolated.
         //
-        let backbone_mapped_taxonomies = taxonomy
+        let backbone_cutoffs = taxonomy
             .iter()
             .map(|rank| {
-                //
-                // Build default rank
-                //
-                let binding = NonDefaultRank(rank.to_string(), 0.0);
-                //
-                // Find the rank in the taxonomy backbone or use the binding
-                //
-                let ranked_taxon = backbone
-                    .iter()
-                    .find(|&level| match level {
-                        DefaultRank(level_rank, _) => level_rank == rank,
-                        _ => false,
-                    })
-                    .unwrap_or(&binding);
-                //
-                // Return the rank
-                //
-                ranked_taxon.to_owned()
+                backbone.iter().find_map(|level| match level {
+                    DefaultRank(level_rank, identity) if level_rank == rank => {
+                        Some(*identity)
+                    }
+                    _ => None,
+                })
             })
             .collect::<Vec<_>>();
+
+        let mut identities = backbone_cutoffs.to_owned();
         //
-        // If the taxonomy has only default ranks, return the backbone
+        // Interpolate each run of consecutive non-default ranks
         //
-        if backbone_mapped_taxonomies.iter().all(|rank| match rank {
-            DefaultRank(_, _) => true,
-            _ => false,
-        }) {
-            return Ok(backbone_mapped_taxonomies);
+        // The run is placed into a window delimited by the previous and the
+        // next default ranks. Case the run is at the start or at the end of
+        // the lineage, the first or the last rank of the run is used as the
+        // window limit.
+        //
+        let mut run_start = 0;
+
+        while run_start < backbone_cutoffs.len() {
+            if backbone_cutoffs[run_start].is_some() {
+                run_start += 1;
+                continue;
+            }
+
+            let run_end = backbone_cutoffs
+                .iter()
+                .skip(run_start)
+                .position(|cutoff| cutoff.is_some())
+                .map(|offset| run_start + offset)
+                .unwrap_or(backbone_cutoffs.len());
+
+            let previous = run_start
+                .checked_sub(1)
+                .and_then(|index| backbone_cutoffs[index]);
+
+            let next = backbone_cutoffs.get(run_end).copied().flatten();
+
+            let last_identity = next.unwrap_or(100.0);
+            let first_identity =
+                previous.unwrap_or_else(|| lowest_cutoff.min(last_identity));
+
+            let window_start = match previous {
+                Some(_) => run_start - 1,
+                None => run_start,
+            };
+
+            let window_end = match next {
+                Some(_) => run_end,
+                None => run_end - 1,
+            };
+
+            let window_size = (window_end - window_start) as f64;
+
+            for (index, identity) in identities
+                .iter_mut()
+                .enumerate()
+                .take(run_end)
+                .skip(run_start)
+            {
+                let target_identity = match window_size > 0.0 {
+                    true => {
+                        first_identity
+                            + (index - window_start) as f64
+                                * ((last_identity - first_identity)
+                                    / window_size)
+                    }
+                    false => first_identity,
+                };
+
+                *identity = Some(round(target_identity, 3));
+            }
+
+            run_start = run_end;
         }
+
         //
-        // Map the non-default ranks windows as a vector of tuples containing
-        // the window which the non-default rank is located and the rank itself.
+        // Build the interpolation. Ranks left without identity are reported
+        // as invalid by the validation step.
         //
-        let non_default_ranks = backbone_mapped_taxonomies
+        let interpolation = taxonomy
             .iter()
-            .enumerate()
-            .filter(|(_, rank)| match rank {
-                NonDefaultRank(_, _) => true,
-                _ => false,
+            .zip(backbone_cutoffs)
+            .zip(identities)
+            .map(|((rank, cutoff), identity)| {
+                let identity = identity.unwrap_or(f64::NAN);
+
+                match cutoff {
+                    Some(_) => DefaultRank(rank.to_owned(), identity),
+                    None => NonDefaultRank(rank.to_string(), identity),
+                }
             })
             .collect::<Vec<_>>();
-        //
-        // Map the previous and next default ranks for each non-default rank.
-        // Non-default ranks should occur in sequences of more than one
-        // non-default rank. Thus, the search should be extended to the next
-        // default rank.
-        //
-        let non_default_ranks_windows = non_default_ranks
-            .into_iter()
-            .map(|(non_default_index, _)| {
-                let previous = backbone_mapped_taxonomies
-                    .iter()
-                    .take(non_default_index)
-                    .rev()
-                    .find(|&level| match level {
-                        NonDefaultRank(_, _) => false,
-                        _ => true,
-                    })
-                    .unwrap_or(&backbone_mapped_taxonomies[0]);
-
-                let previous_index = backbone_mapped_taxonomies
-                    .iter()
-                    .position(|level| level == previous)
-                    .unwrap_or(0);
 
-                let next = backbone_mapped_taxonomies
-                    .iter()
-                    .skip(non_default_index)
-                    .find(|&level| match level {
-                        NonDefaultRank(_, _) => false,
-                        _ => true,
-                    })
-                    .unwrap_or(
-                        &backbone_mapped_taxonomies
-                            [backbone_mapped_taxonomies.len() - 1],
-                    );
+        Self::validate_interpolation(&taxonomy, &interpolation)?;
 
-                let next_index = backbone_mapped_taxonomies
-                    .iter()
-                    .position(|level| level == next)
-                    .unwrap_or(backbone_mapped_taxonomies.len() - 1);
+        Ok(interpolation)
+    }
 
-                let window = backbone_mapped_taxonomies
-                    .iter()
-                    .skip_while(|&level| level != previous)
-                    .take(next_index + 1)
-                    .cloned()
-                    .collect::<Vec<_>>();
+    /// Validate the interpolation invariants
+    ///
+    /// The interpolation should contain one finite identity in the 0 to 100
+    /// range for each rank of the taxonomy, and identities should not decrease
+    /// from the most general to the most specific rank.
+    fn validate_interpolation(
+        taxonomy: &[LinnaeanRank],
+        interpolation: &[RankedLinnaeanIdentity],
+    ) -> Result<(), MappedErrors> {
+        if interpolation.len() != taxonomy.len() {
+            return use_case_err(format!(
+                "Interpolated identities length ({}) is not equal to the taxonomy length ({})",
+                interpolation.len(),
+                taxonomy.len()
+            ))
+            .as_error();
+        }
 
-                (non_default_index, (previous_index, next_index), window)
-            })
-            .collect::<Vec<_>>();
+        if let Some(level) = interpolation.iter().find(|level| {
+            !level.identity().is_finite()
+                || !(0.0..=100.0).contains(&level.identity())
+        }) {
+            return use_case_err(format!(
+                "Invalid identity cutoff of the rank `{level}`. Identity cutoffs should be between 0 and 100"
+            ))
+            .as_error();
+        }
 
-        let mut updated_identities = HashMap::<i32, f64>::new();
-        for (non_default_index, (previous_index, _), window) in
-            non_default_ranks_windows
+        if let Some(pair) = interpolation
+            .windows(2)
+            .find(|pair| pair[1].identity() < pair[0].identity())
         {
-            let target_index = non_default_index - previous_index;
-
-            let first_window_identity = match window[0].to_owned() {
-                DefaultRank(_, identity) => identity,
-                NonDefaultRank(_, _) => match backbone[0] {
-                    DefaultRank(_, default_identity) => default_identity,
-                    _ => panic!("Unexpected error. Could not determine default identity"),
-                },
-            };
+            return use_case_err(format!(
+                "Non monotonic identity cutoffs: `{}` is lower than the more general `{}` in the lineage `{}`",
+                pair[1],
+                pair[0],
+                taxonomy
+                    .iter()
+                    .map(|rank| rank.to_string())
+                    .collect::<Vec<_>>()
+                    .join(";")
+            ))
+            .as_error();
+        }
 
-            let last_window_identity = match window[window.len() - 1].to_owned()
-            {
-                DefaultRank(_, identity) => identity,
-                NonDefaultRank(_, _) => 100.0,
-            };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Non-linnaean ranks found in the NCBI lineages
+    const OTHER_RANKS: [&str; 6] = [
+        "clade",
+        "strain",
+        "serotype",
+        "subspecies",
+        "species-group",
+        "no-rank",
+    ];
+
+    fn custom_taxon(values: &[i16]) -> CustomTaxon {
+        serde_json::from_value(serde_json::json!({
+            "domain": values[0],
+            "kingdom": values[1],
+            "phylum": values[2],
+            "class": values[3],
+            "order": values[4],
+            "family": values[5],
+            "genus": values[6],
+            "species": values[7],
+        }))
+        .unwrap()
+    }
+
+    fn ranks(lineage: &str) -> Vec<LinnaeanRank> {
+        lineage
+            .split(';')
+            .map(|rank| rank.parse::<LinnaeanRank>().unwrap())
+            .collect()
+    }
+
+    fn identities(interpolation: &InterpolatedIdentity) -> Vec<f64> {
+        interpolation
+            .interpolation()
+            .iter()
+            .map(|level| level.identity())
+            .collect()
+    }
 
-            let window_weight = last_window_identity - first_window_identity;
-            let window_size = (window.len() - 1) as f64;
+    /// Lineages ordered from the most general to the most specific rank,
+    /// mixing optional default ranks with runs of non-default ranks
+    fn lineage_strategy() -> impl Strategy<Value = Vec<LinnaeanRank>> {
+        prop::collection::vec(
+            (
+                prop::collection::vec(
+                    prop::sample::select(&OTHER_RANKS[..]),
+                    0..4,
+                ),
+                any::<bool>(),
+            ),
+            9,
+        )
+        .prop_map(|slots| {
+            let default_ranks = LinnaeanRank::ordered_iter(Some(true))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            slots
+                .into_iter()
+                .enumerate()
+                .flat_map(|(index, (others, keep_default))| {
+                    others
+                        .into_iter()
+                        .map(|rank| Other(rank.to_string()))
+                        .chain(
+                            default_ranks
+                                .get(index)
+                                .filter(|_| keep_default)
+                                .cloned(),
+                        )
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        })
+    }
 
-            let target_identity = round(
-                first_window_identity +
-                    (target_index as f64 * (window_weight / window_size)),
-                3,
-            );
+    fn taxon_strategy() -> impl Strategy<Value = (Taxon, Option<CustomTaxon>)> {
+        prop_oneof![
+            Just((Taxon::Fungi, None)),
+            Just((Taxon::Bacteria, None)),
+            Just((Taxon::Eukaryotes, None)),
+            prop::collection::vec(0i16..=100, 8).prop_map(|mut values| {
+                values.sort();
+                (Taxon::Custom, Some(custom_taxon(&values)))
+            }),
+        ]
+    }
 
-            updated_identities
-                .insert(non_default_index as i32, target_identity);
+    proptest! {
+        #[test]
+        fn interpolation_has_the_taxonomy_length(
+            taxonomy in lineage_strategy(),
+            (taxon, custom) in taxon_strategy(),
+        ) {
+            let interpolation =
+                InterpolatedIdentity::new(taxon, taxonomy.to_owned(), custom)
+                    .unwrap();
+
+            prop_assert_eq!(interpolation.interpolation().len(), taxonomy.len());
         }
 
-        Ok(backbone_mapped_taxonomies
-            .into_iter()
-            .enumerate()
-            .map(|(index, item)| match item {
-                NonDefaultRank(rank, _) => {
-                    let identity = updated_identities
-                        .get(&(index as i32))
-                        .unwrap_or(&100.0)
-                        .to_owned();
-
-                    NonDefaultRank(rank, identity)
+        #[test]
+        fn interpolation_is_monotonic_along_the_lineage(
+            taxonomy in lineage_strategy(),
+            (taxon, custom) in taxon_strategy(),
+        ) {
+            let interpolation =
+                InterpolatedIdentity::new(taxon, taxonomy, custom).unwrap();
+
+            for pair in identities(&interpolation).windows(2) {
+                prop_assert!(pair[0] <= pair[1], "{}", interpolation);
+            }
+        }
+
+        #[test]
+        fn interpolation_is_bounded_by_the_neighbouring_default_ranks(
+            taxonomy in lineage_strategy(),
+            (taxon, custom) in taxon_strategy(),
+        ) {
+            let interpolation =
+                InterpolatedIdentity::new(taxon, taxonomy, custom).unwrap();
+            let levels = interpolation.interpolation();
+
+            for (index, level) in levels.iter().enumerate() {
+                if let NonDefaultRank(_, identity) = level {
+                    let previous = levels[..index]
+                        .iter()
+                        .rev()
+                        .find(|level| matches!(level, DefaultRank(_, _)))
+                        .map(|level| level.identity())
+                        .unwrap_or(0.0);
+
+                    let next = levels[index..]
+                        .iter()
+                        .find(|level| matches!(level, DefaultRank(_, _)))
+                        .map(|level| level.identity())
+                        .unwrap_or(100.0);
+
+                    prop_assert!(
+                        previous <= *identity && *identity <= next,
+                        "{}",
+                        interpolation
+                    );
                 }
-                _ => item,
-            })
-            .collect::<Vec<_>>())
+            }
+        }
+    }
+
+    #[test]
+    fn consecutive_other_ranks_are_spread_between_default_ranks() {
+        let interpolation = InterpolatedIdentity::new(
+            Taxon::Bacteria,
+            ranks("d;clade;p;c;o;f;g;s;strain;serotype;clade"),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            identities(&interpolation),
+            vec![
+                60.0, 67.5, 75.0, 80.0, 85.0, 92.0, 97.0, 99.0, 99.333, 99.667,
+                100.0
+            ]
+        );
+    }
+
+    #[test]
+    fn leading_other_ranks_start_from_the_lowest_cutoff() {
+        let interpolation = InterpolatedIdentity::new(
+            Taxon::Bacteria,
+            ranks("clade;no-rank;p;c;o;f;g;s"),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            identities(&interpolation),
+            vec![60.0, 67.5, 75.0, 80.0, 85.0, 92.0, 97.0, 99.0]
+        );
+    }
+
+    #[test]
+    fn omitted_custom_ranks_are_interpolated() {
+        let custom: CustomTaxon = serde_json::from_value(serde_json::json!({
+            "domain": 60,
+            "species": 99,
+        }))
+        .unwrap();
+
+        let interpolation = InterpolatedIdentity::new(
+            Taxon::Custom,
+            ranks("d;k;p;s"),
+            Some(custom),
+        )
+        .unwrap();
+
+        assert_eq!(identities(&interpolation), vec![60.0, 73.0, 86.0, 99.0]);
+    }
+
+    #[test]
+    fn non_monotonic_cutoffs_are_rejected() {
+        let interpolation = InterpolatedIdentity::new(
+            Taxon::Custom,
+            ranks("d;k;p;c;o;f;strain;g;s"),
+            Some(custom_taxon(&[60, 65, 75, 80, 85, 92, 99, 97])),
+        );
+
+        assert!(interpolation.is_err());
     }
 }
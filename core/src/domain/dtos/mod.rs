@@ -1,11 +1,18 @@
 pub mod blast_builder;
 pub mod blast_result;
+pub mod blast_tabular_format;
 pub mod blutils_output;
 pub mod consensus_result;
 pub mod consensus_strategy;
+pub mod consensus_summary;
+pub mod cutoff_registry;
+pub mod feature_table;
 pub mod file_or_stdin;
 pub mod linnaean_ranks;
 pub mod parallel_blast_output;
 pub mod taxon;
+pub mod taxon_abundance;
+pub mod taxonomies_database;
 pub mod taxonomies_map;
+pub mod taxonomies_report;
 pub mod taxonomy_bean;
@@ -1,7 +1,23 @@
-use std::path::PathBuf;
+use super::blast_tabular_format::BlastTabularFormat;
+
+use std::{collections::HashMap, path::PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct ParallelBlastOutput {
     pub output_file: PathBuf,
     pub headers: Option<Vec<String>>,
+
+    /// Query abundances indexed by the query blast header
+    pub abundances: Option<HashMap<String, u64>>,
+
+    /// The column layout of the blast output file
+    pub columns: BlastTabularFormat,
+
+    /// The MD5 checksum of the blast output file
+    pub checksum: Option<String>,
+
+    /// Failure reasons indexed by the query blast header
+    ///
+    /// Queries of chunks which blast execution failed.
+    pub failures: Option<HashMap<String, String>>,
 }
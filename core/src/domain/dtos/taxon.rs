@@ -1,10 +1,14 @@
 use self::Taxon::*;
-use super::linnaean_ranks::{
-    LinnaeanRank::*,
-    RankedLinnaeanIdentity::{self, *},
+use super::{
+    cutoff_registry::{normalize_preset_name, CutoffRegistry},
+    linnaean_ranks::{
+        LinnaeanRank::*,
+        RankedLinnaeanIdentity::{self, *},
+    },
 };
 
-use clap::ValueEnum;
+use core::fmt;
+use mycelium_base::utils::errors::MappedErrors;
 use serde::{Deserialize, Serialize};
 use std::{
     path::PathBuf,
@@ -14,14 +18,20 @@ use std::{
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CustomTaxon {
-    domain: i16,
-    kingdom: Option<i16>,
-    phylum: Option<i16>,
-    class: Option<i16>,
-    order: Option<i16>,
-    family: Option<i16>,
-    genus: Option<i16>,
-    species: i16,
+    domain: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kingdom: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    phylum: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    class: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    order: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    family: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    genus: Option<f64>,
+    species: f64,
 }
 
 impl CustomTaxon {
@@ -63,9 +73,32 @@ impl CustomTaxon {
 
         panic!("Custom taxon file must be a YAML or JSON file");
     }
+
+    /// Get the cutoffs as ranked identities, from the most general to the
+    /// most specific rank
+    ///
+    /// Optional ranks omitted by the user are not included, then they are
+    /// interpolated as the non-default ranks.
+    pub(crate) fn cutoffs(&self) -> Vec<RankedLinnaeanIdentity> {
+        [
+            (Domain, Some(self.domain)),
+            (Kingdom, self.kingdom),
+            (Phylum, self.phylum),
+            (Class, self.class),
+            (Order, self.order),
+            (Family, self.family),
+            (Genus, self.genus),
+            (Species, Some(self.species)),
+        ]
+        .into_iter()
+        .filter_map(|(rank, identity)| {
+            identity.map(|identity| DefaultRank(rank, identity))
+        })
+        .collect()
+    }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, ValueEnum)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Taxon {
     /// Fungi cutoff values based on default Internal Transcribed Spacer (ITS)
@@ -85,10 +118,16 @@ pub enum Taxon {
     /// Custom values must be provided by the user.
     ///
     Custom,
+
+    /// Cutoff values of a named preset of the cutoff registry, as `its` or
+    /// `16s-v4`.
+    ///
+    #[serde(untagged)]
+    Preset(String),
 }
 
 impl FromStr for Taxon {
-    type Err = ();
+    type Err = String;
 
     fn from_str(input: &str) -> Result<Taxon, Self::Err> {
         match input {
@@ -96,90 +135,44 @@ impl FromStr for Taxon {
             "b" | "Bacteria" | "bacteria" => Ok(Taxon::Bacteria),
             "e" | "Eukaryotes" | "eukaryotes" => Ok(Taxon::Eukaryotes),
             "c" | "Custom" | "custom" => Ok(Taxon::Custom),
-            _ => Err(()),
+            other => match normalize_preset_name(other) {
+                name if name.is_empty() => {
+                    Err("The taxon should not be empty".to_string())
+                }
+                name => Ok(Taxon::Preset(name)),
+            },
         }
     }
 }
 
-impl Taxon {
-    pub(super) fn get_taxon_cutoff(
-        self,
-        custom_taxon_values: Option<CustomTaxon>,
-    ) -> Vec<RankedLinnaeanIdentity> {
+impl fmt::Display for Taxon {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Fungi => Self::get_fungal_cutoffs(),
-            Bacteria => Self::get_bacterial_cutoffs(),
-            Eukaryotes => Self::get_eukaryote_cutoffs(),
-            Custom => match custom_taxon_values {
-                Some(custom_taxon_values) => {
-                    Self::get_custom_cutoffs(custom_taxon_values)
-                }
-                None => panic!("Custom taxon values are required"),
-            },
+            Fungi => write!(f, "fungi"),
+            Bacteria => write!(f, "bacteria"),
+            Eukaryotes => write!(f, "eukaryotes"),
+            Custom => write!(f, "custom"),
+            Preset(name) => write!(f, "{}", name),
         }
     }
+}
 
-    /// Filter custom ranks by identity percentage
-    fn get_custom_cutoffs(
-        custom_taxon_values: CustomTaxon,
-    ) -> Vec<RankedLinnaeanIdentity> {
-        vec![
-            DefaultRank(Domain, custom_taxon_values.domain as f64),
-            DefaultRank(
-                Kingdom,
-                custom_taxon_values.kingdom.unwrap_or(0) as f64,
-            ),
-            DefaultRank(Phylum, custom_taxon_values.phylum.unwrap_or(0) as f64),
-            DefaultRank(Class, custom_taxon_values.class.unwrap_or(0) as f64),
-            DefaultRank(Order, custom_taxon_values.order.unwrap_or(0) as f64),
-            DefaultRank(Family, custom_taxon_values.family.unwrap_or(0) as f64),
-            DefaultRank(Genus, custom_taxon_values.genus.unwrap_or(0) as f64),
-            DefaultRank(Species, custom_taxon_values.species as f64),
-        ]
-    }
-
-    /// Filter fungi ranks by identity percentage
-    ///
-    /// TODO: Review the identity percentages and check a reference.
-    fn get_fungal_cutoffs() -> Vec<RankedLinnaeanIdentity> {
-        vec![
-            DefaultRank(Species, 97.0),
-            DefaultRank(Genus, 95.0),
-            DefaultRank(Family, 90.0),
-            DefaultRank(Order, 85.0),
-            DefaultRank(Class, 80.0),
-            DefaultRank(Phylum, 75.0),
-            DefaultRank(Domain, 60.0),
-        ]
-    }
-
-    /// Filter bacteria ranks by identity percentage
-    ///
-    /// TODO: Review the identity percentages and check a reference.
-    fn get_bacterial_cutoffs() -> Vec<RankedLinnaeanIdentity> {
-        vec![
-            DefaultRank(Species, 99.0),
-            DefaultRank(Genus, 97.0),
-            DefaultRank(Family, 92.0),
-            DefaultRank(Order, 85.0),
-            DefaultRank(Class, 80.0),
-            DefaultRank(Phylum, 75.0),
-            DefaultRank(Domain, 60.0),
-        ]
-    }
-
-    /// Filter general eukaryotes ranks by identity percentage
+impl Taxon {
+    /// Get the cutoffs of the taxon
     ///
-    /// TODO: Review the identity percentages and check a reference.
-    fn get_eukaryote_cutoffs() -> Vec<RankedLinnaeanIdentity> {
-        vec![
-            DefaultRank(Species, 97.0),
-            DefaultRank(Genus, 95.0),
-            DefaultRank(Family, 90.0),
-            DefaultRank(Order, 85.0),
-            DefaultRank(Class, 80.0),
-            DefaultRank(Phylum, 75.0),
-            DefaultRank(Domain, 60.0),
-        ]
+    /// Cutoff values resolved before the consensus, as the presets of user
+    /// extended registries, are given as the custom taxon values. Otherwise,
+    /// the taxon is resolved using the registry shipped with Blutils.
+    pub(super) fn get_taxon_cutoff(
+        self,
+        custom_taxon_values: Option<CustomTaxon>,
+    ) -> Result<Vec<RankedLinnaeanIdentity>, MappedErrors> {
+        match custom_taxon_values {
+            Some(custom_taxon_values) => Ok(custom_taxon_values.cutoffs()),
+            None => Ok(CutoffRegistry::shipped()?
+                .resolve(&self, None)?
+                .cutoffs
+                .cutoffs()),
+        }
     }
 }
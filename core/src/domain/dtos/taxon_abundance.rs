@@ -0,0 +1,96 @@
+use super::{
+    consensus_result::QueryWithConsensus, linnaean_ranks::LinnaeanRank,
+};
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The aggregated abundance of the queries assigned to a single taxon
+///
+/// Queries without consensus are aggregated into a single record without
+/// rank, identifier, and taxonomy.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaxonAbundance {
+    pub rank: Option<LinnaeanRank>,
+    pub identifier: Option<String>,
+    pub taxonomy: Option<String>,
+    pub queries: usize,
+    pub abundance: u64,
+}
+
+impl TaxonAbundance {
+    /// Fold query results into per-taxon abundances
+    ///
+    /// Queries without an abundance annotation are counted as a single read.
+    /// Records are sorted by abundance in descending order.
+    pub fn fold_query_abundances(results: &[QueryWithConsensus]) -> Vec<Self> {
+        let mut folder = TaxonAbundanceFolder::default();
+
+        for result in results {
+            folder.push(result);
+        }
+
+        folder.finish()
+    }
+}
+
+/// Incremental fold of query results into per-taxon abundances
+///
+/// Allows building the abundances of results produced in batches without
+/// keeping the results in memory.
+#[derive(Clone, Debug, Default)]
+pub struct TaxonAbundanceFolder {
+    abundances: HashMap<String, TaxonAbundance>,
+    annotated: bool,
+}
+
+impl TaxonAbundanceFolder {
+    pub fn push(&mut self, result: &QueryWithConsensus) {
+        let (key, rank, identifier, taxonomy) = match &result.taxon {
+            Some(bean) => (
+                bean.taxonomy
+                    .to_owned()
+                    .unwrap_or(bean.taxonomy_to_string()),
+                Some(bean.reached_rank.to_owned()),
+                Some(bean.identifier.to_owned()),
+                bean.taxonomy.to_owned(),
+            ),
+            None => (String::new(), None, None, None),
+        };
+
+        let record = self.abundances.entry(key).or_insert(TaxonAbundance {
+            rank,
+            identifier,
+            taxonomy,
+            queries: 0,
+            abundance: 0,
+        });
+
+        record.queries += 1;
+        record.abundance += result.abundance.unwrap_or(1);
+
+        self.annotated |= result.abundance.is_some();
+    }
+
+    /// Check if at least one folded query contains an abundance annotation
+    pub fn has_annotations(&self) -> bool {
+        self.annotated
+    }
+
+    /// Collect the abundances sorted in descending order
+    pub fn finish(self) -> Vec<TaxonAbundance> {
+        let mut abundances = self
+            .abundances
+            .into_values()
+            .collect::<Vec<TaxonAbundance>>();
+
+        abundances.sort_by(|a, b| {
+            b.abundance
+                .cmp(&a.abundance)
+                .then(a.taxonomy.cmp(&b.taxonomy))
+        });
+
+        abundances
+    }
+}
@@ -0,0 +1,765 @@
+use super::taxonomies_map::{Accession, TaxonomiesMap, TaxonomyMapUnit};
+
+use memmap2::Mmap;
+use mycelium_base::utils::errors::{execution_err, MappedErrors};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{read_to_string, File},
+    io::{BufWriter, Read, Write},
+    path::Path,
+};
+
+/// The magic number of the binary Blutils taxonomies database
+const BINARY_MAGIC: &[u8; 8] = b"BLUTILS\0";
+
+/// The version of the binary Blutils taxonomies database layout
+const BINARY_VERSION: u32 = 2;
+
+/// The size of each taxid index entry (taxid and record offset)
+const INDEX_ENTRY_SIZE: usize = 16;
+
+/// The file format of the Blutils taxonomies database
+#[derive(
+    Clone, Debug, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum TaxonomiesDatabaseFormat {
+    /// A single JSON document (`*.blutils.json`)
+    #[default]
+    Json,
+
+    /// A compact binary file indexed by taxid (`*.blutils.bin`)
+    Binary,
+}
+
+impl TaxonomiesDatabaseFormat {
+    /// The extension of the database file after the `.blutils` suffix
+    pub fn extension(&self) -> &str {
+        match self {
+            TaxonomiesDatabaseFormat::Json => "json",
+            TaxonomiesDatabaseFormat::Binary => "bin",
+        }
+    }
+}
+
+/// A Blutils taxonomies database opened for lookup
+///
+/// JSON databases are fully loaded into memory and indexed by taxid. Binary
+/// databases are memory-mapped and records are decoded on demand, so only
+/// the requested taxids are loaded. The format is detected from the file
+/// content.
+pub(crate) enum TaxonomiesDatabase {
+    Json {
+        map: TaxonomiesMap,
+        index: HashMap<u64, usize>,
+    },
+    Binary(BinaryTaxonomies),
+}
+
+impl TaxonomiesDatabase {
+    pub(crate) fn open(path: &Path) -> Result<Self, MappedErrors> {
+        if !path.exists() {
+            return execution_err("Taxonomies file not found").as_error();
+        }
+
+        let mut magic = [0; BINARY_MAGIC.len()];
+
+        let is_binary = File::open(path)
+            .and_then(|mut file| file.read(&mut magic))
+            .map_err(|err| {
+                execution_err(format!(
+                    "Unexpected error on read `taxonomies` file: {err}"
+                ))
+            })?
+            == magic.len()
+            && &magic == BINARY_MAGIC;
+
+        if is_binary {
+            return BinaryTaxonomies::open(path).map(Self::Binary);
+        }
+
+        let content = read_to_string(path).map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on read `taxonomies` file: {err}"
+            ))
+        })?;
+
+        let map =
+            serde_json::from_str::<TaxonomiesMap>(&content).map_err(|err| {
+                execution_err(format!(
+                    "Unexpected error detected on parse `taxonomies` as json: {err}"
+                ))
+            })?;
+
+        //
+        // Aliases are indexed first, so a record taxid is never shadowed by
+        // an alias of another record.
+        //
+        let mut index = HashMap::<u64, usize>::new();
+
+        for (position, taxonomy) in map.taxonomies.iter().enumerate() {
+            for alias in &taxonomy.aliases {
+                index.insert(*alias, position);
+            }
+        }
+
+        for (position, taxonomy) in map.taxonomies.iter().enumerate() {
+            index.insert(taxonomy.taxid, position);
+        }
+
+        Ok(Self::Json { map, index })
+    }
+
+    /// The database settings
+    ///
+    /// Taxonomies of binary databases are not loaded into the settings and
+    /// should be accessed by lookup.
+    pub(crate) fn metadata(&self) -> &TaxonomiesMap {
+        match self {
+            Self::Json { map, .. } => map,
+            Self::Binary(database) => &database.metadata,
+        }
+    }
+
+    /// Get the taxonomy of a single taxid
+    ///
+    /// The taxid is matched against the record taxids and their aliases.
+    pub(crate) fn get(
+        &self,
+        taxid: u64,
+    ) -> Result<Option<TaxonomyMapUnit>, MappedErrors> {
+        match self {
+            Self::Json { map, index } => Ok(index
+                .get(&taxid)
+                .map(|position| map.taxonomies[*position].to_owned())),
+            Self::Binary(database) => database.get(taxid),
+        }
+    }
+
+    /// Get the taxonomies of the taxids found in the database
+    ///
+    /// Returns pairs of the requested taxid and its taxonomy, given merged
+    /// taxids resolve to the taxonomy of the new taxid. Taxids not found in
+    /// the database are ignored.
+    pub(crate) fn lookup(
+        &self,
+        taxids: impl IntoIterator<Item = u64>,
+    ) -> Result<Vec<(u64, TaxonomyMapUnit)>, MappedErrors> {
+        let mut taxonomies = Vec::<(u64, TaxonomyMapUnit)>::new();
+
+        for taxid in taxids {
+            if let Some(taxonomy) = self.get(taxid)? {
+                taxonomies.push((taxid, taxonomy));
+            }
+        }
+
+        Ok(taxonomies)
+    }
+
+    /// Visit all taxonomies of the database in the stored order
+    pub(crate) fn for_each(
+        &self,
+        mut visitor: impl FnMut(&TaxonomyMapUnit) -> Result<(), MappedErrors>,
+    ) -> Result<(), MappedErrors> {
+        match self {
+            Self::Json { map, .. } => {
+                map.taxonomies.iter().try_for_each(visitor)
+            }
+            Self::Binary(database) => {
+                for position in 0..database.count {
+                    let (taxid, offset) = database.index_entry(position)?;
+                    let taxonomy = database.decode(offset)?;
+
+                    //
+                    // Alias entries point to records visited by its own taxid.
+                    //
+                    if taxonomy.taxid == taxid {
+                        visitor(&taxonomy)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A memory-mapped binary taxonomies database
+///
+/// The file layout is composed of:
+///
+/// - The `BLUTILS\0` magic number and the layout version (`u32`).
+/// - The JSON encoded database settings, prefixed by its length (`u64`).
+/// - The number of index entries (`u64`) followed by the index entries, each
+///   one composed of the taxid and the record offset (`u64`), sorted by taxid.
+///   Aliases have its own entries pointing to the record of the new taxid.
+/// - The records, each one composed of the taxid (`u64`), the rank, the
+///   numeric and text lineages, the accessions (a `u32` count followed by the
+///   accession and oid pairs), and the aliases (a `u32` count followed by the
+///   taxids). Strings are prefixed by its length (`u32`).
+///
+/// Integers are little endian.
+pub(crate) struct BinaryTaxonomies {
+    mmap: Mmap,
+    metadata: TaxonomiesMap,
+    count: usize,
+    index_start: usize,
+}
+
+impl BinaryTaxonomies {
+    fn open(path: &Path) -> Result<Self, MappedErrors> {
+        let file = File::open(path).map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on open `taxonomies` file: {err}"
+            ))
+        })?;
+
+        //
+        // SAFETY: the database file is only read, and should not be modified
+        // while the mapping is alive.
+        //
+        let mmap = unsafe { Mmap::map(&file) }.map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on map `taxonomies` file: {err}"
+            ))
+        })?;
+
+        let mut cursor = ByteCursor::new(&mmap, BINARY_MAGIC.len());
+
+        let version = cursor.read_u32()?;
+
+        if version != BINARY_VERSION {
+            return execution_err(format!(
+                "Unsupported binary taxonomies database version: {version}"
+            ))
+            .as_error();
+        }
+
+        let metadata_length = cursor.read_u64()? as usize;
+
+        let metadata = serde_json::from_slice::<TaxonomiesMap>(
+            cursor.read_bytes(metadata_length)?,
+        )
+        .map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on parse binary taxonomies settings: {err}"
+            ))
+        })?;
+
+        let count = cursor.read_u64()? as usize;
+        let index_start = cursor.position;
+
+        if count
+            .checked_mul(INDEX_ENTRY_SIZE)
+            .and_then(|size| size.checked_add(index_start))
+            .is_none_or(|index_end| index_end > mmap.len())
+        {
+            return corrupted("truncated taxids index");
+        }
+
+        Ok(Self {
+            mmap,
+            metadata,
+            count,
+            index_start,
+        })
+    }
+
+    fn index_entry(&self, position: usize) -> Result<(u64, u64), MappedErrors> {
+        let mut cursor = ByteCursor::new(
+            &self.mmap,
+            self.index_start + position * INDEX_ENTRY_SIZE,
+        );
+
+        Ok((cursor.read_u64()?, cursor.read_u64()?))
+    }
+
+    /// Find the taxid record by binary search over the sorted index
+    fn get(&self, taxid: u64) -> Result<Option<TaxonomyMapUnit>, MappedErrors> {
+        let (mut lower, mut upper) = (0, self.count);
+
+        while lower < upper {
+            let middle = lower + (upper - lower) / 2;
+            let (entry_taxid, offset) = self.index_entry(middle)?;
+
+            match entry_taxid.cmp(&taxid) {
+                std::cmp::Ordering::Less => lower = middle + 1,
+                std::cmp::Ordering::Greater => upper = middle,
+                std::cmp::Ordering::Equal => {
+                    return self.decode(offset).map(Some)
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn decode(&self, offset: u64) -> Result<TaxonomyMapUnit, MappedErrors> {
+        let mut cursor = ByteCursor::new(&self.mmap, offset as usize);
+
+        let taxid = cursor.read_u64()?;
+        let rank = cursor.read_string()?;
+        let numeric_lineage = cursor.read_string()?;
+        let text_lineage = cursor.read_string()?;
+
+        let accessions = (0..cursor.read_u32()?)
+            .map(|_| {
+                Ok(Accession {
+                    accession: cursor.read_string()?,
+                    oid: cursor.read_string()?,
+                })
+            })
+            .collect::<Result<Vec<Accession>, MappedErrors>>()?;
+
+        let aliases = (0..cursor.read_u32()?)
+            .map(|_| cursor.read_u64())
+            .collect::<Result<Vec<u64>, MappedErrors>>()?;
+
+        Ok(TaxonomyMapUnit {
+            taxid,
+            aliases,
+            rank,
+            numeric_lineage,
+            text_lineage,
+            accessions,
+        })
+    }
+}
+
+/// Write the taxonomies database in the given format
+pub(crate) fn write_taxonomies_database(
+    mut map: TaxonomiesMap,
+    output_path: &Path,
+    format: &TaxonomiesDatabaseFormat,
+) -> Result<(), MappedErrors> {
+    let file = File::create(output_path).map_err(|err| {
+        execution_err(format!(
+            "Unexpected error on create taxonomies database: {err}"
+        ))
+    })?;
+
+    let mut writer = BufWriter::new(file);
+
+    let write_error = |err: std::io::Error| {
+        execution_err(format!(
+            "Unexpected error on write taxonomies database: {err}"
+        ))
+    };
+
+    match format {
+        TaxonomiesDatabaseFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, &map).map_err(|err| {
+                execution_err(format!(
+                    "Unexpected error on serialize taxonomies database: {err}"
+                ))
+            })?;
+        }
+        TaxonomiesDatabaseFormat::Binary => {
+            let mut taxonomies = std::mem::take(&mut map.taxonomies);
+            taxonomies.sort_by_key(|taxonomy| taxonomy.taxid);
+
+            let metadata = serde_json::to_vec(&map).map_err(|err| {
+                execution_err(format!(
+                    "Unexpected error on serialize taxonomies settings: {err}"
+                ))
+            })?;
+
+            let mut header = Vec::<u8>::new();
+            header.extend_from_slice(BINARY_MAGIC);
+            header.extend_from_slice(&BINARY_VERSION.to_le_bytes());
+            header.extend_from_slice(&(metadata.len() as u64).to_le_bytes());
+            header.extend_from_slice(&metadata);
+
+            //
+            // Offsets are calculated from the records size, allowing records
+            // to be written after the index without buffering.
+            //
+            let mut offsets = Vec::<u64>::with_capacity(taxonomies.len());
+            let mut offset = 0;
+
+            for taxonomy in &taxonomies {
+                offsets.push(offset);
+                offset += encoded_length(taxonomy) as u64;
+            }
+
+            //
+            // Record taxids precede aliases before the stable sort, so
+            // duplicated entries always keep the record taxid.
+            //
+            let mut index = taxonomies
+                .iter()
+                .zip(offsets.iter())
+                .map(|(taxonomy, offset)| (taxonomy.taxid, *offset))
+                .chain(taxonomies.iter().zip(offsets.iter()).flat_map(
+                    |(taxonomy, offset)| {
+                        taxonomy.aliases.iter().map(|alias| (*alias, *offset))
+                    },
+                ))
+                .collect::<Vec<(u64, u64)>>();
+
+            index.sort_by_key(|(taxid, _)| *taxid);
+            index.dedup_by_key(|(taxid, _)| *taxid);
+
+            header.extend_from_slice(&(index.len() as u64).to_le_bytes());
+
+            writer.write_all(&header).map_err(write_error)?;
+
+            let records_start =
+                (header.len() + index.len() * INDEX_ENTRY_SIZE) as u64;
+
+            for (taxid, offset) in &index {
+                writer
+                    .write_all(&taxid.to_le_bytes())
+                    .and_then(|_| {
+                        writer
+                            .write_all(&(records_start + offset).to_le_bytes())
+                    })
+                    .map_err(write_error)?;
+            }
+
+            for taxonomy in &taxonomies {
+                encode(&mut writer, taxonomy).map_err(write_error)?;
+            }
+        }
+    }
+
+    writer.flush().map_err(write_error)
+}
+
+fn encoded_length(taxonomy: &TaxonomyMapUnit) -> usize {
+    let string_length = |value: &str| 4 + value.len();
+
+    8 + string_length(&taxonomy.rank)
+        + string_length(&taxonomy.numeric_lineage)
+        + string_length(&taxonomy.text_lineage)
+        + 4
+        + taxonomy
+            .accessions
+            .iter()
+            .map(|item| {
+                string_length(&item.accession) + string_length(&item.oid)
+            })
+            .sum::<usize>()
+        + 4
+        + 8 * taxonomy.aliases.len()
+}
+
+fn encode(
+    writer: &mut impl Write,
+    taxonomy: &TaxonomyMapUnit,
+) -> std::io::Result<()> {
+    fn write_string(
+        writer: &mut impl Write,
+        value: &str,
+    ) -> std::io::Result<()> {
+        writer.write_all(&(value.len() as u32).to_le_bytes())?;
+        writer.write_all(value.as_bytes())
+    }
+
+    writer.write_all(&taxonomy.taxid.to_le_bytes())?;
+    write_string(writer, &taxonomy.rank)?;
+    write_string(writer, &taxonomy.numeric_lineage)?;
+    write_string(writer, &taxonomy.text_lineage)?;
+    writer.write_all(&(taxonomy.accessions.len() as u32).to_le_bytes())?;
+
+    for item in &taxonomy.accessions {
+        write_string(writer, &item.accession)?;
+        write_string(writer, &item.oid)?;
+    }
+
+    writer.write_all(&(taxonomy.aliases.len() as u32).to_le_bytes())?;
+
+    for alias in &taxonomy.aliases {
+        writer.write_all(&alias.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn corrupted<T>(reason: &str) -> Result<T, MappedErrors> {
+    execution_err(format!("Corrupted binary taxonomies database: {reason}"))
+        .as_error()
+}
+
+/// A bounds checked reader over the memory-mapped database
+struct ByteCursor<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteCursor<'a> {
+    fn new(bytes: &'a [u8], position: usize) -> Self {
+        Self { bytes, position }
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], MappedErrors> {
+        let end = match self.position.checked_add(length) {
+            Some(end) if end <= self.bytes.len() => end,
+            _ => return corrupted("unexpected end of file"),
+        };
+
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, MappedErrors> {
+        let mut buffer = [0; 4];
+        buffer.copy_from_slice(self.read_bytes(4)?);
+        Ok(u32::from_le_bytes(buffer))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, MappedErrors> {
+        let mut buffer = [0; 8];
+        buffer.copy_from_slice(self.read_bytes(8)?);
+        Ok(u64::from_le_bytes(buffer))
+    }
+
+    fn read_string(&mut self) -> Result<String, MappedErrors> {
+        let length = self.read_u32()? as usize;
+
+        String::from_utf8(self.read_bytes(length)?.to_vec())
+            .or_else(|_| corrupted("invalid UTF-8 string"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs::write, path::PathBuf};
+
+    fn database_path(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join("blutils-taxonomies-database-tests");
+
+        std::fs::create_dir_all(&directory).unwrap();
+        directory.join(format!("{}-{name}", std::process::id()))
+    }
+
+    fn taxonomy(taxid: u64, aliases: Vec<u64>, name: &str) -> TaxonomyMapUnit {
+        TaxonomyMapUnit {
+            taxid,
+            aliases,
+            rank: "species".to_owned(),
+            numeric_lineage: format!("d__2;s__{taxid}"),
+            text_lineage: format!("d__bacteria;s__{name}"),
+            accessions: vec![Accession {
+                accession: format!("NR_{taxid}.1"),
+                oid: taxid.to_string(),
+            }],
+        }
+    }
+
+    fn taxonomies_map() -> TaxonomiesMap {
+        TaxonomiesMap {
+            blutils_version: "8.3.1".to_owned(),
+            ignore_taxids: Some(vec![1, 131567]),
+            replace_rank: None,
+            drop_non_linnaean_taxonomies: Some(false),
+            fill_missing_ranks: None,
+            source_database: "mock-16S".to_owned(),
+            taxonomies: vec![
+                taxonomy(1423, vec![], "bacillus subtilis"),
+                taxonomy(562, vec![1637, 83334], "escherichia coli"),
+                //
+                // The alias of an existing record taxid should not shadow it.
+                //
+                taxonomy(90371, vec![1423], "salmonella enterica"),
+            ],
+        }
+    }
+
+    fn write_database(
+        name: &str,
+        format: &TaxonomiesDatabaseFormat,
+    ) -> PathBuf {
+        let path = database_path(name);
+        write_taxonomies_database(taxonomies_map(), &path, format).unwrap();
+        path
+    }
+
+    fn text_lineage(
+        database: &TaxonomiesDatabase,
+        taxid: u64,
+    ) -> Option<String> {
+        database
+            .get(taxid)
+            .unwrap()
+            .map(|taxonomy| taxonomy.text_lineage)
+    }
+
+    fn assert_database_content(database: &TaxonomiesDatabase) {
+        assert_eq!(database.metadata().source_database, "mock-16S");
+        assert_eq!(database.metadata().ignore_taxids, Some(vec![1, 131567]));
+
+        let escherichia = database.get(562).unwrap().unwrap();
+        assert_eq!(escherichia.aliases, vec![1637, 83334]);
+        assert_eq!(escherichia.accessions[0].accession, "NR_562.1");
+        assert_eq!(escherichia.accessions[0].oid, "562");
+        assert_eq!(escherichia.numeric_lineage, "d__2;s__562");
+
+        assert_eq!(
+            text_lineage(database, 1423).as_deref(),
+            Some("d__bacteria;s__bacillus subtilis")
+        );
+
+        assert_eq!(text_lineage(database, 9606), None);
+
+        let mut visited = Vec::<u64>::new();
+
+        database
+            .for_each(|taxonomy| {
+                visited.push(taxonomy.taxid);
+                Ok(())
+            })
+            .unwrap();
+
+        visited.sort();
+        assert_eq!(visited, vec![562, 1423, 90371]);
+    }
+
+    #[test]
+    fn default_format_is_json() {
+        assert_eq!(
+            TaxonomiesDatabaseFormat::default(),
+            TaxonomiesDatabaseFormat::Json
+        );
+    }
+
+    #[test]
+    fn databases_round_trip() {
+        for format in [
+            TaxonomiesDatabaseFormat::Json,
+            TaxonomiesDatabaseFormat::Binary,
+        ] {
+            let path = write_database(
+                &format!("round-trip.blutils.{}", format.extension()),
+                &format,
+            );
+
+            let database = TaxonomiesDatabase::open(&path).unwrap();
+
+            assert_eq!(
+                matches!(database, TaxonomiesDatabase::Binary(_)),
+                format == TaxonomiesDatabaseFormat::Binary
+            );
+
+            assert_database_content(&database);
+        }
+    }
+
+    #[test]
+    fn aliases_resolve_to_the_merged_record() {
+        for format in [
+            TaxonomiesDatabaseFormat::Json,
+            TaxonomiesDatabaseFormat::Binary,
+        ] {
+            let path = write_database(
+                &format!("aliases.blutils.{}", format.extension()),
+                &format,
+            );
+
+            let database = TaxonomiesDatabase::open(&path).unwrap();
+
+            assert_eq!(database.get(83334).unwrap().unwrap().taxid, 562);
+
+            let found = database
+                .lookup([1637, 9606, 1423, 562])
+                .unwrap()
+                .into_iter()
+                .map(|(taxid, taxonomy)| (taxid, taxonomy.taxid))
+                .collect::<Vec<(u64, u64)>>();
+
+            assert_eq!(found, vec![(1637, 562), (1423, 1423), (562, 562)]);
+        }
+    }
+
+    #[test]
+    fn other_binary_database_versions_are_rejected() {
+        let path = write_database(
+            "version-source.blutils.bin",
+            &TaxonomiesDatabaseFormat::Binary,
+        );
+
+        let mut content = std::fs::read(&path).unwrap();
+        let version_path = database_path("version.blutils.bin");
+
+        for version in [0, 1, BINARY_VERSION + 1] {
+            content[BINARY_MAGIC.len()..BINARY_MAGIC.len() + 4]
+                .copy_from_slice(&u32::to_le_bytes(version));
+
+            write(&version_path, &content).unwrap();
+
+            assert!(TaxonomiesDatabase::open(&version_path).is_err());
+        }
+    }
+
+    #[test]
+    fn truncated_binary_databases_are_rejected() {
+        let path = write_database(
+            "truncated-source.blutils.bin",
+            &TaxonomiesDatabaseFormat::Binary,
+        );
+
+        let content = std::fs::read(&path).unwrap();
+        let truncated_path = database_path("truncated.blutils.bin");
+
+        //
+        // The record of the greatest taxid is the last one of the file, so
+        // any truncation should fail either on open or on its lookup.
+        //
+        for length in 0..content.len() {
+            write(&truncated_path, &content[..length]).unwrap();
+
+            match TaxonomiesDatabase::open(&truncated_path) {
+                Err(_) => continue,
+                Ok(database) => {
+                    assert!(database.get(90371).is_err(), "{length}")
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_binary_databases_are_rejected() {
+        let path = write_database(
+            "corrupted-source.blutils.bin",
+            &TaxonomiesDatabaseFormat::Binary,
+        );
+
+        let content = std::fs::read(&path).unwrap();
+        let corrupted_path = database_path("corrupted.blutils.bin");
+
+        let open_error = |content: &[u8]| {
+            write(&corrupted_path, content).unwrap();
+
+            TaxonomiesDatabase::open(&corrupted_path)
+                .err()
+                .map(|err| err.msg())
+                .unwrap_or_default()
+        };
+
+        let mut unsupported = content.clone();
+        unsupported[8..12].copy_from_slice(&99u32.to_le_bytes());
+        assert!(open_error(&unsupported).contains("Unsupported"));
+
+        let metadata_length =
+            u64::from_le_bytes(content[12..20].try_into().unwrap()) as usize;
+        let count_start = 20 + metadata_length;
+
+        let mut oversized = content.clone();
+        oversized[count_start..count_start + 8]
+            .copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(open_error(&oversized).contains("truncated taxids index"));
+
+        let mut invalid_metadata = content.clone();
+        invalid_metadata[20] = b'#';
+        assert!(open_error(&invalid_metadata).contains("settings"));
+
+        let mut invalid_magic = content.clone();
+        invalid_magic[0] = b'#';
+        assert!(!open_error(&invalid_magic).is_empty());
+    }
+}
@@ -8,10 +8,25 @@ pub(crate) struct TaxonomiesMap {
     pub(crate) ignore_taxids: Option<Vec<u64>>,
     pub(crate) replace_rank: Option<HashMap<String, String>>,
     pub(crate) drop_non_linnaean_taxonomies: Option<bool>,
+
+    #[serde(default)]
+    pub(crate) fill_missing_ranks: Option<RankFillingStrategy>,
+
     pub(crate) source_database: String,
     pub(crate) taxonomies: Vec<TaxonomyMapUnit>,
 }
 
+/// The placeholder used to fill Linnaean ranks missing from lineages
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "camelCase")]
+pub enum RankFillingStrategy {
+    /// Use `unclassified-<ancestor>`, e.g. `c__unclassified-bacillota`
+    Unclassified,
+
+    /// Repeat the closest ancestor, e.g. `c__bacillota`
+    Inherit,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct Accession {
@@ -25,6 +40,11 @@ pub(crate) struct Accession {
 #[serde(rename_all = "camelCase")]
 pub(crate) struct TaxonomyMapUnit {
     pub(crate) taxid: u64,
+
+    /// Taxids merged into the taxid, also matched on lookup
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) aliases: Vec<u64>,
+
     pub(crate) rank: String,
     pub(crate) numeric_lineage: String,
     pub(crate) text_lineage: String,
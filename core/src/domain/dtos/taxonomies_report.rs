@@ -0,0 +1,328 @@
+use super::{
+    linnaean_ranks::LinnaeanRank,
+    taxonomies_database::TaxonomiesDatabase,
+    taxonomies_map::{RankFillingStrategy, TaxonomyMapUnit},
+};
+
+use mycelium_base::utils::errors::MappedErrors;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Clone, Debug, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "camelCase")]
+pub enum ReportFormat {
+    /// Human readable text format
+    Text,
+
+    /// JSON format
+    Json,
+}
+
+/// Taxa and accessions counts of a single rank
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RankCount {
+    pub rank: LinnaeanRank,
+    pub taxa: usize,
+    pub accessions: usize,
+}
+
+/// Number of taxa with lineages of a single depth
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineageDepthCount {
+    pub depth: usize,
+    pub taxa: usize,
+}
+
+/// A taxid whose rank is not a Linnaean rank
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OtherRankTaxid {
+    pub taxid: u64,
+    pub rank: String,
+}
+
+/// A lineage containing `taxid-<n>` placeholder names
+///
+/// Placeholders are used by the database builder for taxids without name in
+/// the taxdump.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaceholderLineage {
+    pub taxid: u64,
+    pub text_lineage: String,
+}
+
+/// An accession found more than once in the database
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateAccession {
+    pub accession: String,
+    pub taxids: Vec<u64>,
+}
+
+/// Statistics and validation report of a Blutils taxonomies database
+///
+/// Non Linnaean ranks, placeholder names, and duplicate accessions are listed
+/// as issues to be reviewed before using the database.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaxonomiesReport {
+    pub blutils_version: String,
+    pub source_database: String,
+    pub ignore_taxids: Option<Vec<u64>>,
+    pub replace_rank: Option<BTreeMap<String, String>>,
+    pub drop_non_linnaean_taxonomies: Option<bool>,
+    pub fill_missing_ranks: Option<RankFillingStrategy>,
+    pub taxa: usize,
+    pub accessions: usize,
+    pub ranks: Vec<RankCount>,
+    pub lineage_depths: Vec<LineageDepthCount>,
+    pub other_rank_taxids: Vec<OtherRankTaxid>,
+    pub placeholder_lineages: Vec<PlaceholderLineage>,
+    pub duplicate_accessions: Vec<DuplicateAccession>,
+}
+
+impl TaxonomiesReport {
+    /// Build the report visiting all taxonomies of the database
+    ///
+    /// Ranks are sorted from domain to species, followed by the non Linnaean
+    /// ranks in alphabetical order.
+    pub(crate) fn from_database(
+        database: &TaxonomiesDatabase,
+    ) -> Result<Self, MappedErrors> {
+        let metadata = database.metadata();
+
+        let mut ranks = LinnaeanRank::ordered_iter(Some(true))
+            .map(|rank| RankCount {
+                rank: rank.to_owned(),
+                taxa: 0,
+                accessions: 0,
+            })
+            .collect::<Vec<RankCount>>();
+
+        let mut other_ranks = BTreeMap::<String, RankCount>::new();
+        let mut lineage_depths = BTreeMap::<usize, usize>::new();
+        let mut other_rank_taxids = Vec::<OtherRankTaxid>::new();
+        let mut placeholder_lineages = Vec::<PlaceholderLineage>::new();
+        let mut accession_taxids = HashMap::<String, Vec<u64>>::new();
+        let (mut taxa, mut accessions) = (0, 0);
+
+        database.for_each(|record| {
+            taxa += 1;
+            accessions += record.accessions.len();
+
+            let rank = record
+                .rank
+                .parse::<LinnaeanRank>()
+                .unwrap_or(LinnaeanRank::Other(record.rank.to_owned()));
+
+            let count = match &rank {
+                LinnaeanRank::Other(_) | LinnaeanRank::Undefined => {
+                    other_rank_taxids.push(OtherRankTaxid {
+                        taxid: record.taxid,
+                        rank: record.rank.to_owned(),
+                    });
+
+                    other_ranks.entry(record.rank.to_owned()).or_insert(
+                        RankCount {
+                            rank,
+                            taxa: 0,
+                            accessions: 0,
+                        },
+                    )
+                }
+                _ => ranks
+                    .iter_mut()
+                    .find(|count| count.rank == rank)
+                    .expect("Linnaean ranks should be counted"),
+            };
+
+            count.taxa += 1;
+            count.accessions += record.accessions.len();
+
+            *lineage_depths
+                .entry(
+                    record
+                        .numeric_lineage
+                        .split(';')
+                        .filter(|element| !element.is_empty())
+                        .count(),
+                )
+                .or_default() += 1;
+
+            if has_placeholder_names(record) {
+                placeholder_lineages.push(PlaceholderLineage {
+                    taxid: record.taxid,
+                    text_lineage: record.text_lineage.to_owned(),
+                });
+            }
+
+            for accession in &record.accessions {
+                accession_taxids
+                    .entry(accession.accession.to_owned())
+                    .or_default()
+                    .push(record.taxid);
+            }
+
+            Ok(())
+        })?;
+
+        let mut duplicate_accessions = accession_taxids
+            .into_iter()
+            .filter(|(_, taxids)| taxids.len() > 1)
+            .map(|(accession, taxids)| DuplicateAccession { accession, taxids })
+            .collect::<Vec<DuplicateAccession>>();
+
+        duplicate_accessions.sort_by(|a, b| a.accession.cmp(&b.accession));
+        other_rank_taxids.sort_by_key(|item| item.taxid);
+        placeholder_lineages.sort_by_key(|item| item.taxid);
+
+        ranks.extend(other_ranks.into_values());
+
+        Ok(TaxonomiesReport {
+            blutils_version: metadata.blutils_version.to_owned(),
+            source_database: metadata.source_database.to_owned(),
+            ignore_taxids: metadata.ignore_taxids.to_owned(),
+            replace_rank: metadata
+                .replace_rank
+                .to_owned()
+                .map(|ranks| ranks.into_iter().collect()),
+            drop_non_linnaean_taxonomies: metadata.drop_non_linnaean_taxonomies,
+            fill_missing_ranks: metadata.fill_missing_ranks.to_owned(),
+            taxa,
+            accessions,
+            ranks,
+            lineage_depths: lineage_depths
+                .into_iter()
+                .map(|(depth, taxa)| LineageDepthCount { depth, taxa })
+                .collect(),
+            other_rank_taxids,
+            placeholder_lineages,
+            duplicate_accessions,
+        })
+    }
+
+    /// The number of issues found in the database
+    pub fn issues(&self) -> usize {
+        self.other_rank_taxids.len()
+            + self.placeholder_lineages.len()
+            + self.duplicate_accessions.len()
+    }
+
+    /// Format the report as human readable text
+    pub fn to_text(&self) -> String {
+        let none = "none".to_string();
+
+        let mut lines = vec![
+            format!("Blutils version:              {}", self.blutils_version),
+            format!("Source database:              {}", self.source_database),
+            format!(
+                "Ignored taxids:               {}",
+                self.ignore_taxids
+                    .as_ref()
+                    .map(|taxids| taxids
+                        .iter()
+                        .map(|taxid| taxid.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", "))
+                    .unwrap_or(none.to_owned())
+            ),
+            format!(
+                "Replaced ranks:               {}",
+                self.replace_rank
+                    .as_ref()
+                    .map(|ranks| ranks
+                        .iter()
+                        .map(|(from, to)| format!("{from}={to}"))
+                        .collect::<Vec<String>>()
+                        .join(", "))
+                    .unwrap_or(none.to_owned())
+            ),
+            format!(
+                "Drop non Linnaean taxonomies: {}",
+                self.drop_non_linnaean_taxonomies.unwrap_or(false)
+            ),
+            format!(
+                "Fill missing ranks:           {}",
+                match &self.fill_missing_ranks {
+                    Some(RankFillingStrategy::Unclassified) => "unclassified",
+                    Some(RankFillingStrategy::Inherit) => "inherit",
+                    None => "none",
+                }
+            ),
+            String::new(),
+            format!("Taxa:                         {}", self.taxa),
+            format!("Accessions:                   {}", self.accessions),
+            String::new(),
+            format!("{:<20}{:>12}{:>12}", "rank", "taxa", "accessions"),
+        ];
+
+        for count in &self.ranks {
+            lines.push(format!(
+                "{:<20}{:>12}{:>12}",
+                count.rank.as_full_rank_string(),
+                count.taxa,
+                count.accessions
+            ));
+        }
+
+        lines.push(String::new());
+        lines.push(format!("{:<20}{:>12}", "lineage depth", "taxa"));
+
+        for count in &self.lineage_depths {
+            lines.push(format!("{:<20}{:>12}", count.depth, count.taxa));
+        }
+
+        lines.push(String::new());
+        lines.push(format!(
+            "Taxids with non Linnaean ranks: {}",
+            self.other_rank_taxids.len()
+        ));
+
+        for item in &self.other_rank_taxids {
+            lines.push(format!("  {}\t{}", item.taxid, item.rank));
+        }
+
+        lines.push(format!(
+            "Lineages with placeholder names: {}",
+            self.placeholder_lineages.len()
+        ));
+
+        for item in &self.placeholder_lineages {
+            lines.push(format!("  {}\t{}", item.taxid, item.text_lineage));
+        }
+
+        lines.push(format!(
+            "Duplicate accessions: {}",
+            self.duplicate_accessions.len()
+        ));
+
+        for item in &self.duplicate_accessions {
+            lines.push(format!(
+                "  {}\t{}",
+                item.accession,
+                item.taxids
+                    .iter()
+                    .map(|taxid| taxid.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ));
+        }
+
+        lines.join("\n") + "\n"
+    }
+}
+
+/// Check if any lineage element is named with a `taxid-<n>` placeholder
+fn has_placeholder_names(record: &TaxonomyMapUnit) -> bool {
+    record.text_lineage.split(';').any(|element| {
+        element
+            .split_once("__")
+            .and_then(|(_, name)| name.strip_prefix("taxid-"))
+            .is_some_and(|taxid| {
+                !taxid.is_empty() && taxid.chars().all(|c| c.is_ascii_digit())
+            })
+    })
+}
@@ -11,6 +11,14 @@ pub enum ExecutionResponse {
 }
 
 pub trait ExecuteBlastn: Sync + Send + Debug {
+    /// Check if the subject reads should be a valid blast database
+    ///
+    /// Adapters aligning against other reference formats, as FASTA files,
+    /// should return `false` to skip the blast database validation.
+    fn requires_blast_database(&self) -> bool {
+        true
+    }
+
     fn run(
         &self,
         query_sequences: String,
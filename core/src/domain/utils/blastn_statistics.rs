@@ -0,0 +1,90 @@
+/// The score of identical bases of the `blastn` task
+pub const BLASTN_MATCH_SCORE: i32 = 2;
+
+/// The score of distinct or ambiguous bases of the `blastn` task
+pub const BLASTN_MISMATCH_SCORE: i32 = -3;
+
+/// The cost to open a gap of the `blastn` task, charged together with the
+/// first extension
+pub const BLASTN_GAP_OPEN: i32 = 5;
+
+/// The cost of each gap position of the `blastn` task
+pub const BLASTN_GAP_EXTEND: i32 = 2;
+
+/// The Karlin-Altschul lambda of the `blastn` task scores
+const LAMBDA: f64 = 0.625;
+
+/// The Karlin-Altschul K of the `blastn` task scores
+const K: f64 = 0.41;
+
+/// The raw score of an alignment using the `blastn` task scores
+pub fn blastn_raw_score(
+    identities: usize,
+    mismatches: usize,
+    gap_openings: usize,
+    gaps: usize,
+) -> i32 {
+    identities as i32 * BLASTN_MATCH_SCORE
+        + mismatches as i32 * BLASTN_MISMATCH_SCORE
+        - gap_openings as i32 * BLASTN_GAP_OPEN
+        - gaps as i32 * BLASTN_GAP_EXTEND
+}
+
+/// The bit score of a `blastn` task raw score
+pub fn blastn_bit_score(score: i32) -> f64 {
+    (LAMBDA * score as f64 - K.ln()) / std::f64::consts::LN_2
+}
+
+/// The expected number of hits with the raw score in the search space
+///
+/// The search space is the product of the query and the subjects lengths,
+/// without the length adjustments of blast.
+pub fn blastn_e_value(
+    score: i32,
+    query_length: usize,
+    subjects_length: u64,
+) -> f64 {
+    K * query_length as f64
+        * subjects_length as f64
+        * (-LAMBDA * score as f64).exp()
+}
+
+/// Format the e-value following the blast tabular output
+pub fn format_e_value(e_value: f64) -> String {
+    match e_value {
+        value if value < 1.0e-180 => "0.0".to_string(),
+        value if value < 1.0e-99 => format_exponent(value, 0),
+        value if value < 0.0009 => format_exponent(value, 2),
+        value if value < 0.1 => format!("{value:.3}"),
+        value if value < 1.0 => format!("{value:.2}"),
+        value if value < 10.0 => format!("{value:.1}"),
+        value => format!("{value:.0}"),
+    }
+}
+
+/// Format the bit score following the blast tabular output
+pub fn format_bit_score(bit_score: f64) -> String {
+    match bit_score {
+        value if value > 9999.0 => format_exponent(value, 3),
+        value if value > 99.9 => format!("{value:.0}"),
+        value => format!("{value:.1}"),
+    }
+}
+
+/// Format the value in scientific notation with two digits exponents, as the
+/// C `%e` format used by blast
+fn format_exponent(value: f64, precision: usize) -> String {
+    let formatted = format!("{value:.precision$e}");
+
+    match formatted.split_once('e') {
+        Some((mantissa, exponent)) => {
+            let (sign, digits) = match exponent.strip_prefix('-') {
+                Some(digits) => ("-", digits),
+                None => ("+", exponent),
+            };
+
+            format!("{mantissa}e{sign}{digits:0>2}")
+        }
+        None => formatted,
+    }
+}
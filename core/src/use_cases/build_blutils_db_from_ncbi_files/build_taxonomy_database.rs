@@ -1,13 +1,18 @@
 use super::{
-    load_del_nodes_dataframe, load_lineage_dataframe, load_merged_dataframe,
-    load_names_dataframe, load_nodes_dataframe,
+    fill_lineage_ranks, load_del_nodes_dataframe, load_lineage_dataframe,
+    load_merged_dataframe, load_names_dataframe, load_nodes_dataframe,
 };
 use crate::{
     domain::dtos::{
         linnaean_ranks::LinnaeanRank,
-        taxonomies_map::{Accession, TaxonomiesMap, TaxonomyMapUnit},
+        taxonomies_database::{
+            write_taxonomies_database, TaxonomiesDatabaseFormat,
+        },
+        taxonomies_map::{
+            Accession, RankFillingStrategy, TaxonomiesMap, TaxonomyMapUnit,
+        },
     },
-    use_cases::shared::write_or_append_to_file,
+    use_cases::shared::{sibling_output_file, write_or_append_to_file},
 };
 
 use mycelium_base::utils::errors::{use_case_err, MappedErrors};
@@ -16,13 +21,7 @@ use polars_ops::{
     prelude::DataFrameJoinOps,
 };
 use slugify::slugify;
-use std::{
-    collections::HashMap,
-    fs::{remove_file, File},
-    io::Write,
-    path::PathBuf,
-    str::FromStr,
-};
+use std::{collections::HashMap, fs::remove_file, path::PathBuf, str::FromStr};
 use tracing::{debug, warn};
 
 #[derive(Debug, Clone)]
@@ -44,6 +43,7 @@ pub(crate) struct RankedTaxidUnit {
         ignore_taxids,
         replace_rank,
         drop_non_linnaean_taxonomies,
+        fill_missing_ranks,
     )
 )]
 pub(crate) fn build_taxonomy_database(
@@ -56,31 +56,15 @@ pub(crate) fn build_taxonomy_database(
     ignore_taxids: Option<Vec<u64>>,
     replace_rank: Option<HashMap<String, String>>,
     drop_non_linnaean_taxonomies: Option<bool>,
+    fill_missing_ranks: Option<RankFillingStrategy>,
     database: String,
     output_file_path: PathBuf,
+    output_format: TaxonomiesDatabaseFormat,
 ) -> Result<(), MappedErrors> {
     // ? -----------------------------------------------------------------------
     // ? Validate arguments
     // ? -----------------------------------------------------------------------
 
-    if !names_path.is_file() {
-        return use_case_err(format!("Invalid names path: {:?}", names_path))
-            .as_error();
-    }
-
-    if !lineage_path.is_file() {
-        return use_case_err(format!(
-            "Invalid lineages path: {:?}",
-            lineage_path
-        ))
-        .as_error();
-    }
-
-    if !nodes_path.is_file() {
-        return use_case_err(format!("Invalid nodes path: {:?}", nodes_path))
-            .as_error();
-    }
-
     if !del_nodes_path.is_file() {
         return use_case_err(format!(
             "Invalid delnodes path: {:?}",
@@ -104,6 +88,171 @@ pub(crate) fn build_taxonomy_database(
     debug!("Loading and validating `MERGED` nodes");
     let merged_map = load_merged_dataframe(merged_path)?;
 
+    let ranked_tax_ids =
+        load_ranked_taxids(names_path, nodes_path, lineage_path)?;
+
+    // ? -----------------------------------------------------------------------
+    // ? Build output files
+    // ? -----------------------------------------------------------------------
+
+    //
+    // Create the main output file path
+    //
+    let output_database_file = sibling_output_file(
+        &output_file_path,
+        &format!("blutils.{}", output_format.extension()),
+    );
+
+    //
+    // Create a file to include not mapped tax_ids
+    //
+    let non_mapped_file =
+        sibling_output_file(&output_file_path, "non-mapped.tsv");
+
+    let non_mapped_file_file_binding = non_mapped_file.as_path();
+
+    if non_mapped_file_file_binding.exists() {
+        remove_file(non_mapped_file_file_binding).unwrap();
+    }
+
+    let (non_mapped_writer, non_mapped_file) =
+        write_or_append_to_file(&non_mapped_file_file_binding);
+
+    // ? -----------------------------------------------------------------------
+    // ? Hydrate lineages and Build the output taxonomies dataframe
+    // ? -----------------------------------------------------------------------
+
+    let write_non_mapped = |line: String| {
+        match non_mapped_writer(
+            line,
+            non_mapped_file
+                .try_clone()
+                .expect("Unexpected error detected on write non-mapped file"),
+        ) {
+            Ok(_) => (),
+            Err(err) => panic!("{err}"),
+        };
+    };
+
+    //
+    // Taxids are resolved before hydrating lineages, given accessions of
+    // merged taxids are grouped with the accessions of the new taxid. The
+    // merged taxids are kept as aliases of the new taxid.
+    //
+    let mut resolved_tax_ids =
+        HashMap::<u64, (Vec<Accession>, Vec<u64>)>::new();
+
+    for (tax_id, accessions) in accessions_map {
+        if ranked_tax_ids.contains_key(&tax_id) {
+            resolved_tax_ids
+                .entry(tax_id)
+                .or_default()
+                .0
+                .extend(accessions);
+
+            continue;
+        }
+
+        //
+        // This condition is triggered when a tax_id is not found in the
+        // taxdump files and is a deleted node.
+        //
+        if del_nodes_vector.contains(&tax_id) {
+            write_non_mapped(format!("{tax_id}\tdeleted\t\n"));
+            continue;
+        }
+
+        //
+        // This condition is triggered when a tax_id is not found in the
+        // taxdump files and is a merged node.
+        //
+        match merged_map.get(&tax_id) {
+            Some(new_tax_id) if ranked_tax_ids.contains_key(new_tax_id) => {
+                write_non_mapped(format!("{tax_id}\tremapped\t{new_tax_id}\n"));
+
+                let (new_accessions, aliases) =
+                    resolved_tax_ids.entry(*new_tax_id).or_default();
+
+                new_accessions.extend(accessions);
+                aliases.push(tax_id);
+            }
+            Some(new_tax_id) => {
+                write_non_mapped(format!("{tax_id}\tmerged\t{new_tax_id}\n"))
+            }
+            None => write_non_mapped(format!("{tax_id}\tunknown\t\n")),
+        }
+    }
+
+    let mut taxonomies = Vec::<TaxonomyMapUnit>::new();
+
+    for (tax_id, (accessions, mut aliases)) in resolved_tax_ids {
+        if let Some(mut taxonomy) = build_taxonomy_unit(
+            tax_id,
+            &ranked_tax_ids[&tax_id],
+            &ranked_tax_ids,
+            accessions,
+            &ignore_taxids,
+            &replace_rank,
+            drop_non_linnaean_taxonomies,
+        ) {
+            aliases.sort();
+            taxonomy.aliases = aliases;
+
+            if let Some(strategy) = &fill_missing_ranks {
+                fill_lineage_ranks(&mut taxonomy, strategy);
+            }
+
+            taxonomies.push(taxonomy);
+        }
+    }
+
+    write_taxonomies_database(
+        TaxonomiesMap {
+            blutils_version: env!("CARGO_PKG_VERSION").to_string(),
+            ignore_taxids,
+            replace_rank,
+            drop_non_linnaean_taxonomies,
+            fill_missing_ranks,
+            source_database: database,
+            taxonomies,
+        },
+        &output_database_file,
+        &output_format,
+    )
+}
+
+/// Load the names, rank and lineage of all taxids of the taxdump files
+pub(super) fn load_ranked_taxids(
+    names_path: PathBuf,
+    nodes_path: PathBuf,
+    lineage_path: PathBuf,
+) -> Result<HashMap<u64, RankedTaxidUnit>, MappedErrors> {
+    // ? -----------------------------------------------------------------------
+    // ? Validate arguments
+    // ? -----------------------------------------------------------------------
+
+    if !names_path.is_file() {
+        return use_case_err(format!("Invalid names path: {:?}", names_path))
+            .as_error();
+    }
+
+    if !lineage_path.is_file() {
+        return use_case_err(format!(
+            "Invalid lineages path: {:?}",
+            lineage_path
+        ))
+        .as_error();
+    }
+
+    if !nodes_path.is_file() {
+        return use_case_err(format!("Invalid nodes path: {:?}", nodes_path))
+            .as_error();
+    }
+
+    // ? -----------------------------------------------------------------------
+    // ? Load reference data-frames
+    // ? -----------------------------------------------------------------------
+
     debug!("Loading and validating `NAMES`");
     let names_df = load_names_dataframe(names_path)?;
 
@@ -236,263 +385,150 @@ pub(crate) fn build_taxonomy_database(
         );
     }
 
-    // ? -----------------------------------------------------------------------
-    // ? Build output files
-    // ? -----------------------------------------------------------------------
-
-    let mut output_path = output_file_path;
-    output_path.set_extension("json");
-
-    //
-    // Create the main output file path
-    //
-    let output_database_file = match output_path.parent() {
-        Some(parent) => parent.join(format!(
-            "{}.blutils.json",
-            output_path.file_stem().unwrap().to_str().unwrap()
-        )),
-        None => PathBuf::from("blutils.json"),
-    };
-
-    //
-    // Create a file to include not mapped tax_ids
-    //
-    let non_mapped_file = match output_path.parent() {
-        Some(parent) => parent.join(format!(
-            "{}.non-mapped.tsv",
-            output_path.file_stem().unwrap().to_str().unwrap()
-        )),
-        None => PathBuf::from("non-mapped.tsv"),
-    };
-
-    let non_mapped_file_file_binding = non_mapped_file.as_path();
-
-    if non_mapped_file_file_binding.exists() {
-        remove_file(non_mapped_file_file_binding).unwrap();
-    }
-
-    let (non_mapped_writer, non_mapped_file) =
-        write_or_append_to_file(&non_mapped_file_file_binding);
-
-    // ? -----------------------------------------------------------------------
-    // ? Hydrate lineages and Build the output taxonomies dataframe
-    // ? -----------------------------------------------------------------------
+    Ok(ranked_tax_ids)
+}
 
-    let mut taxonomies = Vec::<TaxonomyMapUnit>::new();
+/// Build the taxonomies database record of a taxid
+///
+/// The lineage is hydrated from the ranked taxids. Returns `None` if the
+/// taxid rank is not a Linnaean rank and the `drop_non_linnaean_taxonomies`
+/// flag is set to true.
+pub(super) fn build_taxonomy_unit(
+    tax_id: u64,
+    ranked_tax_id: &RankedTaxidUnit,
+    ranked_tax_ids: &HashMap<u64, RankedTaxidUnit>,
+    accessions: Vec<Accession>,
+    ignore_taxids: &Option<Vec<u64>>,
+    replace_rank: &Option<HashMap<String, String>>,
+    drop_non_linnaean_taxonomies: Option<bool>,
+) -> Option<TaxonomyMapUnit> {
+    let lineage = ranked_tax_id
+        .lineage
+        .split(" ")
+        .flat_map(|lineage_tax_id| {
+            if lineage_tax_id.is_empty() || lineage_tax_id == "null" {
+                return None;
+            }
 
-    accessions_map.into_iter().for_each(|(tax_id, accessions)| {
-        let header = format!("{tax_id}");
-
-        let ranked_tax_id = match ranked_tax_ids.get(&tax_id) {
-            Some(res) => res,
-            None => {
-                //
-                // This condition is triggered when a tax_id is not found in the
-                // taxdump files and is a deleted node.
-                //
-                if del_nodes_vector.contains(&tax_id) {
-                    match non_mapped_writer(
-                        format!("{}\t{}\n", header, "deleted"),
-                        non_mapped_file
-                            .try_clone()
-                            .expect("Unexpected error detected on write non-mapped file"),
-                    ) {
-                        Ok(_) => (),
-                        Err(err) => panic!("{err}")
-                    };
-
-                    return;
-                }
+            let lineage_tax_id = lineage_tax_id.trim().parse::<u64>().unwrap();
 
-                //
-                // This condition is triggered when a tax_id is not found in the
-                // taxdump files and is a merged node.
-                //
-                if let Some(new_tax_id) = merged_map.get(&tax_id) {
-                    match ranked_tax_ids.get(&new_tax_id) {
-                        Some(res) => res,
-                        None => {
-
-                            match non_mapped_writer(
-                                format!("{}\t{}\n", header, "merged"),
-                                non_mapped_file
-                                    .try_clone()
-                                    .expect("Unexpected error detected on write non-mapped file"),
-                            ) {
-                                Ok(_) => (),
-                                Err(err) => panic!("{err}")
-                            };
-
-                            return;
-                        }
-                    }
-                } else {
-                    match non_mapped_writer(
-                        format!("{}\t{}\n", header, "unknown"),
-                        non_mapped_file
-                            .try_clone()
-                            .expect("Unexpected error detected on write non-mapped file"),
-                    ) {
-                        Ok(_) => (),
-                        Err(err) => panic!("{err}")
-                    };
-
-                    return;
+            if let Some(taxids) = ignore_taxids {
+                if taxids.contains(&lineage_tax_id) {
+                    return None;
                 }
             }
-        };
 
-        let lineage = ranked_tax_id.lineage
-            .split(" ")
-            .flat_map(|lineage_tax_id| {
-                if lineage_tax_id.is_empty() || lineage_tax_id == "null" {
+            let record = match ranked_tax_ids.get(&lineage_tax_id) {
+                Some(res) => res,
+                None => {
+                    warn!(
+                        "Unmapped tax_id detected {lineage_tax_id} in lineage: {lineage}",
+                        lineage = ranked_tax_id.lineage
+                    );
+
                     return None;
                 }
+            };
 
-                let lineage_tax_id = lineage_tax_id.trim().parse::<u64>().unwrap();
-
-                if let Some(taxids) = ignore_taxids.to_owned() {
-                    if taxids.contains(&lineage_tax_id) {
-                        return None;
+            let valid_rank = match replace_rank {
+                Some(replace_rank) => {
+                    if let Some(replaced_rank) = replace_rank.get(&record.rank)
+                    {
+                        replaced_rank.to_string()
+                    } else {
+                        record.rank.to_string()
                     }
                 }
-
-                let record = match ranked_tax_ids.get(&lineage_tax_id) {
-                    Some(res) => res,
-                    None => {
-                        warn!(
-                            "Unmapped tax_id detected {lineage_tax_id} in lineage: {lineage}",
-                            lineage = ranked_tax_id.lineage
-                        );
-
-                        return None;
-                    }
-                };
-
-                let valid_rank = match replace_rank.to_owned() {
-                    Some(replace_rank) => {
-                        if let Some(replaced_rank) = replace_rank.get(&record.rank) {
-                            replaced_rank.to_string()
+                None => record.rank.to_string(),
+            };
+
+            let valid_rank = match valid_rank.parse::<LinnaeanRank>() {
+                Ok(res) => match res {
+                    //
+                    // Skip non linnaean taxonomies if the non-linnaean rank
+                    // was found and the `drop_non_linnaean_taxonomies` flag
+                    // is set to true.
+                    //
+                    LinnaeanRank::Other(rank) => {
+                        if let Some(true) = drop_non_linnaean_taxonomies {
+                            return None;
                         } else {
-                            record.rank.to_string()
+                            slugify!(rank.clone().as_str(), separator = "-")
                         }
                     }
-                    None => record.rank.to_string(),
-                };
-
-                let valid_rank = match valid_rank.parse::<LinnaeanRank>() {
-                    Ok(res) => match res {
-                        //
-                        // Skip non linnaean taxonomies if the non-linnaean rank
-                        // was found and the `drop_non_linnaean_taxonomies` flag
-                        // is set to true.
-                        //
-                        LinnaeanRank::Other(rank) => {
-                            if let Some(true) = drop_non_linnaean_taxonomies {
-                                return None;
-                            } else {
-                                slugify!(rank.clone().as_str(), separator = "-")
-                            }
-                        }
-                        _ => res.to_string(),
-                    },
-                    Err(_) => {
-                        panic!(
-                            "Unexpected error detected on parse rank: {}", record.rank
-                        )
-                    },
-                };
-
-                let ranked_name = format!(
-                    "{}__{}",
-                    valid_rank,
-                    slugify!(record.name.as_str()).replace("__", "_")
-                );
-
-                let ranked_taxid = format!(
-                    "{}__{}",
-                    valid_rank,
-                    lineage_tax_id
-                );
-
-                Some((ranked_taxid, ranked_name))
-            })
-            .collect::<Vec<(String, String)>>();
-
-        //
-        // Skip non linnaean taxonomies if the non-linnaean rank was found and
-        // the `drop_non_linnaean_taxonomies` flag is set to true.
-        //
-        let slug_rank = match LinnaeanRank::from_str(&ranked_tax_id.rank) {
-            Ok(res) => match res {
-                LinnaeanRank::Other(rank) => {
-                    if let Some(true) = drop_non_linnaean_taxonomies {
-                        return;
-                    } else {
-                        slugify!(rank.as_str(), separator = "-")
-                    }
+                    _ => res.to_string(),
+                },
+                Err(_) => {
+                    panic!(
+                        "Unexpected error detected on parse rank: {}",
+                        record.rank
+                    )
                 }
-                _ => res.to_string(),
-            },
-            Err(_) => slugify!(ranked_tax_id.rank.as_str(), separator = "-"),
-        };
+            };
 
-        //
-        // Write the taxi-ds based taxonomies to the output files
-        //
-        let mut ranked_taxids = lineage
-            .iter()
-            .map(|(ranked_taxid,_)| ranked_taxid.to_string())
-            .collect::<Vec<String>>()
-            .join(";");
+            let ranked_name = format!(
+                "{}__{}",
+                valid_rank,
+                slugify!(record.name.as_str()).replace("__", "_")
+            );
 
-        ranked_taxids = format!("{};{}__{}", ranked_taxids, slug_rank, tax_id);
+            let ranked_taxid = format!("{}__{}", valid_rank, lineage_tax_id);
 
-        //
-        // Write the names based taxonomies to the output files
-        //
-        let mut ranked_names = lineage
-            .iter()
-            .map(|(_, ranked_name)| ranked_name.to_string())
-            .collect::<Vec<String>>()
-            .join(";");
-
-        ranked_names = format!(
-            "{};{}__{}", 
-            ranked_names, 
-            slug_rank, 
-            slugify!(ranked_tax_id.name.as_str()).replace("__", "_")
-        );
+            Some((ranked_taxid, ranked_name))
+        })
+        .collect::<Vec<(String, String)>>();
 
-        taxonomies.push(TaxonomyMapUnit {
-            taxid: tax_id,
-            rank: slug_rank,
-            numeric_lineage: ranked_taxids,
-            text_lineage: ranked_names,
-            accessions: accessions.to_owned(),
-        });
-    });
-
-    let mut file = match File::create(output_database_file) {
-        Err(err) => panic!("{err}"),
-        Ok(res) => res,
+    //
+    // Skip non linnaean taxonomies if the non-linnaean rank was found and
+    // the `drop_non_linnaean_taxonomies` flag is set to true.
+    //
+    let slug_rank = match LinnaeanRank::from_str(&ranked_tax_id.rank) {
+        Ok(res) => match res {
+            LinnaeanRank::Other(rank) => {
+                if let Some(true) = drop_non_linnaean_taxonomies {
+                    return None;
+                } else {
+                    slugify!(rank.as_str(), separator = "-")
+                }
+            }
+            _ => res.to_string(),
+        },
+        Err(_) => slugify!(ranked_tax_id.rank.as_str(), separator = "-"),
     };
 
-    match file.write_all(
-        serde_json::to_string_pretty(&TaxonomiesMap {
-            blutils_version: env!("CARGO_PKG_VERSION").to_string(),
-            ignore_taxids,
-            replace_rank,
-            drop_non_linnaean_taxonomies,
-            source_database: database,
-            taxonomies,
-        })
-        .unwrap()
-        .as_bytes(),
-    ) {
-        Err(err) => panic!("{err}"),
-        Ok(_) => (),
-    };
+    //
+    // Write the taxi-ds based taxonomies to the output files
+    //
+    let mut ranked_taxids = lineage
+        .iter()
+        .map(|(ranked_taxid, _)| ranked_taxid.to_string())
+        .collect::<Vec<String>>()
+        .join(";");
+
+    ranked_taxids = format!("{};{}__{}", ranked_taxids, slug_rank, tax_id);
 
-    Ok(())
+    //
+    // Write the names based taxonomies to the output files
+    //
+    let mut ranked_names = lineage
+        .iter()
+        .map(|(_, ranked_name)| ranked_name.to_string())
+        .collect::<Vec<String>>()
+        .join(";");
+
+    ranked_names = format!(
+        "{};{}__{}",
+        ranked_names,
+        slug_rank,
+        slugify!(ranked_tax_id.name.as_str()).replace("__", "_")
+    );
+
+    Some(TaxonomyMapUnit {
+        taxid: tax_id,
+        aliases: Vec::new(),
+        rank: slug_rank,
+        numeric_lineage: ranked_taxids,
+        text_lineage: ranked_names,
+        accessions,
+    })
 }
@@ -0,0 +1,166 @@
+use crate::domain::dtos::{
+    linnaean_ranks::LinnaeanRank,
+    taxonomies_map::{RankFillingStrategy, TaxonomyMapUnit},
+};
+
+/// Fill the Linnaean ranks missing from the taxonomy lineages
+///
+/// Lineages are rewritten with exactly one element per Linnaean rank, from
+/// domain to species. Missing ranks receive a placeholder derived from the
+/// closest ancestor found in the lineage, given the filling strategy. Non
+/// Linnaean ranks, including ranks below species, are removed.
+///
+/// Numeric lineage elements are taxids, except for placeholders. The
+/// `Unclassified` strategy writes `unclassified-<taxid>` values, and ranks
+/// without ancestor, as a missing domain, are filled as `unclassified` with
+/// any strategy. Consumers of numeric lineages, as the Kraken taxonomy export
+/// and the subset by taxid, should skip elements not parsed as taxids.
+pub(super) fn fill_lineage_ranks(
+    taxonomy: &mut TaxonomyMapUnit,
+    strategy: &RankFillingStrategy,
+) {
+    let numeric_elements = split_lineage(&taxonomy.numeric_lineage);
+    let text_elements = split_lineage(&taxonomy.text_lineage);
+
+    let mut numeric_lineage = Vec::<String>::new();
+    let mut text_lineage = Vec::<String>::new();
+    let mut ancestor: Option<(&str, &str)> = None;
+
+    for rank in LinnaeanRank::ordered_iter(Some(true)) {
+        let position = numeric_elements
+            .iter()
+            .position(|(element_rank, _)| element_rank == rank);
+
+        let (taxid, name) = match (position, ancestor) {
+            (Some(position), _) => {
+                let taxid = numeric_elements[position].1;
+                let name = text_elements
+                    .get(position)
+                    .map(|(_, name)| *name)
+                    .unwrap_or(taxid);
+
+                ancestor = Some((taxid, name));
+                (taxid.to_string(), name.to_string())
+            }
+            (None, Some((taxid, name))) => match strategy {
+                RankFillingStrategy::Unclassified => (
+                    format!("unclassified-{taxid}"),
+                    format!("unclassified-{name}"),
+                ),
+                RankFillingStrategy::Inherit => {
+                    (taxid.to_string(), name.to_string())
+                }
+            },
+            (None, None) => {
+                ("unclassified".to_string(), "unclassified".to_string())
+            }
+        };
+
+        numeric_lineage.push(format!("{rank}__{taxid}"));
+        text_lineage.push(format!("{rank}__{name}"));
+    }
+
+    taxonomy.numeric_lineage = numeric_lineage.join(";");
+    taxonomy.text_lineage = text_lineage.join(";");
+}
+
+/// Split a lineage into its rank and value pairs
+fn split_lineage(lineage: &str) -> Vec<(LinnaeanRank, &str)> {
+    lineage
+        .split(';')
+        .filter_map(|element| element.split_once("__"))
+        .filter_map(|(rank, value)| {
+            rank.parse::<LinnaeanRank>().ok().map(|rank| (rank, value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn taxonomy(numeric_lineage: &str, text_lineage: &str) -> TaxonomyMapUnit {
+        TaxonomyMapUnit {
+            taxid: 0,
+            aliases: Vec::new(),
+            rank: "s".to_owned(),
+            numeric_lineage: numeric_lineage.to_owned(),
+            text_lineage: text_lineage.to_owned(),
+            accessions: Vec::new(),
+        }
+    }
+
+    fn filled(
+        numeric_lineage: &str,
+        text_lineage: &str,
+        strategy: RankFillingStrategy,
+    ) -> (String, String) {
+        let mut taxonomy = taxonomy(numeric_lineage, text_lineage);
+        fill_lineage_ranks(&mut taxonomy, &strategy);
+        (taxonomy.numeric_lineage, taxonomy.text_lineage)
+    }
+
+    #[test]
+    fn ranks_between_phylum_and_order_are_filled_as_unclassified() {
+        assert_eq!(
+            filled(
+                "d__2;p__1239;o__1385;f__186817;g__1386;s__1423",
+                "d__bacteria;p__bacillota;o__bacillales;f__bacillaceae;g__bacillus;s__bacillus-subtilis",
+                RankFillingStrategy::Unclassified,
+            ),
+            (
+                "d__2;k__unclassified-2;p__1239;c__unclassified-1239;o__1385;f__186817;g__1386;s__1423".to_owned(),
+                "d__bacteria;k__unclassified-bacteria;p__bacillota;c__unclassified-bacillota;o__bacillales;f__bacillaceae;g__bacillus;s__bacillus-subtilis".to_owned(),
+            )
+        );
+    }
+
+    #[test]
+    fn ranks_between_phylum_and_order_inherit_the_ancestor() {
+        assert_eq!(
+            filled(
+                "d__2;p__1239;o__1385;f__186817;g__1386;s__1423",
+                "d__bacteria;p__bacillota;o__bacillales;f__bacillaceae;g__bacillus;s__bacillus-subtilis",
+                RankFillingStrategy::Inherit,
+            ),
+            (
+                "d__2;k__2;p__1239;c__1239;o__1385;f__186817;g__1386;s__1423".to_owned(),
+                "d__bacteria;k__bacteria;p__bacillota;c__bacillota;o__bacillales;f__bacillaceae;g__bacillus;s__bacillus-subtilis".to_owned(),
+            )
+        );
+    }
+
+    #[test]
+    fn leading_missing_domain_is_unclassified() {
+        for strategy in [
+            RankFillingStrategy::Unclassified,
+            RankFillingStrategy::Inherit,
+        ] {
+            let (numeric_lineage, text_lineage) = filled(
+                "superkingdom__2;p__1239;c__91061;o__1385;f__186817;g__1386;s__1423",
+                "superkingdom__bacteria;p__bacillota;c__bacilli;o__bacillales;f__bacillaceae;g__bacillus;s__bacillus-subtilis",
+                strategy,
+            );
+
+            assert!(numeric_lineage
+                .starts_with("d__unclassified;k__unclassified;p__1239;"));
+            assert!(text_lineage
+                .starts_with("d__unclassified;k__unclassified;p__bacillota;"));
+        }
+    }
+
+    #[test]
+    fn ranks_below_species_are_removed() {
+        assert_eq!(
+            filled(
+                "d__2;k__2;p__1224;c__1236;o__91347;f__543;g__561;s__562;strain__511145",
+                "d__bacteria;k__bacteria;p__pseudomonadota;c__gammaproteobacteria;o__enterobacterales;f__enterobacteriaceae;g__escherichia;s__escherichia-coli;strain__escherichia-coli-k-12",
+                RankFillingStrategy::Unclassified,
+            ),
+            (
+                "d__2;k__2;p__1224;c__1236;o__91347;f__543;g__561;s__562".to_owned(),
+                "d__bacteria;k__bacteria;p__pseudomonadota;c__gammaproteobacteria;o__enterobacterales;f__enterobacteriaceae;g__escherichia;s__escherichia-coli".to_owned(),
+            )
+        );
+    }
+}
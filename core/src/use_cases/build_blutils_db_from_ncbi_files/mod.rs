@@ -1,23 +1,35 @@
 mod build_accessions_map;
 mod build_taxonomy_database;
+mod fill_lineage_ranks;
 mod load_del_nodes_dataframe;
 mod load_dump_file;
 mod load_lineage_dataframe;
 mod load_merged_dataframe;
 mod load_names_dataframe;
 mod load_nodes_dataframe;
+mod update_taxonomy_database;
 
 use build_accessions_map::*;
 use build_taxonomy_database::*;
+use fill_lineage_ranks::*;
 use load_del_nodes_dataframe::*;
 use load_dump_file::*;
 use load_lineage_dataframe::*;
 use load_merged_dataframe::*;
 use load_names_dataframe::*;
 use load_nodes_dataframe::*;
+use update_taxonomy_database::*;
+
+use crate::domain::dtos::{
+    taxonomies_database::TaxonomiesDatabaseFormat,
+    taxonomies_map::RankFillingStrategy,
+};
 
 use mycelium_base::utils::errors::{execution_err, MappedErrors};
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 use tracing::info;
 
 /// Build blutil sreference database from NCBI files
@@ -28,7 +40,8 @@ use tracing::info;
         taxdump_directory_path,
         ignore_taxids,
         replace_rank,
-        drop_non_linnaean_taxonomies
+        drop_non_linnaean_taxonomies,
+        fill_missing_ranks
     )
 )]
 pub fn build_ref_db_from_ncbi_files(
@@ -37,7 +50,9 @@ pub fn build_ref_db_from_ncbi_files(
     ignore_taxids: Option<Vec<u64>>,
     replace_rank: Option<HashMap<String, String>>,
     drop_non_linnaean_taxonomies: Option<bool>,
+    fill_missing_ranks: Option<RankFillingStrategy>,
     output_file_path: PathBuf,
+    output_format: TaxonomiesDatabaseFormat,
 ) -> Result<(), MappedErrors> {
     // ? -----------------------------------------------------------------------
     // ? Build blast database
@@ -76,11 +91,52 @@ pub fn build_ref_db_from_ncbi_files(
         ignore_taxids,
         replace_rank,
         drop_non_linnaean_taxonomies,
+        fill_missing_ranks,
         blast_database_path.to_string(),
         output_file_path,
+        output_format,
     )?;
 
     info!("Taxonomy database built successfully");
 
     Ok(())
 }
+
+/// Update a blutils reference database from a new NCBI taxdump
+///
+/// The accessions of the existing taxonomies database are reused, so the blast
+/// database is not scanned again.
+#[tracing::instrument(
+    name = "Update Reference DB from New TaxDump",
+    skip(taxonomies_database_path, taxdump_directory_path)
+)]
+pub fn update_ref_db_from_ncbi_files(
+    taxonomies_database_path: &Path,
+    taxdump_directory_path: PathBuf,
+    output_file_path: PathBuf,
+    output_format: TaxonomiesDatabaseFormat,
+) -> Result<(), MappedErrors> {
+    info!(
+        "Updating taxonomy database {:?} from: {:?}",
+        taxonomies_database_path, taxdump_directory_path
+    );
+
+    if !taxdump_directory_path.is_dir() {
+        return execution_err(format!(
+            "Invalid taxdump directory path: {:?}",
+            taxdump_directory_path
+        ))
+        .as_error();
+    }
+
+    update_taxonomy_database(
+        taxonomies_database_path,
+        &taxdump_directory_path,
+        output_file_path,
+        output_format,
+    )?;
+
+    info!("Taxonomy database updated successfully");
+
+    Ok(())
+}
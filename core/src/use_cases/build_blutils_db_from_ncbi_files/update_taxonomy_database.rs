@@ -0,0 +1,547 @@
+use super::{
+    build_taxonomy_unit, fill_lineage_ranks, load_del_nodes_dataframe,
+    load_merged_dataframe, load_ranked_taxids,
+};
+use crate::{
+    domain::dtos::{
+        taxonomies_database::{
+            write_taxonomies_database, TaxonomiesDatabase,
+            TaxonomiesDatabaseFormat,
+        },
+        taxonomies_map::{TaxonomiesMap, TaxonomyMapUnit},
+    },
+    use_cases::shared::sibling_output_file,
+};
+
+use mycelium_base::utils::errors::{execution_err, use_case_err, MappedErrors};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use tracing::{debug, info};
+
+/// The kind of change applied to a taxid of the taxonomies database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum TaxonomyChangeKind {
+    /// The taxid was merged into another taxid
+    Merged,
+
+    /// The taxid was deleted from the taxdump
+    Deleted,
+
+    /// The taxid was not found in the taxdump
+    Unknown,
+
+    /// The taxid rank is not Linnaean and non Linnaean taxonomies are dropped
+    Dropped,
+
+    /// The taxid lineage or rank changed
+    LineageChanged,
+}
+
+impl fmt::Display for TaxonomyChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaxonomyChangeKind::Merged => write!(f, "merged"),
+            TaxonomyChangeKind::Deleted => write!(f, "deleted"),
+            TaxonomyChangeKind::Unknown => write!(f, "unknown"),
+            TaxonomyChangeKind::Dropped => write!(f, "dropped"),
+            TaxonomyChangeKind::LineageChanged => write!(f, "lineage-changed"),
+        }
+    }
+}
+
+/// A changelog entry of the taxonomies database update
+struct TaxonomyChange {
+    taxid: u64,
+    kind: TaxonomyChangeKind,
+    new_taxid: Option<u64>,
+    previous_lineage: String,
+    current_lineage: Option<String>,
+}
+
+/// Update an existing taxonomies database from a new taxdump
+///
+/// Taxids listed in the `merged.dmp` file are remapped to the new taxid, and
+/// their accessions are moved to the new taxid record. Taxids listed in the
+/// `delnodes.dmp` file, or missing from the new taxdump, are removed. The
+/// lineages of the remaining taxids are rebuilt using the options stored in
+/// the existing database, and only the records whose lineage changed are
+/// replaced.
+///
+/// Every change is recorded in a `{stem}.changelog.tsv` file written beside
+/// the updated database.
+#[tracing::instrument(
+    name = "Update Taxonomy DB",
+    skip(taxonomies_database_path, taxdump_directory_path)
+)]
+pub(crate) fn update_taxonomy_database(
+    taxonomies_database_path: &Path,
+    taxdump_directory_path: &Path,
+    output_file_path: PathBuf,
+    output_format: TaxonomiesDatabaseFormat,
+) -> Result<(), MappedErrors> {
+    // ? -----------------------------------------------------------------------
+    // ? Validate arguments
+    // ? -----------------------------------------------------------------------
+
+    let del_nodes_path = taxdump_directory_path.join("delnodes.dmp");
+    let merged_path = taxdump_directory_path.join("merged.dmp");
+
+    if !del_nodes_path.is_file() {
+        return use_case_err(format!(
+            "Invalid delnodes path: {:?}",
+            del_nodes_path
+        ))
+        .as_error();
+    }
+
+    if !merged_path.is_file() {
+        return use_case_err(format!("Invalid merged path: {:?}", merged_path))
+            .as_error();
+    }
+
+    // ? -----------------------------------------------------------------------
+    // ? Load the existing database and the new taxdump
+    // ? -----------------------------------------------------------------------
+
+    let taxonomies_database =
+        TaxonomiesDatabase::open(taxonomies_database_path)?;
+    let metadata = taxonomies_database.metadata();
+
+    debug!("Loading and validating `DELETED` nodes");
+    let del_nodes = load_del_nodes_dataframe(del_nodes_path)?
+        .into_iter()
+        .collect::<HashSet<u64>>();
+
+    debug!("Loading and validating `MERGED` nodes");
+    let merged_map = load_merged_dataframe(merged_path)?;
+
+    let ranked_tax_ids = load_ranked_taxids(
+        taxdump_directory_path.join("names.dmp"),
+        taxdump_directory_path.join("nodes.dmp"),
+        taxdump_directory_path.join("taxidlineage.dmp"),
+    )?;
+
+    // ? -----------------------------------------------------------------------
+    // ? Remap, drop, and rebuild the existing taxonomies
+    // ? -----------------------------------------------------------------------
+
+    let mut taxonomies = Vec::<TaxonomyMapUnit>::new();
+    let mut positions = HashMap::<u64, usize>::new();
+    let mut changes = Vec::<TaxonomyChange>::new();
+
+    taxonomies_database.for_each(|record| {
+        let mut change = |kind, new_taxid, current_lineage| {
+            changes.push(TaxonomyChange {
+                taxid: record.taxid,
+                kind,
+                new_taxid,
+                previous_lineage: record.text_lineage.to_owned(),
+                current_lineage,
+            })
+        };
+
+        if del_nodes.contains(&record.taxid) {
+            change(TaxonomyChangeKind::Deleted, None, None);
+            return Ok(());
+        }
+
+        let new_taxid = merged_map.get(&record.taxid).copied();
+        let taxid = new_taxid.unwrap_or(record.taxid);
+
+        let ranked_tax_id = match ranked_tax_ids.get(&taxid) {
+            Some(res) => res,
+            None => {
+                change(
+                    match new_taxid {
+                        Some(_) => TaxonomyChangeKind::Merged,
+                        None => TaxonomyChangeKind::Unknown,
+                    },
+                    new_taxid,
+                    None,
+                );
+
+                return Ok(());
+            }
+        };
+
+        let mut taxonomy = match build_taxonomy_unit(
+            taxid,
+            ranked_tax_id,
+            &ranked_tax_ids,
+            record.accessions.to_owned(),
+            &metadata.ignore_taxids,
+            &metadata.replace_rank,
+            metadata.drop_non_linnaean_taxonomies,
+        ) {
+            Some(res) => res,
+            None => {
+                change(TaxonomyChangeKind::Dropped, new_taxid, None);
+                return Ok(());
+            }
+        };
+
+        if let Some(strategy) = &metadata.fill_missing_ranks {
+            fill_lineage_ranks(&mut taxonomy, strategy);
+        }
+
+        if new_taxid.is_some() {
+            change(
+                TaxonomyChangeKind::Merged,
+                new_taxid,
+                Some(taxonomy.text_lineage.to_owned()),
+            );
+        } else if taxonomy.rank != record.rank
+            || taxonomy.numeric_lineage != record.numeric_lineage
+            || taxonomy.text_lineage != record.text_lineage
+        {
+            change(
+                TaxonomyChangeKind::LineageChanged,
+                None,
+                Some(taxonomy.text_lineage.to_owned()),
+            );
+        }
+
+        //
+        // Merged taxids are kept as aliases of the new taxid, together with
+        // the aliases of previous updates.
+        //
+        taxonomy.aliases = record.aliases.to_owned();
+
+        if new_taxid.is_some() {
+            taxonomy.aliases.push(record.taxid);
+        }
+
+        //
+        // Merged taxids may point to a taxid already present in the database.
+        // In such case accessions and aliases are moved to the existing
+        // record.
+        //
+        match positions.get(&taxonomy.taxid) {
+            Some(position) => {
+                let existing = &mut taxonomies[*position];
+                existing.accessions.extend(taxonomy.accessions);
+                existing.aliases.extend(taxonomy.aliases);
+            }
+            None => {
+                positions.insert(taxonomy.taxid, taxonomies.len());
+                taxonomies.push(taxonomy);
+            }
+        }
+
+        Ok(())
+    })?;
+
+    changes.sort_by_key(|change| change.taxid);
+
+    for taxonomy in taxonomies.iter_mut() {
+        taxonomy.aliases.sort();
+        taxonomy.aliases.dedup();
+    }
+
+    for kind in [
+        TaxonomyChangeKind::Merged,
+        TaxonomyChangeKind::Deleted,
+        TaxonomyChangeKind::Unknown,
+        TaxonomyChangeKind::Dropped,
+        TaxonomyChangeKind::LineageChanged,
+    ] {
+        info!(
+            "{} taxids {kind}",
+            changes.iter().filter(|change| change.kind == kind).count()
+        );
+    }
+
+    // ? -----------------------------------------------------------------------
+    // ? Write the updated database and the changelog
+    // ? -----------------------------------------------------------------------
+
+    write_changelog(
+        &changes,
+        &sibling_output_file(&output_file_path, "changelog.tsv"),
+    )?;
+
+    write_taxonomies_database(
+        TaxonomiesMap {
+            blutils_version: env!("CARGO_PKG_VERSION").to_string(),
+            ignore_taxids: metadata.ignore_taxids.to_owned(),
+            replace_rank: metadata.replace_rank.to_owned(),
+            drop_non_linnaean_taxonomies: metadata.drop_non_linnaean_taxonomies,
+            fill_missing_ranks: metadata.fill_missing_ranks.to_owned(),
+            source_database: metadata.source_database.to_owned(),
+            taxonomies,
+        },
+        &sibling_output_file(
+            &output_file_path,
+            &format!("blutils.{}", output_format.extension()),
+        ),
+        &output_format,
+    )
+}
+
+fn write_changelog(
+    changes: &[TaxonomyChange],
+    path: &Path,
+) -> Result<(), MappedErrors> {
+    let mut writer = BufWriter::new(File::create(path).map_err(|err| {
+        execution_err(format!("Unexpected error on create changelog: {err}"))
+    })?);
+
+    writeln!(
+        writer,
+        "taxid\tchange\tnew_taxid\tprevious_lineage\tcurrent_lineage"
+    )
+    .and_then(|_| {
+        changes.iter().try_for_each(|change| {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}",
+                change.taxid,
+                change.kind,
+                change
+                    .new_taxid
+                    .map(|taxid| taxid.to_string())
+                    .unwrap_or_default(),
+                change.previous_lineage,
+                change.current_lineage.as_deref().unwrap_or_default(),
+            )
+        })
+    })
+    .and_then(|_| writer.flush())
+    .map_err(|err| {
+        execution_err(format!("Unexpected error on write changelog: {err}"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::dtos::taxonomies_map::Accession;
+    use std::fs::{create_dir_all, read_to_string, write};
+
+    fn test_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir()
+            .join("blutils-update-taxonomy-tests")
+            .join(format!("{}-{name}", std::process::id()));
+
+        create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn taxonomy(
+        taxid: u64,
+        numeric_lineage: &str,
+        text_lineage: &str,
+    ) -> TaxonomyMapUnit {
+        TaxonomyMapUnit {
+            taxid,
+            aliases: Vec::new(),
+            rank: "s".to_owned(),
+            numeric_lineage: numeric_lineage.to_owned(),
+            text_lineage: text_lineage.to_owned(),
+            accessions: vec![Accession {
+                accession: format!("NR_{taxid}.1"),
+                oid: taxid.to_string(),
+            }],
+        }
+    }
+
+    /// Write the taxdump files, one record per line with `|` separated
+    /// columns
+    fn write_taxdump(directory: &Path, files: &[(&str, &[&str])]) {
+        create_dir_all(directory).unwrap();
+
+        for (file, rows) in files {
+            write(
+                directory.join(file),
+                rows.iter()
+                    .map(|row| format!("{}\t|\n", row.replace('|', "\t|\t")))
+                    .collect::<String>(),
+            )
+            .unwrap();
+        }
+    }
+
+    /// A database built from a previous taxdump. The `1402` species is
+    /// renamed, `1410` is merged into `1423`, `1500` is merged into the
+    /// `1502` species absent from the database, `1600` is deleted, and `1700`
+    /// is missing from the new taxdump.
+    fn previous_database(directory: &Path) -> PathBuf {
+        let path = directory.join("previous.blutils.json");
+
+        write_taxonomies_database(
+            TaxonomiesMap {
+                blutils_version: "8.3.1".to_owned(),
+                ignore_taxids: None,
+                replace_rank: None,
+                drop_non_linnaean_taxonomies: Some(false),
+                fill_missing_ranks: None,
+                source_database: "mock-16S".to_owned(),
+                taxonomies: vec![
+                    taxonomy(
+                        1402,
+                        "d__2;p__1239;g__1386;s__1402",
+                        "d__bacteria;p__bacillota;g__bacillus;s__bacillus-licheniformis",
+                    ),
+                    taxonomy(
+                        1410,
+                        "d__2;p__1239;g__1386;s__1410",
+                        "d__bacteria;p__bacillota;g__bacillus;s__bacillus-amyloliquefaciens",
+                    ),
+                    taxonomy(
+                        1423,
+                        "d__2;p__1239;g__1386;s__1423",
+                        "d__bacteria;p__bacillota;g__bacillus;s__bacillus-subtilis",
+                    ),
+                    taxonomy(
+                        1500,
+                        "d__2;p__1239;g__1386;s__1500",
+                        "d__bacteria;p__bacillota;g__bacillus;s__bacillus-sp-1500",
+                    ),
+                    taxonomy(
+                        1600,
+                        "d__2;p__1239;g__1386;s__1600",
+                        "d__bacteria;p__bacillota;g__bacillus;s__bacillus-sp-1600",
+                    ),
+                    taxonomy(
+                        1700,
+                        "d__2;p__1239;g__1386;s__1700",
+                        "d__bacteria;p__bacillota;g__bacillus;s__bacillus-sp-1700",
+                    ),
+                ],
+            },
+            &path,
+            &TaxonomiesDatabaseFormat::Json,
+        )
+        .unwrap();
+
+        path
+    }
+
+    #[test]
+    fn taxonomies_are_updated_from_the_new_taxdump() {
+        let directory = test_directory("update");
+        let taxdump = directory.join("taxdump");
+
+        write_taxdump(
+            &taxdump,
+            &[
+                (
+                    "names.dmp",
+                    &[
+                        "2|Bacteria||scientific name",
+                        "1239|Bacillota||scientific name",
+                        "1386|Bacillus||scientific name",
+                        "1402|Bacillus paralicheniformis||scientific name",
+                        "1423|Bacillus subtilis||scientific name",
+                        "1502|Bacillus velezensis||scientific name",
+                    ],
+                ),
+                (
+                    "nodes.dmp",
+                    &[
+                        "2|1|domain",
+                        "1239|2|phylum",
+                        "1386|1239|genus",
+                        "1402|1386|species",
+                        "1423|1386|species",
+                        "1502|1386|species",
+                    ],
+                ),
+                (
+                    "taxidlineage.dmp",
+                    &[
+                        "2|",
+                        "1239|2",
+                        "1386|2 1239",
+                        "1402|2 1239 1386",
+                        "1423|2 1239 1386",
+                        "1502|2 1239 1386",
+                    ],
+                ),
+                ("delnodes.dmp", &["1600"]),
+                ("merged.dmp", &["1410|1423", "1500|1502"]),
+            ],
+        );
+
+        update_taxonomy_database(
+            &previous_database(&directory),
+            &taxdump,
+            directory.join("updated"),
+            TaxonomiesDatabaseFormat::Json,
+        )
+        .unwrap();
+
+        // ? -------------------------------------------------------------------
+        // ? Check the updated database
+        // ? -------------------------------------------------------------------
+
+        let database =
+            TaxonomiesDatabase::open(&directory.join("updated.blutils.json"))
+                .unwrap();
+
+        let mut taxids = Vec::<u64>::new();
+        database
+            .for_each(|record| {
+                taxids.push(record.taxid);
+                Ok(())
+            })
+            .unwrap();
+
+        taxids.sort();
+        assert_eq!(taxids, vec![1402, 1423, 1502]);
+
+        let renamed = database.get(1402).unwrap().unwrap();
+        assert_eq!(
+            renamed.text_lineage,
+            "d__bacteria;p__bacillota;g__bacillus;s__bacillus-paralicheniformis"
+        );
+
+        //
+        // Accessions of a taxid merged into an existing record are moved to
+        // the existing record.
+        //
+        let subtilis = database.get(1410).unwrap().unwrap();
+        assert_eq!(subtilis.taxid, 1423);
+        assert_eq!(subtilis.aliases, vec![1410]);
+        assert_eq!(
+            subtilis
+                .accessions
+                .iter()
+                .map(|accession| accession.accession.as_str())
+                .collect::<Vec<_>>(),
+            vec!["NR_1410.1", "NR_1423.1"]
+        );
+
+        let velezensis = database.get(1500).unwrap().unwrap();
+        assert_eq!(velezensis.taxid, 1502);
+        assert_eq!(velezensis.aliases, vec![1500]);
+        assert_eq!(velezensis.numeric_lineage, "d__2;p__1239;g__1386;s__1502");
+
+        assert!(database.get(1600).unwrap().is_none());
+        assert!(database.get(1700).unwrap().is_none());
+
+        // ? -------------------------------------------------------------------
+        // ? Check the changelog
+        // ? -------------------------------------------------------------------
+
+        let changelog =
+            read_to_string(directory.join("updated.changelog.tsv")).unwrap();
+
+        assert_eq!(
+            changelog.lines().collect::<Vec<_>>(),
+            vec![
+                "taxid\tchange\tnew_taxid\tprevious_lineage\tcurrent_lineage",
+                "1402\tlineage-changed\t\td__bacteria;p__bacillota;g__bacillus;s__bacillus-licheniformis\td__bacteria;p__bacillota;g__bacillus;s__bacillus-paralicheniformis",
+                "1410\tmerged\t1423\td__bacteria;p__bacillota;g__bacillus;s__bacillus-amyloliquefaciens\td__bacteria;p__bacillota;g__bacillus;s__bacillus-subtilis",
+                "1500\tmerged\t1502\td__bacteria;p__bacillota;g__bacillus;s__bacillus-sp-1500\td__bacteria;p__bacillota;g__bacillus;s__bacillus-velezensis",
+                "1600\tdeleted\t\td__bacteria;p__bacillota;g__bacillus;s__bacillus-sp-1600\t",
+                "1700\tunknown\t\td__bacteria;p__bacillota;g__bacillus;s__bacillus-sp-1700\t",
+            ]
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use super::shared::{create_writer, stream_blast_sequences, write_error};
+use crate::domain::dtos::{
+    linnaean_ranks::LinnaeanRank, taxonomies_database::TaxonomiesDatabase,
+};
+
+use mycelium_base::utils::errors::MappedErrors;
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+use tracing::{info, warn};
+
+/// The reference database formats of third party taxonomic classifiers
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClassifierDatabaseFormat {
+    /// vsearch/usearch SINTAX: `>ID;tax=d:name,p:name,...;`
+    Sintax,
+
+    /// usearch UTAX: `>ID;tax=d:name,p:name,...;`
+    Utax,
+
+    /// DADA2 `assignTaxonomy`: `>d__name;p__name;...;`
+    Dada2,
+
+    /// mothur `classify.seqs`: a FASTA file with `>ID` headers and a
+    /// taxonomy file with `ID\tname;name;...;` lines
+    Mothur,
+}
+
+/// Build a taxonomic classifier reference database from the Blutils database
+///
+/// Sequences are streamed from the blast database and written with the
+/// lineage of its accession in the taxonomies database, following the header
+/// conventions of the target classifier. Only Linnaean ranks are exported.
+/// Sequences not found in the taxonomies database are skipped.
+///
+/// Sequences are written to `output_file_path` with the `fasta` extension.
+/// The mothur taxonomy file is written with the `tax` extension.
+#[tracing::instrument(
+    name = "Build classifier DB from Blutils DB",
+    skip(taxonomies_database_path, blast_database_path, output_file_path)
+)]
+pub fn build_classifier_db_from_blutils_db(
+    taxonomies_database_path: &Path,
+    blast_database_path: &PathBuf,
+    output_file_path: PathBuf,
+    format: ClassifierDatabaseFormat,
+    use_taxid: Option<bool>,
+) -> Result<(), MappedErrors> {
+    // ? -----------------------------------------------------------------------
+    // ? Index the lineages of the taxonomies database by accession
+    // ? -----------------------------------------------------------------------
+
+    let taxonomies_database =
+        TaxonomiesDatabase::open(taxonomies_database_path)?;
+
+    //
+    // Lineages are formatted once by taxid and shared by the accessions.
+    //
+    let mut lineages = Vec::<(u64, String)>::new();
+    let mut accessions = HashMap::<String, usize>::new();
+
+    taxonomies_database.for_each(|record| {
+        let lineage = match use_taxid {
+            Some(true) => &record.numeric_lineage,
+            _ => &record.text_lineage,
+        };
+
+        lineages.push((record.taxid, format_lineage(lineage, &format)));
+
+        for accession in &record.accessions {
+            accessions
+                .insert(accession.accession.to_owned(), lineages.len() - 1);
+        }
+
+        Ok(())
+    })?;
+
+    // ? -----------------------------------------------------------------------
+    // ? Write the output files
+    // ? -----------------------------------------------------------------------
+
+    let mut sequences_file = output_file_path.to_owned();
+    sequences_file.set_extension("fasta");
+
+    let mut sequences_writer = create_writer(&sequences_file)?;
+
+    let mut taxonomies_writer = match format {
+        ClassifierDatabaseFormat::Mothur => {
+            let mut taxonomies_file = output_file_path.to_owned();
+            taxonomies_file.set_extension("tax");
+            let writer = create_writer(&taxonomies_file)?;
+            Some((taxonomies_file, writer))
+        }
+        _ => None,
+    };
+
+    let (mut written, mut skipped) = (0, 0);
+
+    stream_blast_sequences(blast_database_path, |item| {
+        let (taxid, lineage) = match accessions.get(item.accession) {
+            Some(position) => &lineages[*position],
+            None => {
+                skipped += 1;
+                return Ok(());
+            }
+        };
+
+        let identifier = format!("{taxid}-{}-{}", item.oid, item.accession);
+
+        let header = match format {
+            ClassifierDatabaseFormat::Sintax
+            | ClassifierDatabaseFormat::Utax => {
+                format!("{identifier};{lineage}")
+            }
+            ClassifierDatabaseFormat::Dada2 => lineage.to_owned(),
+            ClassifierDatabaseFormat::Mothur => identifier.to_owned(),
+        };
+
+        writeln!(sequences_writer, ">{header}\n{}", item.sequence)
+            .map_err(|err| write_error(&sequences_file, err))?;
+
+        if let Some((taxonomies_file, writer)) = taxonomies_writer.as_mut() {
+            writeln!(writer, "{identifier}\t{lineage}")
+                .map_err(|err| write_error(taxonomies_file, err))?;
+        }
+
+        written += 1;
+
+        Ok(())
+    })?;
+
+    sequences_writer
+        .flush()
+        .map_err(|err| write_error(&sequences_file, err))?;
+
+    if let Some((taxonomies_file, mut writer)) = taxonomies_writer {
+        writer
+            .flush()
+            .map_err(|err| write_error(&taxonomies_file, err))?;
+    }
+
+    if skipped > 0 {
+        warn!(
+            "{skipped} sequences not found in the taxonomies database were skipped"
+        );
+    }
+
+    info!("{written} sequences written to {:?}", sequences_file);
+
+    Ok(())
+}
+
+/// Format a Blutils lineage following the classifier conventions
+///
+/// Elements of non Linnaean ranks are removed.
+fn format_lineage(lineage: &str, format: &ClassifierDatabaseFormat) -> String {
+    let elements = lineage
+        .split(';')
+        .filter_map(|element| element.split_once("__"))
+        .filter_map(|(rank, name)| match rank.parse::<LinnaeanRank>() {
+            Ok(LinnaeanRank::Other(_)) | Ok(LinnaeanRank::Undefined) => None,
+            Ok(rank) => Some((rank, name)),
+            Err(_) => None,
+        })
+        .collect::<Vec<(LinnaeanRank, &str)>>();
+
+    match format {
+        ClassifierDatabaseFormat::Sintax | ClassifierDatabaseFormat::Utax => {
+            format!(
+                "tax={};",
+                elements
+                    .iter()
+                    .map(|(rank, name)| format!("{rank}:{name}"))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            )
+        }
+        ClassifierDatabaseFormat::Dada2 => elements
+            .iter()
+            .map(|(rank, name)| format!("{rank}__{name};"))
+            .collect(),
+        ClassifierDatabaseFormat::Mothur => elements
+            .iter()
+            .map(|(_, name)| format!("{name};"))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINEAGE: &str = "d__bacteria;clade__terrabacteria-group;p__bacillota;c__bacilli;o__bacillales;f__bacillaceae;g__bacillus;s__bacillus-subtilis;strain__bacillus-subtilis-168";
+
+    #[test]
+    fn sintax_and_utax_lineages_are_formatted() {
+        for format in [
+            ClassifierDatabaseFormat::Sintax,
+            ClassifierDatabaseFormat::Utax,
+        ] {
+            assert_eq!(
+                format_lineage(LINEAGE, &format),
+                "tax=d:bacteria,p:bacillota,c:bacilli,o:bacillales,f:bacillaceae,g:bacillus,s:bacillus-subtilis;"
+            );
+        }
+    }
+
+    #[test]
+    fn dada2_lineages_are_formatted() {
+        assert_eq!(
+            format_lineage(LINEAGE, &ClassifierDatabaseFormat::Dada2),
+            "d__bacteria;p__bacillota;c__bacilli;o__bacillales;f__bacillaceae;g__bacillus;s__bacillus-subtilis;"
+        );
+    }
+
+    #[test]
+    fn mothur_lineages_are_formatted() {
+        assert_eq!(
+            format_lineage(LINEAGE, &ClassifierDatabaseFormat::Mothur),
+            "bacteria;bacillota;bacilli;bacillales;bacillaceae;bacillus;bacillus-subtilis;"
+        );
+    }
+
+    #[test]
+    fn numeric_lineages_are_formatted() {
+        assert_eq!(
+            format_lineage(
+                "d__2;no-rank__1783272;p__1239;g__1386;s__1423",
+                &ClassifierDatabaseFormat::Sintax
+            ),
+            "tax=d:2,p:1239,g:1386,s:1423;"
+        );
+    }
+}
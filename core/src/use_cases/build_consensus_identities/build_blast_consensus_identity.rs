@@ -6,6 +6,8 @@ use crate::domain::dtos::{
     taxonomy_bean::TaxonomyBean,
 };
 
+use mycelium_base::utils::errors::{use_case_err, MappedErrors};
+
 pub(super) fn build_blast_consensus_identity(
     query: String,
     mut bean: TaxonomyBean,
@@ -15,7 +17,7 @@ pub(super) fn build_blast_consensus_identity(
     taxonomy: Vec<TaxonomyBean>,
     interpolated_taxonomy: InterpolatedIdentity,
     consensus_beans: Option<Vec<ConsensusBean>>,
-) -> QueryWithConsensus {
+) -> Result<QueryWithConsensus, MappedErrors> {
     //
     // Update the rank of the bean according to the interpolated taxonomy.
     //
@@ -94,12 +96,17 @@ pub(super) fn build_blast_consensus_identity(
                 .join(";"),
         )
     } else {
-        panic!("No taxonomy found for bean at index: {}", bean_index);
+        return use_case_err(format!(
+            "No taxonomy found for bean at index: {bean_index}"
+        ))
+        .as_error();
     }
 
-    QueryWithConsensus {
+    Ok(QueryWithConsensus {
         query,
         taxon: Some(bean),
         run_id: None,
-    }
+        abundance: None,
+        error: None,
+    })
 }
@@ -11,7 +11,7 @@ use crate::domain::dtos::{
     taxonomy_bean::{Taxonomy, TaxonomyBean},
 };
 
-use mycelium_base::utils::errors::MappedErrors;
+use mycelium_base::utils::errors::{use_case_err, MappedErrors};
 use std::collections::HashSet;
 
 /// Find the consensus among Blast results with multiple output.
@@ -36,11 +36,15 @@ pub(super) fn find_multi_taxa_consensus(
     //
     // ? -----------------------------------------------------------------------
 
-    let mut sorted_records = records.to_owned();
+    let mut parsed_records = records
+        .into_iter()
+        .map(|record| {
+            force_parsed_taxonomy(record.taxonomy.to_owned())
+                .map(|taxonomy| (taxonomy, record))
+        })
+        .collect::<Result<Vec<(Vec<TaxonomyBean>, BlastResultRow)>, _>>()?;
 
-    sorted_records.sort_by(|a, b| {
-        let a_taxonomy = force_parsed_taxonomy(a.taxonomy.to_owned());
-        let b_taxonomy = force_parsed_taxonomy(b.taxonomy.to_owned());
+    parsed_records.sort_by(|(a_taxonomy, a), (b_taxonomy, b)| {
         a_taxonomy
             .len()
             .cmp(&b_taxonomy.len())
@@ -53,15 +57,20 @@ pub(super) fn find_multi_taxa_consensus(
             .then(a.subject_accession.cmp(&b.subject_accession))
     });
 
+    let (sorted_taxonomies, sorted_records): (
+        Vec<Vec<TaxonomyBean>>,
+        Vec<BlastResultRow>,
+    ) = parsed_records.into_iter().unzip();
+
     //
     // The reference taxonomy is the longest or shortest taxonomy vector, given
     // the selected strategy.
     //
     let reference_taxonomy = match match strategy {
-        ConsensusStrategy::Cautious => sorted_records.first(),
-        ConsensusStrategy::Relaxed => sorted_records.last(),
+        ConsensusStrategy::Cautious => sorted_taxonomies.first(),
+        ConsensusStrategy::Relaxed => sorted_taxonomies.last(),
     } {
-        Some(reference) => force_parsed_taxonomy(reference.taxonomy.to_owned()),
+        Some(reference) => reference.to_owned(),
         None => {
             return Ok(ConsensusResult::NoConsensusFound(no_consensus_option))
         }
@@ -75,19 +84,26 @@ pub(super) fn find_multi_taxa_consensus(
     //
     // ? -----------------------------------------------------------------------
 
-    let sorted_taxonomies = sorted_records
-        .iter()
-        .map(|i| force_parsed_taxonomy(i.taxonomy.to_owned()))
-        .collect::<Vec<Vec<TaxonomyBean>>>();
-
     let lowest_taxonomy_of_higher_rank = {
-        let mut rank_taxonomies = sorted_taxonomies.first().unwrap().to_owned();
+        let mut rank_taxonomies =
+            sorted_taxonomies.first().cloned().unwrap_or_default();
 
         rank_taxonomies.sort_by(|a, b| {
-            a.perc_identity.partial_cmp(&b.perc_identity).unwrap()
+            a.perc_identity
+                .partial_cmp(&b.perc_identity)
+                .unwrap_or(std::cmp::Ordering::Equal)
         });
 
-        rank_taxonomies.first().unwrap().to_owned()
+        match rank_taxonomies.first() {
+            Some(bean) => bean.to_owned(),
+            None => {
+                return use_case_err(format!(
+                    "Empty taxonomy found for query: {}",
+                    no_consensus_option.query
+                ))
+                .as_error()
+            }
+        }
     };
 
     // ? -----------------------------------------------------------------------
@@ -98,6 +114,8 @@ pub(super) fn find_multi_taxa_consensus(
         query: no_consensus_option.query.to_owned(),
         taxon: Some(lowest_taxonomy_of_higher_rank),
         run_id: None,
+        abundance: None,
+        error: None,
     };
 
     // ? -----------------------------------------------------------------------
@@ -121,9 +139,10 @@ pub(super) fn find_multi_taxa_consensus(
 
     if interpolated_identities.interpolation().len() != reference_taxonomy.len()
     {
-        panic!(
-            "Interpolated identities length is not equal to reference taxonomy length"
-        );
+        return use_case_err(
+            "Interpolated identities length is not equal to reference taxonomy length",
+        )
+        .as_error();
     }
 
     // ? -----------------------------------------------------------------------
@@ -196,7 +215,7 @@ pub(super) fn find_multi_taxa_consensus(
                 reference_taxonomy.to_owned(),
                 interpolated_identities.to_owned(),
                 Some(consensus_beans),
-            );
+            )?;
 
             break;
         }
@@ -210,7 +229,7 @@ pub(super) fn find_multi_taxa_consensus(
             reference_taxonomy.to_owned(),
             interpolated_identities.to_owned(),
             Some(consensus_beans),
-        )
+        )?
     }
 
     Ok(ConsensusResult::ConsensusFound(final_taxon))
@@ -11,7 +11,7 @@ use crate::domain::dtos::{
     taxonomy_bean::{Taxonomy, TaxonomyBean},
 };
 
-use mycelium_base::utils::errors::MappedErrors;
+use mycelium_base::utils::errors::{use_case_err, MappedErrors};
 use std::collections::HashMap;
 
 pub(super) fn find_single_query_consensus(
@@ -45,23 +45,16 @@ pub(super) fn find_single_query_consensus(
 
     let no_consensus = QueryWithoutConsensus {
         query: query.to_owned(),
+        abundance: None,
     };
 
     for score in sorted_keys.to_owned().into_iter() {
         let bit_score_matches = result
             .to_owned()
             .into_iter()
-            .filter_map(|i| {
-                if i.bit_score != score {
-                    None
-                } else {
-                    match i.to_owned().parse_taxonomy() {
-                        Err(err) => panic!("{err}"),
-                        Ok(res) => Some(res),
-                    }
-                }
-            })
-            .collect::<Vec<BlastResultRow>>();
+            .filter(|i| i.bit_score == score)
+            .map(|mut i| i.parse_taxonomy())
+            .collect::<Result<Vec<BlastResultRow>, MappedErrors>>()?;
         //
         // Early return case no results found.
         //
@@ -86,7 +79,7 @@ pub(super) fn find_single_query_consensus(
             // is not already parsed.
             //
             let taxonomies =
-                force_parsed_taxonomy(target_blast_match.taxonomy.to_owned());
+                force_parsed_taxonomy(target_blast_match.taxonomy.to_owned())?;
             //
             // Generate interpolated identities for the taxon.
             //
@@ -112,10 +105,13 @@ pub(super) fn find_single_query_consensus(
             //
             let target_bean = match identity_adjusted_taxonomy.last() {
                 Some(bean) => bean.to_owned(),
-                None => panic!(
-                    "No taxonomy found for result: {:?}",
-                    target_blast_match.subject_accession
-                ),
+                None => {
+                    return use_case_err(format!(
+                        "No taxonomy found for result: {}",
+                        target_blast_match.subject_accession
+                    ))
+                    .as_error()
+                }
             };
             //
             // Initialize the consensus bean.
@@ -146,22 +142,21 @@ pub(super) fn find_single_query_consensus(
                     ..target_bean
                 }),
                 run_id: None,
+                abundance: None,
+                error: None,
             }));
         }
         //
         // Fetch the lower taxonomic rank case more than one record returned.
         //
         if bit_score_matches.len() > 1 {
-            match find_multi_taxa_consensus(
+            return find_multi_taxa_consensus(
                 bit_score_matches,
                 taxon,
                 no_consensus.clone(),
                 strategy.to_owned(),
                 custom_taxon_values,
-            ) {
-                Err(err) => panic!("{err}"),
-                Ok(res) => return Ok(res),
-            };
+            );
         }
     }
 
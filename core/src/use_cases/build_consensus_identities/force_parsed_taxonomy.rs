@@ -1,10 +1,15 @@
 use crate::domain::dtos::taxonomy_bean::{Taxonomy, TaxonomyBean};
 
-pub(super) fn force_parsed_taxonomy(taxonomy: Taxonomy) -> Vec<TaxonomyBean> {
+use mycelium_base::utils::errors::{use_case_err, MappedErrors};
+
+pub(super) fn force_parsed_taxonomy(
+    taxonomy: Taxonomy,
+) -> Result<Vec<TaxonomyBean>, MappedErrors> {
     match taxonomy {
-        Taxonomy::Literal(_) => {
-            panic!("Invalid format taxonomic field.")
+        Taxonomy::Literal(literal) => {
+            use_case_err(format!("Invalid format taxonomic field: {literal}"))
+                .as_error()
         }
-        Taxonomy::Parsed(res) => res,
+        Taxonomy::Parsed(res) => Ok(res),
     }
 }
@@ -2,31 +2,47 @@ mod build_blast_consensus_identity;
 mod find_multi_taxa_consensus;
 mod find_single_query_consensus;
 mod force_parsed_taxonomy;
+mod resolve_taxids_by_accession;
+mod stream_consensus_identities;
 
 use build_blast_consensus_identity::*;
 use find_multi_taxa_consensus::*;
 use find_single_query_consensus::*;
 use force_parsed_taxonomy::*;
 use polars_core::{
-    export::num::ToPrimitive, prelude::NamedFrom, series::Series,
+    export::num::{NumCast, ToPrimitive},
+    prelude::{AnyValue, NamedFrom},
+    series::Series,
 };
+use resolve_taxids_by_accession::*;
+
+pub use stream_consensus_identities::*;
 
 use crate::domain::dtos::{
     blast_result::{BlastQueryResult, BlastResultRow},
-    consensus_result::{ConsensusResult, QueryWithoutConsensus},
+    blast_tabular_format::{BlastField, BlastTabularFormat},
+    consensus_result::{
+        ConsensusResult, QueryWithFailure, QueryWithoutConsensus,
+    },
     consensus_strategy::ConsensusStrategy,
+    file_or_stdin::Sequence,
     parallel_blast_output::ParallelBlastOutput,
     taxon::{CustomTaxon, Taxon},
-    taxonomies_map::TaxonomiesMap,
+    taxonomies_database::TaxonomiesDatabase,
     taxonomy_bean::Taxonomy,
 };
 
 use mycelium_base::utils::errors::{execution_err, use_case_err, MappedErrors};
 use polars::prelude::{CsvReader, DataFrame, DataType, Schema};
-use polars_io::SerReader;
+use polars_io::{mmap::MmapBytesReader, SerReader};
 use polars_lazy::prelude::*;
 use rayon::prelude::{IntoParallelIterator, ParallelIterator};
-use std::{collections::HashMap, fs::read_to_string, path::Path, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    path::Path,
+    sync::Arc,
+};
 use tracing::{error, info, warn};
 
 /// BUild consensus identities from BlastN output.
@@ -35,7 +51,14 @@ use tracing::{error, info, warn};
 /// consensus taxonomies based on the `subjects` frequencies and concordance.
 #[tracing::instrument(
     name = "Build consensus identities from Blast output",
-    skip(blast_output, taxonomies_file, taxon, strategy, use_taxid)
+    skip(
+        blast_output,
+        taxonomies_file,
+        taxon,
+        strategy,
+        use_taxid,
+        accessions_file
+    )
 )]
 pub fn build_consensus_identities(
     blast_output: ParallelBlastOutput,
@@ -44,6 +67,7 @@ pub fn build_consensus_identities(
     strategy: ConsensusStrategy,
     use_taxid: Option<bool>,
     custom_taxon_values: Option<CustomTaxon>,
+    accessions_file: Option<&Path>,
 ) -> Result<Vec<ConsensusResult>, MappedErrors> {
     // ? -----------------------------------------------------------------------
     // ? Load blast output as lazy
@@ -51,19 +75,51 @@ pub fn build_consensus_identities(
 
     info!("Loading Blast results");
 
-    let blast_output_df = get_results_dataframe(&blast_output.output_file)?;
+    let blast_output_file =
+        File::open(&blast_output.output_file).map_err(|err| {
+            error!("Unexpected error detected on read `blast_output`: {}", err);
+            execution_err(String::from(
+                "Unexpected error occurred on load table.",
+            ))
+        })?;
+
+    let blast_output_df =
+        get_results_dataframe(blast_output_file, &blast_output.columns)?;
 
     info!("Blast results loaded");
 
     // ? -----------------------------------------------------------------------
-    // ? Load taxonomies as lazy
+    // ? Open taxonomies database
     // ? -----------------------------------------------------------------------
 
     info!("Loading Blutils taxonomies");
 
-    let taxonomies_df = get_taxonomies_dataframe(taxonomies_file, use_taxid)?;
+    let taxonomies_database = TaxonomiesDatabase::open(taxonomies_file)?;
+
+    info!(
+        "Blutils taxonomies loaded (source database: {})",
+        taxonomies_database.metadata().source_database
+    );
+
+    // ? -----------------------------------------------------------------------
+    // ? Resolve missing taxids by subject accession
+    // ? -----------------------------------------------------------------------
+
+    let blast_output_df = resolve_taxids_by_accession(
+        blast_output_df,
+        &taxonomies_database,
+        accessions_file,
+    )?;
 
-    info!("Blutils taxonomies loaded");
+    // ? -----------------------------------------------------------------------
+    // ? Lookup taxonomies of the blast subjects
+    // ? -----------------------------------------------------------------------
+
+    let taxonomies_df = get_taxonomies_dataframe(
+        &blast_output_df,
+        &taxonomies_database,
+        use_taxid,
+    )?;
 
     // ? -----------------------------------------------------------------------
     // ? Merge files as lazy
@@ -94,6 +150,7 @@ pub fn build_consensus_identities(
                 remaining_query_results.push(BlastQueryResult {
                     query: header,
                     results: None,
+                    failure: None,
                 });
             };
         });
@@ -101,48 +158,142 @@ pub fn build_consensus_identities(
 
     query_results.append(&mut remaining_query_results);
 
-    query_results
+    //
+    // Abundances are collected from the input sequences headers when
+    // available. Otherwise, try to parse it from the blast query identifier.
+    //
+    let abundances = blast_output.abundances.unwrap_or_default();
+    let failures = blast_output.failures.unwrap_or_default();
+
+    let consensus_results = query_results
         .into_par_iter()
         .map(|result| {
-            if result.results.to_owned().is_none() {
-                return Ok(ConsensusResult::NoConsensusFound(
-                    QueryWithoutConsensus {
-                        query: result.query,
-                    },
-                ));
+            build_query_consensus(
+                result,
+                &abundances,
+                &failures,
+                &taxon,
+                &strategy,
+                &custom_taxon_values,
+            )
+        })
+        .collect::<Vec<ConsensusResult>>();
+
+    Ok(consensus_results)
+}
+
+/// Build the consensus result of a single query
+///
+/// Abundances are collected from the input sequences headers when available.
+/// Otherwise, try to parse it from the blast query identifier.
+fn build_query_consensus(
+    result: BlastQueryResult,
+    abundances: &HashMap<String, u64>,
+    failures: &HashMap<String, String>,
+    taxon: &Taxon,
+    strategy: &ConsensusStrategy,
+    custom_taxon_values: &Option<CustomTaxon>,
+) -> ConsensusResult {
+    let abundance = match abundances.get(&result.query) {
+        Some(abundance) => Some(*abundance),
+        None => Sequence::parse_abundance(&result.query),
+    };
+
+    //
+    // Queries with malformed blast result rows are reported as failed.
+    //
+    if let Some(reason) = result.failure {
+        return ConsensusResult::Failed(QueryWithFailure {
+            query: result.query,
+            reason,
+            abundance,
+        });
+    }
+
+    let results = match result.results {
+        Some(results) => results,
+        None => {
+            //
+            // Queries without results given a blast failure are reported as
+            // failed.
+            //
+            if let Some(reason) = failures.get(&result.query) {
+                return ConsensusResult::Failed(QueryWithFailure {
+                    query: result.query,
+                    reason: reason.to_owned(),
+                    abundance,
+                });
             }
 
-            match find_single_query_consensus(
+            return ConsensusResult::NoConsensusFound(QueryWithoutConsensus {
+                query: result.query,
+                abundance,
+            });
+        }
+    };
+
+    //
+    // Errors of a single query should not interrupt the consensus generation
+    // of the remaining ones.
+    //
+    match find_single_query_consensus(
+        result.query.to_owned(),
+        results,
+        taxon.to_owned(),
+        strategy.to_owned(),
+        custom_taxon_values.to_owned(),
+    ) {
+        Err(err) => {
+            warn!(
+                "Unexpected error on build consensus of query {}: {}",
                 result.query,
-                result.results.unwrap(),
-                taxon.to_owned(),
-                strategy.to_owned(),
-                custom_taxon_values.to_owned(),
-            ) {
-                Err(err) => {
-                    panic!("Unexpected error on parse blast results: {err}")
-                }
-                Ok(res) => Ok(res),
-            }
-        })
-        .collect()
+                err.msg()
+            );
+
+            ConsensusResult::Failed(QueryWithFailure {
+                query: result.query,
+                reason: err.msg(),
+                abundance,
+            })
+        }
+        Ok(res) => res.with_abundance(abundance),
+    }
 }
 
 /// Group results by query
 ///
 /// Each query results should be grouped into a `BlastQueryResult` struct.
+/// Rows with malformed values are discarded, and their queries are marked as
+/// failed instead of interrupting the remaining queries.
 fn fold_results_by_query(
     joined_df: LazyFrame,
 ) -> Result<Vec<BlastQueryResult>, MappedErrors> {
-    let mut binding = joined_df.collect().unwrap();
+    let mut binding = joined_df.collect().map_err(|err| {
+        execution_err(format!(
+            "Unexpected error detected on join blast results: {err}"
+        ))
+    })?;
+
     let joined_df_chunked = binding.as_single_chunk_par();
 
-    let mut iters = joined_df_chunked
+    //
+    // Columns are collected by name in the same order of the `BlastField`
+    // named variants, followed by the joined taxonomy column.
+    //
+    let mut iters = BlastField::NAMED
         .iter()
-        .map(|s| s.iter())
-        .collect::<Vec<_>>();
+        .map(|field| field.column_name())
+        .chain(["taxonomy"])
+        .map(|name| joined_df_chunked.column(name).map(|s| s.iter()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| {
+            execution_err(format!(
+                "Unexpected error detected on collect blast results: {err}"
+            ))
+        })?;
 
     let mut mapped_results = HashMap::<String, Vec<BlastResultRow>>::new();
+    let mut failures = HashMap::<String, String>::new();
 
     for _ in 0..joined_df_chunked.height() {
         let mut counter = 0;
@@ -161,9 +312,12 @@ fn fold_results_by_query(
         let mut e_value: f64 = 0.0;
         let mut bit_score: i64 = 0;
         let mut taxonomy: String = String::new();
+        let mut row_error: Option<String> = None;
 
         for iter in &mut iters {
-            let value = iter.next().expect("Not enough rows to iterate.");
+            let value = iter.next().ok_or(execution_err(
+                "Not enough rows to iterate over blast results",
+            ))?;
 
             match counter {
                 0 => query = value.to_owned().to_string().replace("\"", ""),
@@ -171,17 +325,17 @@ fn fold_results_by_query(
                     subject_accession =
                         value.to_owned().to_string().replace("\"", "")
                 }
-                2 => subject_taxid = value.try_extract().unwrap(),
-                3 => perc_identity = value.try_extract().unwrap(),
-                4 => align_length = value.try_extract().unwrap(),
-                5 => mismatches = value.try_extract().unwrap(),
-                6 => gap_openings = value.try_extract().unwrap(),
-                7 => q_start = value.try_extract().unwrap(),
-                8 => q_end = value.try_extract().unwrap(),
-                9 => s_start = value.try_extract().unwrap(),
-                10 => s_end = value.try_extract().unwrap(),
-                11 => e_value = value.try_extract().unwrap(),
-                12 => bit_score = value.try_extract().unwrap(),
+                2 => extract_into(&value, &mut subject_taxid, &mut row_error),
+                3 => extract_into(&value, &mut perc_identity, &mut row_error),
+                4 => extract_into(&value, &mut align_length, &mut row_error),
+                5 => extract_into(&value, &mut mismatches, &mut row_error),
+                6 => extract_into(&value, &mut gap_openings, &mut row_error),
+                7 => extract_into(&value, &mut q_start, &mut row_error),
+                8 => extract_into(&value, &mut q_end, &mut row_error),
+                9 => extract_into(&value, &mut s_start, &mut row_error),
+                10 => extract_into(&value, &mut s_end, &mut row_error),
+                11 => extract_into(&value, &mut e_value, &mut row_error),
+                12 => extract_into(&value, &mut bit_score, &mut row_error),
                 13 => taxonomy = value.to_owned().to_string().replace("\"", ""),
                 _ => warn!("Unmapped value: {:?}", value),
             };
@@ -189,6 +343,13 @@ fn fold_results_by_query(
             counter = counter + 1;
         }
 
+        if let Some(reason) = row_error {
+            warn!("Malformed blast result of query {query}: {reason}");
+            failures.entry(query.to_owned()).or_insert(reason);
+            mapped_results.entry(query).or_default();
+            continue;
+        }
+
         mapped_results.entry(query).or_insert_with(Vec::new).push(
             BlastResultRow {
                 subject_accession,
@@ -210,59 +371,195 @@ fn fold_results_by_query(
 
     Ok(mapped_results
         .into_iter()
-        .map(|(k, v)| BlastQueryResult {
-            query: k,
-            results: match v.len() {
-                0 => None,
-                _ => Some(v),
-            },
+        .map(|(k, v)| {
+            let failure = failures.remove(&k);
+
+            BlastQueryResult {
+                query: k,
+                results: match (v.len(), &failure) {
+                    (0, _) | (_, Some(_)) => None,
+                    _ => Some(v),
+                },
+                failure,
+            }
         })
         .collect::<Vec<BlastQueryResult>>())
 }
 
-/// Load BlastN output dataframe.
+/// Extract a numeric value from a blast results cell
 ///
-/// The results dataframe is a default tabular option of the Blast results.
-fn get_results_dataframe(path: &Path) -> Result<DataFrame, MappedErrors> {
-    let column_definitions = vec![
-        ("query".to_string(), DataType::String),
-        ("subject_accession".to_string(), DataType::String),
-        ("subject_taxid".to_string(), DataType::Int64),
-        ("perc_identity".to_string(), DataType::Float64),
-        ("align_length".to_string(), DataType::Int64),
-        ("mismatches".to_string(), DataType::Int64),
-        ("gap_openings".to_string(), DataType::Int64),
-        ("q_start".to_string(), DataType::Int64),
-        ("q_end".to_string(), DataType::Int64),
-        ("s_start".to_string(), DataType::Int64),
-        ("s_end".to_string(), DataType::Int64),
-        ("e_value".to_string(), DataType::Float64),
-        ("bit_score".to_string(), DataType::Float64),
-    ];
+/// Numeric fields are loaded as strings, then parsed here to allow malformed
+/// values to fail only the rows containing them.
+fn extract_value<T: NumCast>(value: &AnyValue) -> Result<T, MappedErrors> {
+    match value {
+        AnyValue::String(text) => text
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(NumCast::from)
+            .ok_or(execution_err(format!(
+                "Unexpected error on extract blast value `{text}`: not a \
+                number"
+            ))),
+        _ => value.try_extract().map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on extract blast value `{value}`: {err}"
+            ))
+        }),
+    }
+}
 
-    load_named_dataframe(path, column_definitions, vec![])
+/// Extract a numeric value from a blast results cell into the target
+///
+/// Only the first extraction error of the row is recorded.
+fn extract_into<T: NumCast>(
+    value: &AnyValue,
+    target: &mut T,
+    row_error: &mut Option<String>,
+) {
+    match extract_value(value) {
+        Ok(extracted) => *target = extracted,
+        Err(err) => {
+            row_error.get_or_insert(err.msg());
+        }
+    }
 }
 
-fn get_taxonomies_dataframe(
-    path: &Path,
-    use_taxid: Option<bool>,
+/// Load BlastN output dataframe.
+///
+/// The results dataframe is a tabular option of the Blast results. Columns are
+/// mapped by name given the blast output format. Fields not used by the
+/// consensus algorithm are ignored, and optional fields absent from the blast
+/// output are filled with default values.
+fn get_results_dataframe(
+    source: impl MmapBytesReader,
+    columns: &BlastTabularFormat,
 ) -> Result<DataFrame, MappedErrors> {
-    if !path.exists() {
-        return execution_err("Taxonomies file not found").as_error();
+    columns.validate()?;
+
+    let mut loaded_fields = Vec::<BlastField>::new();
+    let mut exclude_list = Vec::<String>::new();
+
+    let column_definitions = columns
+        .fields()
+        .iter()
+        .zip(columns.specifiers())
+        .enumerate()
+        .map(|(index, (field, specifier))| {
+            //
+            // Unknown and repeated fields are loaded as strings and excluded
+            // from the resulting dataframe.
+            //
+            if matches!(field, BlastField::Other(_))
+                || loaded_fields.contains(field)
+            {
+                let name = format!("{specifier}_{index}");
+                exclude_list.push(name.to_owned());
+                return (name, DataType::String);
+            }
+
+            loaded_fields.push(field.to_owned());
+
+            //
+            // Numeric fields are also loaded as strings. Values are parsed
+            // while results are grouped by query, allowing malformed cells to
+            // fail only their own queries.
+            //
+            (field.column_name().to_string(), DataType::String)
+        })
+        .collect::<Vec<(String, DataType)>>();
+
+    let mut df =
+        load_named_dataframe(source, column_definitions, exclude_list)?;
+
+    //
+    // Taxids are loaded as strings given the `staxids` field could contain
+    // multiple semicolon separated taxids. Only the first one is used.
+    //
+    let taxids = match loaded_fields.contains(&BlastField::SubjectTaxid) {
+        true => df
+            .column(BlastField::SubjectTaxid.column_name())
+            .and_then(|column| column.str().map(|values| values.to_owned()))
+            .map_err(|err| {
+                execution_err(format!(
+                    "Unexpected error detected on parse blast taxids: {err}"
+                ))
+            })?
+            .into_iter()
+            .map(|taxid| {
+                taxid
+                    .and_then(|taxid| taxid.split(';').next())
+                    .and_then(|taxid| taxid.trim().parse::<i64>().ok())
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<i64>>(),
+        false => {
+            warn!("Blast output does not contain taxids");
+            vec![0; df.height()]
+        }
+    };
+
+    let height = df.height();
+
+    let mut named_columns =
+        vec![Series::new(BlastField::SubjectTaxid.column_name(), taxids)];
+
+    for field in BlastField::NAMED.iter() {
+        if loaded_fields.contains(field) || field == &BlastField::SubjectTaxid {
+            continue;
+        }
+
+        let name = field.column_name();
+
+        named_columns.push(match field {
+            BlastField::EValue => Series::new(name, vec![0.0; height]),
+            _ => Series::new(name, vec![0_i64; height]),
+        });
     }
 
-    let rdr = read_to_string(path).map_err(|err| {
-        execution_err(format!(
-            "Unexpected error on read `taxonomies` file: {err}"
-        ))
-    })?;
+    for column in named_columns {
+        df.with_column(column).map_err(|err| {
+            use_case_err(format!(
+                "Unexpected error detected on fill blast column: {err}"
+            ))
+        })?;
+    }
+
+    df.select(BlastField::NAMED.iter().map(|field| field.column_name()))
+        .map_err(|err| {
+            use_case_err(format!(
+                "Unexpected error detected on select blast columns: {err}"
+            ))
+        })
+}
 
-    let taxonomy_map =
-        serde_json::from_str::<TaxonomiesMap>(&rdr).map_err(|err| {
+/// Build the taxonomies dataframe of the blast subjects
+///
+/// Only taxonomies of the subject taxids present in the blast results are
+/// loaded from the taxonomies database.
+fn get_taxonomies_dataframe(
+    blast_output_df: &DataFrame,
+    taxonomies_database: &TaxonomiesDatabase,
+    use_taxid: Option<bool>,
+) -> Result<DataFrame, MappedErrors> {
+    let taxids = blast_output_df
+        .column(BlastField::SubjectTaxid.column_name())
+        .and_then(|column| column.i64().map(|values| values.to_owned()))
+        .map_err(|err| {
             execution_err(format!(
-                "Unexpected error detected on parse `taxonomies` as json: {err}"
+                "Unexpected error detected on collect blast taxids: {err}"
             ))
-        })?;
+        })?
+        .into_iter()
+        .flatten()
+        .filter_map(|taxid| u64::try_from(taxid).ok())
+        .collect::<HashSet<u64>>();
+
+    //
+    // The requested taxid is used as the join key, allowing subjects
+    // annotated with merged taxids to be joined with the new taxid taxonomy.
+    //
+    let taxonomies = taxonomies_database.lookup(taxids)?;
 
     let column_definitions = vec![
         ("taxid".to_string(), DataType::Int64),
@@ -272,18 +569,16 @@ fn get_taxonomies_dataframe(
     let mut df = DataFrame::new(vec![
         Series::new(
             "taxid",
-            taxonomy_map
-                .taxonomies
+            taxonomies
                 .iter()
-                .map(|item| item.taxid.to_f64().unwrap())
+                .map(|(taxid, _)| taxid.to_f64().unwrap())
                 .collect::<Vec<_>>(),
         ),
         Series::new(
             "taxonomy",
-            taxonomy_map
-                .taxonomies
+            taxonomies
                 .iter()
-                .map(|v| {
+                .map(|(_, v)| {
                     if let Some(true) = use_taxid {
                         v.numeric_lineage.to_owned()
                     } else {
@@ -306,17 +601,7 @@ fn get_taxonomies_dataframe(
     }
 
     for (column, _type) in schema.iter() {
-        df.apply(column, |s| match s.cast(_type) {
-            Ok(casted) => casted,
-            Err(err) => {
-                let error_message =
-                    format!("Unexpected error detected on cast column: {err}");
-
-                tracing::error!(error_message);
-                panic!("{error_message}");
-            }
-        })
-        .map_err(|err| {
+        df.try_apply(column, |s| s.cast(_type)).map_err(|err| {
             use_case_err(format!(
                 "Unexpected error detected on apply column: {err}"
             ))
@@ -327,7 +612,7 @@ fn get_taxonomies_dataframe(
 }
 
 fn load_named_dataframe(
-    path: &Path,
+    source: impl MmapBytesReader,
     column_definitions: Vec<(String, DataType)>,
     exclude_list: Vec<String>,
 ) -> Result<DataFrame, MappedErrors> {
@@ -354,20 +639,15 @@ fn load_named_dataframe(
     }
 
     // Load dataframe
-    match CsvReader::from_path(path) {
-        Err(err) => {
-            error!("Unexpected error detected on read `blast_output`: {}", err);
-            execution_err(String::from(
-                "Unexpected error occurred on load table.",
+    CsvReader::new(source)
+        .with_separator(b'\t')
+        .has_header(false)
+        .with_schema(Some(Arc::new(schema)))
+        .with_columns(Some(columns_names))
+        .finish()
+        .map_err(|err| {
+            execution_err(format!(
+                "Unexpected error detected on parse `blast_output`: {err}"
             ))
-            .as_error()
-        }
-        Ok(res) => Ok(res
-            .with_separator(b'\t')
-            .has_header(false)
-            .with_schema(Some(Arc::new(schema)))
-            .with_columns(Some(columns_names))
-            .finish()
-            .unwrap()),
-    }
+        })
 }
@@ -0,0 +1,260 @@
+use crate::domain::dtos::{
+    blast_tabular_format::BlastField, taxonomies_database::TaxonomiesDatabase,
+};
+
+use mycelium_base::utils::errors::{execution_err, use_case_err, MappedErrors};
+use polars::prelude::DataFrame;
+use polars_core::{prelude::NamedFrom, series::Series};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+use tracing::{info, warn};
+
+/// Resolve missing subject taxids using the subject accessions
+///
+/// Blast databases built without the `-taxid_map` option report the taxid
+/// zero for all subjects. Such taxids are replaced by the taxid mapped to the
+/// subject accession, firstly from the `accessions_file` if provided and then
+/// from the accessions stored in the taxonomies database. Accessions are matched
+/// with and without version suffix (e.g. `NR_024570.1` and `NR_024570`).
+pub(super) fn resolve_taxids_by_accession(
+    blast_output_df: DataFrame,
+    taxonomies_database: &TaxonomiesDatabase,
+    accessions_file: Option<&Path>,
+) -> Result<DataFrame, MappedErrors> {
+    let (taxids, accessions) = collect_taxids_and_accessions(&blast_output_df)?;
+
+    // ? -----------------------------------------------------------------------
+    // ? Collect accessions without taxid
+    // ? -----------------------------------------------------------------------
+
+    let mut missing = HashSet::<String>::new();
+
+    for (taxid, accession) in taxids.iter().zip(accessions.iter()) {
+        if let (None | Some(0), Some(accession)) = (taxid, accession) {
+            insert_missing_accession(&mut missing, accession);
+        }
+    }
+
+    if missing.is_empty() {
+        return Ok(blast_output_df);
+    }
+
+    // ? -----------------------------------------------------------------------
+    // ? Replace missing taxids
+    // ? -----------------------------------------------------------------------
+
+    let accessions_map =
+        load_accessions_map(&missing, taxonomies_database, accessions_file)?;
+
+    let (blast_output_df, unresolved) =
+        replace_missing_taxids(blast_output_df, &accessions_map)?;
+
+    if !unresolved.is_empty() {
+        warn!(
+            "{} subject accessions without taxid could not be resolved",
+            unresolved.len()
+        );
+    }
+
+    info!("Subject taxids resolved by accession");
+
+    Ok(blast_output_df)
+}
+
+/// Register the accession, with and without version, as missing
+pub(super) fn insert_missing_accession(
+    missing: &mut HashSet<String>,
+    accession: &str,
+) {
+    missing.insert(accession.to_string());
+    missing.insert(unversioned(accession).to_string());
+}
+
+/// Build the taxids map of the missing accessions
+///
+/// Accessions stored in the taxonomies database are overridden by the ones of the
+/// `accessions_file`, if provided. Only accessions registered as `missing`
+/// are kept.
+pub(super) fn load_accessions_map(
+    missing: &HashSet<String>,
+    taxonomies_database: &TaxonomiesDatabase,
+    accessions_file: Option<&Path>,
+) -> Result<HashMap<String, i64>, MappedErrors> {
+    let mut accessions_map = HashMap::<String, i64>::new();
+
+    if missing.is_empty() {
+        return Ok(accessions_map);
+    }
+
+    taxonomies_database.for_each(|taxonomy| {
+        for item in &taxonomy.accessions {
+            insert_accession(
+                &mut accessions_map,
+                missing,
+                &item.accession,
+                taxonomy.taxid as i64,
+            );
+        }
+
+        Ok(())
+    })?;
+
+    if let Some(path) = accessions_file {
+        load_accessions_file(path, missing, &mut accessions_map)?;
+    }
+
+    Ok(accessions_map)
+}
+
+/// Replace the zero or null taxids by the ones mapped to the accessions
+///
+/// Returns the resulting dataframe and the accessions without mapped taxid.
+pub(super) fn replace_missing_taxids(
+    mut blast_output_df: DataFrame,
+    accessions_map: &HashMap<String, i64>,
+) -> Result<(DataFrame, HashSet<String>), MappedErrors> {
+    let (taxids, accessions) = collect_taxids_and_accessions(&blast_output_df)?;
+
+    let mut unresolved = HashSet::<String>::new();
+
+    let resolved_taxids = taxids
+        .iter()
+        .zip(accessions.iter())
+        .map(|(taxid, accession)| match (taxid, accession) {
+            (Some(taxid), _) if *taxid != 0 => *taxid,
+            (_, Some(accession)) => accessions_map
+                .get(*accession)
+                .or_else(|| accessions_map.get(unversioned(accession)))
+                .copied()
+                .unwrap_or_else(|| {
+                    unresolved.insert(accession.to_string());
+                    0
+                }),
+            _ => 0,
+        })
+        .collect::<Vec<i64>>();
+
+    blast_output_df
+        .with_column(Series::new(
+            BlastField::SubjectTaxid.column_name(),
+            resolved_taxids,
+        ))
+        .map_err(|err| {
+            use_case_err(format!(
+                "Unexpected error detected on replace blast taxids: {err}"
+            ))
+        })?;
+
+    Ok((blast_output_df, unresolved))
+}
+
+/// The subject taxids and accessions columns of the blast output
+type TaxidsAndAccessions<'a> = (Vec<Option<i64>>, Vec<Option<&'a str>>);
+
+/// Collect the subject taxids and accessions columns of the blast output
+fn collect_taxids_and_accessions(
+    blast_output_df: &DataFrame,
+) -> Result<TaxidsAndAccessions<'_>, MappedErrors> {
+    let taxids = blast_output_df
+        .column(BlastField::SubjectTaxid.column_name())
+        .and_then(|column| column.i64().map(|values| values.to_owned()))
+        .map_err(|err| {
+            execution_err(format!(
+                "Unexpected error detected on collect blast taxids: {err}"
+            ))
+        })?
+        .into_iter()
+        .collect::<Vec<Option<i64>>>();
+
+    let accessions = blast_output_df
+        .column(BlastField::SubjectAccession.column_name())
+        .and_then(|column| column.str())
+        .map_err(|err| {
+            execution_err(format!(
+                "Unexpected error detected on collect blast accessions: {err}"
+            ))
+        })?
+        .into_iter()
+        .collect::<Vec<Option<&str>>>();
+
+    Ok((taxids, accessions))
+}
+
+/// Load an accession to taxid mapping file
+///
+/// Accepts the NCBI `accession2taxid` format (`accession`,
+/// `accession.version`, `taxid`, and `gi` columns) or a two columns tab
+/// separated file containing the accession and the taxid. Lines without a
+/// numeric taxid, like the header, are ignored.
+fn load_accessions_file(
+    path: &Path,
+    missing: &HashSet<String>,
+    accessions_map: &mut HashMap<String, i64>,
+) -> Result<(), MappedErrors> {
+    let file = File::open(path).map_err(|err| {
+        execution_err(format!(
+            "Unexpected error on open accessions file `{}`: {err}",
+            path.display()
+        ))
+    })?;
+
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on read accessions file: {err}"
+            ))
+        })?;
+
+        let columns = line.split('\t').collect::<Vec<&str>>();
+
+        let (accessions, taxid) = match columns.as_slice() {
+            [accession, taxid] => (vec![*accession], *taxid),
+            [accession, versioned, taxid, ..] => {
+                (vec![*accession, *versioned], *taxid)
+            }
+            _ => continue,
+        };
+
+        let taxid = match taxid.trim().parse::<i64>() {
+            Ok(taxid) => taxid,
+            Err(_) => continue,
+        };
+
+        for accession in accessions {
+            insert_accession(accessions_map, missing, accession.trim(), taxid);
+        }
+    }
+
+    Ok(())
+}
+
+/// Insert the accession into the map if it is missing in the blast output
+fn insert_accession(
+    accessions_map: &mut HashMap<String, i64>,
+    missing: &HashSet<String>,
+    accession: &str,
+    taxid: i64,
+) {
+    for key in [accession, unversioned(accession)] {
+        if missing.contains(key) {
+            accessions_map.insert(key.to_string(), taxid);
+        }
+    }
+}
+
+/// Remove the version suffix from the accession
+fn unversioned(accession: &str) -> &str {
+    match accession.rsplit_once('.') {
+        Some((base, version))
+            if !version.is_empty()
+                && version.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            base
+        }
+        _ => accession,
+    }
+}
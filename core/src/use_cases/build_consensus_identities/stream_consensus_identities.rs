@@ -0,0 +1,419 @@
+use super::{
+    build_query_consensus, fold_results_by_query, get_results_dataframe,
+    get_taxonomies_dataframe, insert_missing_accession, load_accessions_map,
+    replace_missing_taxids,
+};
+
+use crate::domain::dtos::{
+    blast_result::BlastQueryResult,
+    blast_tabular_format::{BlastField, BlastTabularFormat},
+    consensus_result::ConsensusResult,
+    consensus_strategy::ConsensusStrategy,
+    parallel_blast_output::ParallelBlastOutput,
+    taxon::{CustomTaxon, Taxon},
+    taxonomies_database::TaxonomiesDatabase,
+};
+
+use mycelium_base::utils::errors::{
+    execution_err, invalid_arg_err, MappedErrors,
+};
+use polars_lazy::prelude::*;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::File,
+    io::{BufRead, BufReader, Cursor, Lines},
+    path::Path,
+};
+use tracing::{info, warn};
+
+/// The default number of queries of each streaming consensus batch
+pub const DEFAULT_STREAM_BATCH_SIZE: usize = 1000;
+
+/// Build consensus identities from BlastN output in batches of queries
+///
+/// The blast output is read sequentially and each batch of `batch_size`
+/// queries is joined with the taxonomies of its subjects and folded into
+/// consensus results, so the memory usage does not depend on the blast output
+/// size.
+/// Results of each query should be contiguous in the blast output, as
+/// produced by blast. Queries listed in the blast output headers without
+/// blast results are produced after the blast output is consumed.
+///
+/// Batches are produced lazily by the returned `ConsensusStream` iterator.
+#[tracing::instrument(
+    name = "Stream consensus identities from Blast output",
+    skip(
+        blast_output,
+        taxonomies_file,
+        taxon,
+        strategy,
+        use_taxid,
+        accessions_file
+    )
+)]
+pub fn stream_consensus_identities(
+    blast_output: ParallelBlastOutput,
+    taxonomies_file: &Path,
+    taxon: Taxon,
+    strategy: ConsensusStrategy,
+    use_taxid: Option<bool>,
+    custom_taxon_values: Option<CustomTaxon>,
+    accessions_file: Option<&Path>,
+) -> Result<ConsensusStream, MappedErrors> {
+    blast_output.columns.validate()?;
+
+    // ? -----------------------------------------------------------------------
+    // ? Load taxonomies
+    // ? -----------------------------------------------------------------------
+
+    info!("Loading Blutils taxonomies");
+
+    let taxonomies_database = TaxonomiesDatabase::open(taxonomies_file)?;
+
+    info!(
+        "Blutils taxonomies loaded (source database: {})",
+        taxonomies_database.metadata().source_database
+    );
+
+    // ? -----------------------------------------------------------------------
+    // ? Map subject accessions without taxid
+    // ? -----------------------------------------------------------------------
+
+    let missing = collect_missing_accessions(
+        &blast_output.output_file,
+        &blast_output.columns,
+    )?;
+
+    let accessions_map =
+        load_accessions_map(&missing, &taxonomies_database, accessions_file)?;
+
+    // ? -----------------------------------------------------------------------
+    // ? Open the blast output
+    // ? -----------------------------------------------------------------------
+
+    let lines = open_blast_output(&blast_output.output_file)?.lines();
+
+    let query_index = blast_output
+        .columns
+        .position(&BlastField::Query)
+        .unwrap_or(0);
+
+    Ok(ConsensusStream {
+        lines,
+        next_line: None,
+        query_index,
+        columns: blast_output.columns,
+        taxonomies_database,
+        use_taxid,
+        accessions_map,
+        unresolved: HashSet::new(),
+        processed: HashSet::new(),
+        headers: blast_output.headers,
+        remaining: None,
+        abundances: blast_output.abundances.unwrap_or_default(),
+        failures: blast_output.failures.unwrap_or_default(),
+        taxon,
+        strategy,
+        custom_taxon_values,
+        batch_size: DEFAULT_STREAM_BATCH_SIZE,
+        finished: false,
+    })
+}
+
+/// A lazy iterator over batches of consensus results
+///
+/// Created by `stream_consensus_identities`.
+pub struct ConsensusStream {
+    lines: Lines<BufReader<File>>,
+    next_line: Option<(String, String)>,
+    query_index: usize,
+    columns: BlastTabularFormat,
+    taxonomies_database: TaxonomiesDatabase,
+    use_taxid: Option<bool>,
+    accessions_map: HashMap<String, i64>,
+    unresolved: HashSet<String>,
+    processed: HashSet<String>,
+    headers: Option<Vec<String>>,
+    remaining: Option<VecDeque<String>>,
+    abundances: HashMap<String, u64>,
+    failures: HashMap<String, String>,
+    taxon: Taxon,
+    strategy: ConsensusStrategy,
+    custom_taxon_values: Option<CustomTaxon>,
+    batch_size: usize,
+    finished: bool,
+}
+
+impl ConsensusStream {
+    /// Set the maximum number of queries of each batch
+    pub fn with_batch_size(
+        mut self,
+        batch_size: usize,
+    ) -> Result<Self, MappedErrors> {
+        if batch_size == 0 {
+            return invalid_arg_err("The stream batch size should be positive")
+                .as_error();
+        }
+
+        self.batch_size = batch_size;
+        Ok(self)
+    }
+
+    /// Read the blast results of the next batch of queries
+    ///
+    /// Returns the blast output lines of the batch and the batch queries in
+    /// the blast output order.
+    fn read_batch(&mut self) -> Result<(String, Vec<String>), MappedErrors> {
+        let mut content = String::new();
+        let mut queries = Vec::<String>::new();
+
+        loop {
+            let (query, line) = match self.next_line.take() {
+                Some(item) => item,
+                None => match self.lines.next() {
+                    None => break,
+                    Some(line) => {
+                        let line = line.map_err(|err| {
+                            execution_err(format!(
+                                "Unexpected error on read blast output: {err}"
+                            ))
+                        })?;
+
+                        if line.is_empty() {
+                            continue;
+                        }
+
+                        let query = line
+                            .split('\t')
+                            .nth(self.query_index)
+                            .unwrap_or_default()
+                            .to_string();
+
+                        (query, line)
+                    }
+                },
+            };
+
+            if queries.last() != Some(&query) {
+                if queries.len() == self.batch_size {
+                    self.next_line = Some((query, line));
+                    break;
+                }
+
+                if !self.processed.insert(query.to_owned()) {
+                    return execution_err(format!(
+                        "Blast results of query `{query}` are not contiguous. Streaming consensus requires the blast output grouped by query."
+                    ))
+                    .as_error();
+                }
+
+                queries.push(query);
+            }
+
+            content.push_str(&line);
+            content.push('\n');
+        }
+
+        Ok((content, queries))
+    }
+
+    /// Build consensus results of a batch of blast results
+    fn build_batch(
+        &mut self,
+        content: String,
+        queries: Vec<String>,
+    ) -> Result<Vec<ConsensusResult>, MappedErrors> {
+        let blast_output_df = get_results_dataframe(
+            Cursor::new(content.into_bytes()),
+            &self.columns,
+        )?;
+
+        let (blast_output_df, unresolved) =
+            replace_missing_taxids(blast_output_df, &self.accessions_map)?;
+
+        self.unresolved.extend(unresolved);
+
+        let taxonomies_df = get_taxonomies_dataframe(
+            &blast_output_df,
+            &self.taxonomies_database,
+            self.use_taxid,
+        )?;
+
+        let joined_df = blast_output_df.lazy().left_join(
+            taxonomies_df.lazy(),
+            col("subject_taxid"),
+            col("taxid"),
+        );
+
+        let mut results = fold_results_by_query(joined_df)?
+            .into_iter()
+            .map(|result| (result.query.to_owned(), result))
+            .collect::<HashMap<String, BlastQueryResult>>();
+
+        //
+        // Results are produced in the blast output order.
+        //
+        let query_results = queries
+            .into_iter()
+            .map(|query| {
+                results.remove(&query).unwrap_or(BlastQueryResult {
+                    query,
+                    results: None,
+                    failure: None,
+                })
+            })
+            .collect::<Vec<BlastQueryResult>>();
+
+        Ok(self.build_consensus(query_results))
+    }
+
+    fn build_consensus(
+        &self,
+        query_results: Vec<BlastQueryResult>,
+    ) -> Vec<ConsensusResult> {
+        query_results
+            .into_par_iter()
+            .map(|result| {
+                build_query_consensus(
+                    result,
+                    &self.abundances,
+                    &self.failures,
+                    &self.taxon,
+                    &self.strategy,
+                    &self.custom_taxon_values,
+                )
+            })
+            .collect::<Vec<ConsensusResult>>()
+    }
+
+    /// Build results of the input queries without blast results
+    fn next_remaining(&mut self) -> Option<Vec<ConsensusResult>> {
+        if self.remaining.is_none() {
+            if !self.unresolved.is_empty() {
+                warn!(
+                    "{} subject accessions without taxid could not be resolved",
+                    self.unresolved.len()
+                );
+            }
+
+            let processed = std::mem::take(&mut self.processed);
+
+            self.remaining = Some(
+                self.headers
+                    .take()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|header| !processed.contains(header))
+                    .collect(),
+            );
+        }
+
+        let remaining = self.remaining.as_mut()?;
+
+        if remaining.is_empty() {
+            return None;
+        }
+
+        let query_results = remaining
+            .drain(..self.batch_size.min(remaining.len()))
+            .map(|query| BlastQueryResult {
+                query,
+                results: None,
+                failure: None,
+            })
+            .collect::<Vec<BlastQueryResult>>();
+
+        Some(self.build_consensus(query_results))
+    }
+}
+
+impl Iterator for ConsensusStream {
+    type Item = Result<Vec<ConsensusResult>, MappedErrors>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let batch = match self.read_batch() {
+            Ok((_, queries)) if queries.is_empty() => {
+                return match self.next_remaining() {
+                    Some(results) => Some(Ok(results)),
+                    None => {
+                        self.finished = true;
+                        None
+                    }
+                };
+            }
+            Ok((content, queries)) => self.build_batch(content, queries),
+            Err(err) => Err(err),
+        };
+
+        //
+        // Errors interrupt the stream given the blast output could not be
+        // reliably consumed after it.
+        //
+        if batch.is_err() {
+            self.finished = true;
+        }
+
+        Some(batch)
+    }
+}
+
+fn open_blast_output(path: &Path) -> Result<BufReader<File>, MappedErrors> {
+    File::open(path).map(BufReader::new).map_err(|err| {
+        execution_err(format!(
+            "Unexpected error on open blast output `{}`: {err}",
+            path.display()
+        ))
+    })
+}
+
+/// Collect the subject accessions reported without taxid
+///
+/// The blast output is scanned once, keeping only the subject accessions, to
+/// allow the accessions map to be built before the consensus generation.
+fn collect_missing_accessions(
+    path: &Path,
+    columns: &BlastTabularFormat,
+) -> Result<HashSet<String>, MappedErrors> {
+    let mut missing = HashSet::<String>::new();
+
+    let accession_index = match columns.position(&BlastField::SubjectAccession)
+    {
+        Some(index) => index,
+        None => return Ok(missing),
+    };
+
+    let taxid_index = columns.position(&BlastField::SubjectTaxid);
+
+    for line in open_blast_output(path)?.lines() {
+        let line = line.map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on read blast output: {err}"
+            ))
+        })?;
+
+        let fields = line.split('\t').collect::<Vec<&str>>();
+
+        let taxid = taxid_index
+            .and_then(|index| fields.get(index))
+            .and_then(|taxid| taxid.split(';').next())
+            .and_then(|taxid| taxid.trim().parse::<i64>().ok())
+            .unwrap_or(0);
+
+        if taxid != 0 {
+            continue;
+        }
+
+        if let Some(accession) = fields.get(accession_index) {
+            if !missing.contains(*accession) {
+                insert_missing_accession(&mut missing, accession);
+            }
+        }
+    }
+
+    Ok(missing)
+}
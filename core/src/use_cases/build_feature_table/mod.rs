@@ -0,0 +1,141 @@
+use super::{shared::load_blutils_output, OutputFormat};
+use crate::domain::dtos::{
+    feature_table::{FeatureTable, FeatureTableFormat},
+    file_or_stdin::{FileOrStdin, Source},
+    linnaean_ranks::LinnaeanRank,
+};
+
+use mycelium_base::utils::errors::{use_case_err, MappedErrors};
+use std::{
+    fs::{remove_file, File},
+    io::Write,
+    path::PathBuf,
+};
+use tracing::{info, warn};
+
+/// Build a taxon-by-sample feature table from multiple blutils outputs
+///
+/// Each blutils output becomes a sample column. Samples are named after the
+/// input file stem, or after the run ID of the blutils output when
+/// `use_run_id` is true.
+#[tracing::instrument(
+    name = "Build feature table from Blutils outputs",
+    skip(blutils_results, output_file, result_format, table_format)
+)]
+pub fn build_feature_table(
+    blutils_results: Vec<FileOrStdin>,
+    rank: LinnaeanRank,
+    use_run_id: bool,
+    output_file: Option<PathBuf>,
+    result_format: OutputFormat,
+    table_format: FeatureTableFormat,
+) -> Result<(), MappedErrors> {
+    if blutils_results.is_empty() {
+        return use_case_err("At least one blutils output should be provided")
+            .as_error();
+    }
+
+    // ? -----------------------------------------------------------------------
+    // ? Load samples into the feature table
+    // ? -----------------------------------------------------------------------
+
+    let mut table = FeatureTable::new(rank);
+
+    for blutils_result in blutils_results {
+        let file_stem = match &blutils_result.source {
+            Source::Stdin => "stdin".to_string(),
+            Source::Arg(file) => PathBuf::from(file)
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or(file)
+                .to_string(),
+        };
+
+        let content =
+            load_blutils_output(blutils_result, result_format.to_owned())?;
+
+        let sample = match use_run_id {
+            false => file_stem,
+            true => match content.config.as_ref().map(|config| config.run_id) {
+                Some(run_id) => run_id.to_string(),
+                None => match content
+                    .results
+                    .iter()
+                    .find_map(|result| result.run_id)
+                {
+                    Some(run_id) => run_id.to_string(),
+                    None => {
+                        return use_case_err(format!(
+                            "Run ID not found for sample `{file_stem}`"
+                        ))
+                        .as_error()
+                    }
+                },
+            },
+        };
+
+        if table.samples.contains(&sample) {
+            return use_case_err(format!("Duplicated sample name: {sample}"))
+                .as_error();
+        }
+
+        info!("Loading sample {sample}");
+
+        table.add_sample(sample, &content);
+    }
+
+    // ? -----------------------------------------------------------------------
+    // ? Serialize the feature table
+    // ? -----------------------------------------------------------------------
+
+    let table_content = match table_format {
+        FeatureTableFormat::Tsv => table.to_tsv(),
+        FeatureTableFormat::Biom => serde_json::to_string(&table.to_biom())
+            .map_err(|err| {
+                use_case_err(format!(
+                    "Unexpected error on serialize BIOM table: {err}"
+                ))
+            })?,
+    };
+
+    // ? -----------------------------------------------------------------------
+    // ? Write the feature table
+    // ? -----------------------------------------------------------------------
+
+    let output_file = match output_file {
+        Some(mut path) => {
+            match table_format {
+                FeatureTableFormat::Tsv => path.set_extension("tsv"),
+                FeatureTableFormat::Biom => path.set_extension("biom"),
+            };
+
+            path
+        }
+        None => {
+            println!("{}", table_content.trim_end());
+            return Ok(());
+        }
+    };
+
+    if output_file.exists() {
+        warn!("Output file already exists. Removing it.");
+        remove_file(&output_file).map_err(|err| {
+            use_case_err(format!("Could not remove file given {err}"))
+        })?;
+    }
+
+    let mut file = File::create(&output_file).map_err(|err| {
+        use_case_err(format!(
+            "Error on persist feature table into {:?}: {err}",
+            output_file
+        ))
+    })?;
+
+    file.write_all(table_content.as_bytes()).map_err(|err| {
+        use_case_err(format!(
+            "Unexpected error on write feature table file: {err}"
+        ))
+    })?;
+
+    Ok(())
+}
@@ -11,9 +11,15 @@ use std::{
 use subprocess::Exec;
 use tracing::warn;
 
+/// Write the blast database sequences with the Kraken2 taxid headers
+///
+/// The taxid of each sequence is given by the `resolve_taxid` function from
+/// its accession and blast database taxid. Sequences resolved to `None` are
+/// skipped.
 pub(super) fn generate_fasta_file(
     blast_database_path: &PathBuf,
     mut output_file: PathBuf,
+    resolve_taxid: impl Fn(&str, u64) -> Option<u64>,
 ) -> Result<Vec<(String, usize)>, MappedErrors> {
     // ? -----------------------------------------------------------------------
     // ? Validate the blast database
@@ -28,6 +34,7 @@ pub(super) fn generate_fasta_file(
     // ? -----------------------------------------------------------------------
 
     let mut headers = Vec::<(String, usize)>::new();
+    let mut skipped = 0;
 
     // ? -----------------------------------------------------------------------
     // ? Generate the fasta file
@@ -74,6 +81,16 @@ pub(super) fn generate_fasta_file(
                     line.next().expect(er_msg).trim(),
                 );
 
+                let taxid =
+                    match resolve_taxid(accession, taxid.parse().unwrap()) {
+                        Some(taxid) => taxid as usize,
+                        None => {
+                            skipped += 1;
+                            buf_line.clear();
+                            continue;
+                        }
+                    };
+
                 if let Err(err) = file_writer(
                     format!(
                         ">kraken:taxid|{taxid}|{accession}\n{sequence}\n",
@@ -95,13 +112,17 @@ pub(super) fn generate_fasta_file(
                     panic!("Unexpected error detected on write sequences database: {err}");
                 };
 
-                headers.push((accession.to_owned(), taxid.parse().unwrap()));
+                headers.push((accession.to_owned(), taxid));
 
                 buf_line.clear();
             }
         }
     };
 
+    if skipped > 0 {
+        warn!("{skipped} sequences filtered by the taxonomies database were skipped");
+    }
+
     // ? -----------------------------------------------------------------------
     // ? Return a positive response
     // ? -----------------------------------------------------------------------
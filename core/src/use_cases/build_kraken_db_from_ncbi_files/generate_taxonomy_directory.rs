@@ -0,0 +1,511 @@
+use crate::{
+    domain::dtos::{
+        linnaean_ranks::LinnaeanRank, taxonomies_database::TaxonomiesDatabase,
+    },
+    use_cases::shared::{create_writer, write_error},
+};
+
+use mycelium_base::utils::errors::{execution_err, use_case_err, MappedErrors};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{create_dir_all, File},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+use tracing::{info, warn};
+
+/// The taxid of the root of the NCBI taxonomy
+const ROOT_TAXID: u64 = 1;
+
+/// The taxonomy tree of a NCBI taxdump
+pub(super) struct TaxdumpTree {
+    /// The parent taxid of each taxid of the `nodes.dmp` file
+    parents: HashMap<u64, u64>,
+
+    /// The new taxid of each taxid of the `merged.dmp` file
+    merged: HashMap<u64, u64>,
+}
+
+impl TaxdumpTree {
+    /// Resolve merged taxids to the current taxid
+    ///
+    /// Merge chains are followed until a taxid not merged is found.
+    pub(super) fn resolve_taxid(&self, taxid: u64) -> u64 {
+        let mut current = taxid;
+        let mut visited = HashSet::<u64>::new();
+
+        while let Some(new_taxid) = self.merged.get(&current) {
+            if !visited.insert(current) {
+                break;
+            }
+
+            current = *new_taxid;
+        }
+
+        current
+    }
+}
+
+/// Load the taxonomy tree of the `nodes.dmp` and `merged.dmp` taxdump files
+///
+/// The `merged.dmp` file is optional.
+pub(super) fn load_taxdump_tree(
+    taxdump_directory_path: &Path,
+) -> Result<TaxdumpTree, MappedErrors> {
+    let nodes_path = taxdump_directory_path.join("nodes.dmp");
+    let names_path = taxdump_directory_path.join("names.dmp");
+    let merged_path = taxdump_directory_path.join("merged.dmp");
+
+    if !nodes_path.is_file() {
+        return use_case_err(format!("Invalid nodes path: {:?}", nodes_path))
+            .as_error();
+    }
+
+    if !names_path.is_file() {
+        return use_case_err(format!("Invalid names path: {:?}", names_path))
+            .as_error();
+    }
+
+    let mut parents = HashMap::<u64, u64>::new();
+
+    for_each_dump_line(&nodes_path, |fields, _| {
+        if let (Some(taxid), Some(parent)) = (fields.next(), fields.next()) {
+            parents.insert(taxid, parent);
+        }
+
+        Ok(())
+    })?;
+
+    let mut merged = HashMap::<u64, u64>::new();
+
+    if merged_path.is_file() {
+        for_each_dump_line(&merged_path, |fields, _| {
+            if let (Some(taxid), Some(new_taxid)) =
+                (fields.next(), fields.next())
+            {
+                merged.insert(taxid, new_taxid);
+            }
+
+            Ok(())
+        })?;
+    } else {
+        warn!("Merged file not found. Merged taxids will not be remapped.");
+    }
+
+    Ok(TaxdumpTree { parents, merged })
+}
+
+/// Write the `taxonomy` directory expected by `kraken2-build`
+///
+/// The `names.dmp` and `nodes.dmp` files are copied from the taxdump, pruned
+/// to the given taxids and their ancestors.
+pub(super) fn generate_taxonomy_directory(
+    taxids: HashSet<u64>,
+    taxdump_tree: &TaxdumpTree,
+    taxdump_directory_path: &Path,
+    output_directory: PathBuf,
+) -> Result<(), MappedErrors> {
+    // ? -----------------------------------------------------------------------
+    // ? Collect the taxids and their ancestors
+    // ? -----------------------------------------------------------------------
+
+    let mut kept = HashSet::<u64>::new();
+    let mut unknown = 0;
+
+    for taxid in taxids {
+        if !taxdump_tree.parents.contains_key(&taxid) {
+            unknown += 1;
+            continue;
+        }
+
+        let mut current = taxid;
+
+        while kept.insert(current) {
+            match taxdump_tree.parents.get(&current) {
+                Some(parent) if *parent != current => current = *parent,
+                _ => break,
+            }
+        }
+    }
+
+    if unknown > 0 {
+        warn!(
+            "{unknown} taxids not found in the taxdump nodes. Sequences of such taxids will not be classified by Kraken2."
+        );
+    }
+
+    // ? -----------------------------------------------------------------------
+    // ? Write the pruned taxdump files
+    // ? -----------------------------------------------------------------------
+
+    create_taxonomy_directory(&output_directory)?;
+
+    for file_name in ["nodes.dmp", "names.dmp"] {
+        write_pruned_dump_file(
+            &taxdump_directory_path.join(file_name),
+            &output_directory.join(file_name),
+            &kept,
+        )?;
+    }
+
+    info!(
+        "{} taxids written to the taxonomy directory {:?}",
+        kept.len(),
+        output_directory
+    );
+
+    Ok(())
+}
+
+/// Write the `taxonomy` directory expected by `kraken2-build` from the
+/// lineages of the Blutils database
+///
+/// The tree follows the lineages of the records of the given taxids, so the
+/// ignored taxids, replaced ranks, and dropped non Linnaean taxonomies of the
+/// Blutils database are reflected in the exported taxonomy. Lineage elements
+/// without taxid, as the placeholders of filled ranks, are skipped. The record
+/// taxids are always written, even if absent from the filled lineages.
+///
+/// Names are copied from the `names.dmp` file of the taxdump if given.
+/// Otherwise, the names of the Blutils text lineages are used.
+pub(super) fn generate_taxonomy_directory_from_blutils_db(
+    taxids: HashSet<u64>,
+    taxonomies_database: &TaxonomiesDatabase,
+    taxdump_directory_path: Option<&Path>,
+    output_directory: PathBuf,
+) -> Result<(), MappedErrors> {
+    // ? -----------------------------------------------------------------------
+    // ? Collect the nodes of the Blutils lineages
+    // ? -----------------------------------------------------------------------
+
+    //
+    // Nodes are sorted by taxid to produce reproducible files.
+    //
+    let mut nodes = BTreeMap::<u64, (u64, String, String)>::new();
+
+    taxonomies_database.for_each(|record| {
+        if !taxids.contains(&record.taxid) {
+            return Ok(());
+        }
+
+        let mut parent = ROOT_TAXID;
+
+        for (numeric, text) in record
+            .numeric_lineage
+            .split(';')
+            .zip(record.text_lineage.split(';'))
+        {
+            let (rank, taxid) = match numeric.split_once("__") {
+                Some((rank, taxid)) => match taxid.parse::<u64>() {
+                    Ok(taxid) => (rank, taxid),
+                    Err(_) => continue,
+                },
+                None => continue,
+            };
+
+            let name = text.split_once("__").map(|(_, name)| name);
+
+            nodes.entry(taxid).or_insert((
+                parent,
+                kraken_rank(rank),
+                name.unwrap_or_default().to_owned(),
+            ));
+
+            parent = taxid;
+        }
+
+        //
+        // Lineages of databases built with filled ranks do not include the
+        // terminal non Linnaean elements, as strains. Such records are linked
+        // to the last lineage element, named after it unless the taxdump
+        // names are given.
+        //
+        let name = record
+            .text_lineage
+            .rsplit(';')
+            .find_map(|element| element.split_once("__"))
+            .map(|(_, name)| name);
+
+        nodes.entry(record.taxid).or_insert((
+            parent,
+            kraken_rank(&record.rank),
+            name.unwrap_or_default().to_owned(),
+        ));
+
+        Ok(())
+    })?;
+
+    nodes.entry(ROOT_TAXID).or_insert((
+        ROOT_TAXID,
+        "no rank".to_string(),
+        "root".to_string(),
+    ));
+
+    // ? -----------------------------------------------------------------------
+    // ? Write the taxonomy files
+    // ? -----------------------------------------------------------------------
+
+    create_taxonomy_directory(&output_directory)?;
+
+    let nodes_file = output_directory.join("nodes.dmp");
+    let mut writer = create_writer(&nodes_file)?;
+
+    nodes
+        .iter()
+        .try_for_each(|(taxid, (parent, rank, _))| {
+            writeln!(writer, "{taxid}\t|\t{parent}\t|\t{rank}\t|\t\t|")
+        })
+        .and_then(|_| writer.flush())
+        .map_err(|err| write_error(&nodes_file, err))?;
+
+    let names_file = output_directory.join("names.dmp");
+
+    match taxdump_directory_path {
+        Some(path) => write_pruned_dump_file(
+            &path.join("names.dmp"),
+            &names_file,
+            &nodes.keys().copied().collect::<HashSet<u64>>(),
+        )?,
+        None => {
+            let mut writer = create_writer(&names_file)?;
+
+            nodes
+                .iter()
+                .try_for_each(|(taxid, (_, _, name))| {
+                    writeln!(
+                        writer,
+                        "{taxid}\t|\t{name}\t|\t\t|\tscientific name\t|"
+                    )
+                })
+                .and_then(|_| writer.flush())
+                .map_err(|err| write_error(&names_file, err))?;
+        }
+    }
+
+    info!(
+        "{} taxids written to the taxonomy directory {:?}",
+        nodes.len(),
+        output_directory
+    );
+
+    Ok(())
+}
+
+/// Convert a Blutils lineage rank to the NCBI rank name read by Kraken2
+///
+/// Domains are written as `superkingdom`, the rank Kraken2 reports with the
+/// `D` code.
+fn kraken_rank(rank: &str) -> String {
+    match rank.parse::<LinnaeanRank>() {
+        Ok(LinnaeanRank::Domain) => "superkingdom".to_string(),
+        Ok(LinnaeanRank::Other(rank)) => rank.replace('-', " "),
+        Ok(rank) => rank.as_full_rank_string(),
+        Err(_) => rank.to_string(),
+    }
+}
+
+fn create_taxonomy_directory(path: &Path) -> Result<(), MappedErrors> {
+    create_dir_all(path).map_err(|err| {
+        execution_err(format!(
+            "Unexpected error on create taxonomy directory: {err}"
+        ))
+    })
+}
+
+/// Copy the lines of a taxdump file whose taxid is in the kept taxids
+fn write_pruned_dump_file(
+    source: &Path,
+    output_file: &Path,
+    kept: &HashSet<u64>,
+) -> Result<(), MappedErrors> {
+    let mut writer = create_writer(output_file)?;
+
+    for_each_dump_line(source, |fields, line| match fields.next() {
+        Some(taxid) if kept.contains(&taxid) => writeln!(writer, "{line}")
+            .map_err(|err| write_error(output_file, err)),
+        _ => Ok(()),
+    })?;
+
+    writer.flush().map_err(|err| write_error(output_file, err))
+}
+
+/// Call the visitor with the numeric fields and the content of each line of a
+/// taxdump file
+///
+/// Fields are parsed lazily, so only the leading numeric fields should be
+/// consumed.
+fn for_each_dump_line(
+    path: &Path,
+    mut visitor: impl FnMut(
+        &mut dyn Iterator<Item = u64>,
+        &str,
+    ) -> Result<(), MappedErrors>,
+) -> Result<(), MappedErrors> {
+    let reader = BufReader::new(File::open(path).map_err(|err| {
+        execution_err(format!("Unexpected error on open {:?}: {err}", path))
+    })?);
+
+    for line in reader.lines() {
+        let line = line.map_err(|err| {
+            execution_err(format!("Unexpected error on read {:?}: {err}", path))
+        })?;
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut fields = line
+            .split("\t|")
+            .map_while(|field| field.trim().parse::<u64>().ok());
+
+        visitor(&mut fields, &line)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::dtos::{
+        taxonomies_database::{
+            write_taxonomies_database, TaxonomiesDatabaseFormat,
+        },
+        taxonomies_map::{
+            Accession, RankFillingStrategy, TaxonomiesMap, TaxonomyMapUnit,
+        },
+    };
+    use std::fs::{read_to_string, write};
+
+    fn test_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir()
+            .join("blutils-taxonomy-directory-tests")
+            .join(format!("{}-{name}", std::process::id()));
+
+        create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn taxonomy(
+        taxid: u64,
+        rank: &str,
+        numeric_lineage: &str,
+        text_lineage: &str,
+    ) -> TaxonomyMapUnit {
+        TaxonomyMapUnit {
+            taxid,
+            aliases: Vec::new(),
+            rank: rank.to_owned(),
+            numeric_lineage: numeric_lineage.to_owned(),
+            text_lineage: text_lineage.to_owned(),
+            accessions: vec![Accession {
+                accession: format!("NR_{taxid}.1"),
+                oid: taxid.to_string(),
+            }],
+        }
+    }
+
+    /// A database built with filled ranks, including a strain record whose
+    /// lineage ends at the species
+    fn filled_database(directory: &Path) -> TaxonomiesDatabase {
+        let path = directory.join("filled.blutils.json");
+
+        write_taxonomies_database(
+            TaxonomiesMap {
+                blutils_version: "8.3.1".to_owned(),
+                ignore_taxids: None,
+                replace_rank: None,
+                drop_non_linnaean_taxonomies: Some(false),
+                fill_missing_ranks: Some(RankFillingStrategy::Inherit),
+                source_database: "mock-16S".to_owned(),
+                taxonomies: vec![
+                    taxonomy(
+                        511145,
+                        "strain",
+                        "d__2;k__2;p__1224;c__1236;o__91347;f__543;g__561;s__562",
+                        "d__bacteria;k__bacteria;p__pseudomonadota;c__gammaproteobacteria;o__enterobacterales;f__enterobacteriaceae;g__escherichia;s__escherichia-coli",
+                    ),
+                    taxonomy(
+                        1423,
+                        "s",
+                        "d__2;k__2;p__1239;c__91061;o__1385;f__186817;g__1386;s__1423",
+                        "d__bacteria;k__bacteria;p__bacillota;c__bacilli;o__bacillales;f__bacillaceae;g__bacillus;s__bacillus-subtilis",
+                    ),
+                ],
+            },
+            &path,
+            &TaxonomiesDatabaseFormat::Json,
+        )
+        .unwrap();
+
+        TaxonomiesDatabase::open(&path).unwrap()
+    }
+
+    #[test]
+    fn strain_records_of_filled_databases_are_written() {
+        let directory = test_directory("filled");
+        let database = filled_database(&directory);
+        let output_directory = directory.join("taxonomy");
+
+        generate_taxonomy_directory_from_blutils_db(
+            HashSet::from([511145, 1423]),
+            &database,
+            None,
+            output_directory.to_owned(),
+        )
+        .unwrap();
+
+        let nodes = read_to_string(output_directory.join("nodes.dmp")).unwrap();
+        let names = read_to_string(output_directory.join("names.dmp")).unwrap();
+
+        assert!(nodes.contains("511145\t|\t562\t|\tstrain\t|\t\t|\n"));
+        assert!(nodes.contains("562\t|\t561\t|\tspecies\t|\t\t|\n"));
+        assert!(nodes.contains("1423\t|\t1386\t|\tspecies\t|\t\t|\n"));
+        assert!(nodes.contains("2\t|\t1\t|\tsuperkingdom\t|\t\t|\n"));
+
+        assert!(names.contains(
+            "511145\t|\tescherichia-coli\t|\t\t|\tscientific name\t|\n"
+        ));
+
+        //
+        // The species taxid should be written once, even if the strain
+        // record is linked to it.
+        //
+        assert_eq!(
+            nodes
+                .lines()
+                .filter(|line| line.starts_with("562\t"))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn strain_names_are_copied_from_the_taxdump() {
+        let directory = test_directory("filled-taxdump");
+        let database = filled_database(&directory);
+        let output_directory = directory.join("taxonomy");
+
+        write(
+            directory.join("names.dmp"),
+            "562\t|\tEscherichia coli\t|\t\t|\tscientific name\t|\n\
+             511145\t|\tEscherichia coli str. K-12 substr. MG1655\t|\t\t|\tscientific name\t|\n\
+             9606\t|\tHomo sapiens\t|\t\t|\tscientific name\t|\n",
+        )
+        .unwrap();
+
+        generate_taxonomy_directory_from_blutils_db(
+            HashSet::from([511145]),
+            &database,
+            Some(&directory),
+            output_directory.to_owned(),
+        )
+        .unwrap();
+
+        let names = read_to_string(output_directory.join("names.dmp")).unwrap();
+
+        assert!(names.contains("511145\t|\tEscherichia coli str. K-12"));
+        assert!(names.contains("562\t|\tEscherichia coli\t|"));
+        assert!(!names.contains("9606"));
+    }
+}
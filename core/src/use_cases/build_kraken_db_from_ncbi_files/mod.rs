@@ -1,19 +1,42 @@
 mod generate_fasta_file;
 mod generate_taxonomies_file;
+mod generate_taxonomy_directory;
 
 use generate_fasta_file::*;
 use generate_taxonomies_file::*;
+use generate_taxonomy_directory::*;
+
+use crate::domain::dtos::taxonomies_database::TaxonomiesDatabase;
 
 use mycelium_base::utils::errors::MappedErrors;
 use std::{
+    collections::{HashMap, HashSet},
     fs::{create_dir_all, remove_dir_all, remove_file},
     path::PathBuf,
 };
 use tracing::warn;
 
+/// Build a Kraken2 library from the blast database
+///
+/// The `library.fna` and `prelim_map.txt` files are written to the output
+/// directory. If the taxdump directory is given, the `taxonomy` directory
+/// expected by `kraken2-build` is also written, containing the `names.dmp` and
+/// `nodes.dmp` files pruned to the taxids of the blast database and their
+/// ancestors. Merged taxids of the blast database are replaced by the new
+/// taxid.
+///
+/// If the Blutils database is given, it is used as the source of truth of the
+/// exported library. Sequences not found in the Blutils database, as the ones
+/// whose taxid was ignored or dropped, are skipped, and the taxid of each
+/// sequence is the one stored in the Blutils database. The `taxonomy`
+/// directory is built from the Blutils lineages, reflecting the ignored taxids
+/// and the replaced ranks of the Blutils database. Names are copied from the
+/// taxdump if given.
 pub fn build_kraken_db_from_ncbi_files(
     blast_database_path: &PathBuf,
     output_directory: PathBuf,
+    taxdump_directory_path: Option<PathBuf>,
+    taxonomies_database_path: Option<PathBuf>,
 ) -> Result<(), MappedErrors> {
     // ? -----------------------------------------------------------------------
     // ? Initialize files
@@ -36,12 +59,76 @@ pub fn build_kraken_db_from_ncbi_files(
     let output_sequences_file = output_directory.join("library.fna");
     let output_taxonomies_file = output_directory.join("prelim_map.txt");
 
+    let taxonomies_database = match &taxonomies_database_path {
+        Some(path) => Some(TaxonomiesDatabase::open(path)?),
+        None => None,
+    };
+
+    let accession_taxids = match &taxonomies_database {
+        Some(database) => {
+            let mut accession_taxids = HashMap::<String, u64>::new();
+
+            database.for_each(|record| {
+                for accession in &record.accessions {
+                    accession_taxids
+                        .insert(accession.accession.to_owned(), record.taxid);
+                }
+
+                Ok(())
+            })?;
+
+            Some(accession_taxids)
+        }
+        None => None,
+    };
+
+    let taxdump_tree = match (&taxdump_directory_path, &taxonomies_database) {
+        (Some(path), None) => Some(load_taxdump_tree(path)?),
+        _ => None,
+    };
+
     // ? -----------------------------------------------------------------------
     // ? Generate sequences database
     // ? -----------------------------------------------------------------------
 
-    let headers =
-        generate_fasta_file(blast_database_path, output_sequences_file)?;
+    let headers = generate_fasta_file(
+        blast_database_path,
+        output_sequences_file,
+        |accession, taxid| match (&accession_taxids, &taxdump_tree) {
+            (Some(accession_taxids), _) => {
+                accession_taxids.get(accession).copied()
+            }
+            (None, Some(tree)) => Some(tree.resolve_taxid(taxid)),
+            (None, None) => Some(taxid),
+        },
+    )?;
+
+    // ? -----------------------------------------------------------------------
+    // ? Generate taxonomy directory
+    // ? -----------------------------------------------------------------------
+
+    let taxids = headers
+        .iter()
+        .map(|(_, taxid)| *taxid as u64)
+        .collect::<HashSet<u64>>();
+
+    match (&taxonomies_database, &taxdump_directory_path, &taxdump_tree) {
+        (Some(database), path, _) => {
+            generate_taxonomy_directory_from_blutils_db(
+                taxids,
+                database,
+                path.as_deref(),
+                output_directory.join("taxonomy"),
+            )?
+        }
+        (None, Some(path), Some(tree)) => generate_taxonomy_directory(
+            taxids,
+            tree,
+            path,
+            output_directory.join("taxonomy"),
+        )?,
+        _ => (),
+    };
 
     // ? -----------------------------------------------------------------------
     // ? Generate taxonomies database
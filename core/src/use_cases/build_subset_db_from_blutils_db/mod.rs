@@ -0,0 +1,413 @@
+use super::shared::{
+    create_writer, sibling_output_file, stream_blast_sequences_batch,
+    write_error,
+};
+use crate::domain::dtos::{
+    linnaean_ranks::LinnaeanRank,
+    taxonomies_database::{
+        write_taxonomies_database, TaxonomiesDatabase, TaxonomiesDatabaseFormat,
+    },
+    taxonomies_map::{TaxonomiesMap, TaxonomyMapUnit},
+};
+
+use mycelium_base::utils::errors::{invalid_arg_err, MappedErrors};
+use slugify::slugify;
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+use tracing::{info, warn};
+
+/// A taxonomic clade used to select the taxonomies of a subset
+#[derive(Debug, Clone, PartialEq)]
+enum Clade {
+    /// A clade given by the taxid, matched against the numeric lineages
+    Taxid(u64),
+
+    /// A clade given as `rank__name`, matched against the text lineages
+    Ranked(String),
+}
+
+impl Clade {
+    /// Parse a clade given as a taxid or a `rank__name` string
+    ///
+    /// The rank and the name are normalized as in the text lineages of the
+    /// Blutils database, so `domain__Bacteria` matches `d__bacteria`.
+    fn parse(value: &str) -> Result<Clade, MappedErrors> {
+        let value = value.trim();
+
+        if let Ok(taxid) = value.parse::<u64>() {
+            return Ok(Clade::Taxid(taxid));
+        }
+
+        match value.split_once("__") {
+            Some((rank, name)) if !rank.is_empty() && !name.is_empty() => {
+                let rank = match rank.parse::<LinnaeanRank>() {
+                    Ok(res) => res.to_string(),
+                    Err(err) => return invalid_arg_err(err).as_error(),
+                };
+
+                Ok(Clade::Ranked(format!(
+                    "{rank}__{}",
+                    slugify!(name).replace("__", "_")
+                )))
+            }
+            _ => invalid_arg_err(format!(
+                "Invalid clade `{value}`. Clades should be given as taxids or as `rank__name` strings."
+            ))
+            .as_error(),
+        }
+    }
+
+    /// Check if the clade is part of the taxonomy lineage
+    fn contains(&self, taxonomy: &TaxonomyMapUnit) -> bool {
+        match self {
+            Clade::Taxid(taxid) => {
+                taxonomy.taxid == *taxid
+                    || taxonomy.numeric_lineage.split(';').any(|element| {
+                        element
+                            .split_once("__")
+                            .and_then(|(_, value)| value.parse::<u64>().ok())
+                            == Some(*taxid)
+                    })
+            }
+            Clade::Ranked(ranked_name) => taxonomy
+                .text_lineage
+                .split(';')
+                .any(|element| element == ranked_name),
+        }
+    }
+}
+
+/// Build a subset of the Blutils database by taxonomic clades
+///
+/// Taxonomies are kept if any of the `include` clades is part of their
+/// lineage, or if no `include` clades are given, and none of the `exclude`
+/// clades is part of their lineage. Clades are given as taxids or as
+/// `rank__name` strings.
+///
+/// The subset is written to `output_file_path` with the `.blutils.bin` or the
+/// `.blutils.json` suffix given the output format, keeping the options of the
+/// source database. If the blast database is given, the sequences of the kept
+/// accessions are pulled using `blastdbcmd -entry_batch` and written with the
+/// `.fasta` suffix, together with a `.taxid_map.txt` file to be used with the
+/// `makeblastdb -taxid_map` option. The accessions list used as the entry
+/// batch is kept with the `.accessions.txt` suffix.
+#[tracing::instrument(
+    name = "Build subset DB from Blutils DB",
+    skip(taxonomies_database_path, blast_database_path, output_file_path)
+)]
+pub fn build_subset_db_from_blutils_db(
+    taxonomies_database_path: &Path,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    blast_database_path: Option<PathBuf>,
+    output_file_path: PathBuf,
+    output_format: TaxonomiesDatabaseFormat,
+) -> Result<(), MappedErrors> {
+    // ? -----------------------------------------------------------------------
+    // ? Parse clades
+    // ? -----------------------------------------------------------------------
+
+    let include = include
+        .iter()
+        .map(|clade| Clade::parse(clade))
+        .collect::<Result<Vec<Clade>, MappedErrors>>()?;
+
+    let exclude = exclude
+        .iter()
+        .map(|clade| Clade::parse(clade))
+        .collect::<Result<Vec<Clade>, MappedErrors>>()?;
+
+    if include.is_empty() && exclude.is_empty() {
+        return invalid_arg_err(
+            "At least one include or exclude clade should be given",
+        )
+        .as_error();
+    }
+
+    // ? -----------------------------------------------------------------------
+    // ? Select the taxonomies of the subset
+    // ? -----------------------------------------------------------------------
+
+    let taxonomies_database =
+        TaxonomiesDatabase::open(taxonomies_database_path)?;
+
+    let mut taxonomies = Vec::<TaxonomyMapUnit>::new();
+    let mut total = 0;
+
+    taxonomies_database.for_each(|record| {
+        total += 1;
+
+        if (include.is_empty()
+            || include.iter().any(|clade| clade.contains(record)))
+            && !exclude.iter().any(|clade| clade.contains(record))
+        {
+            taxonomies.push(record.to_owned());
+        }
+
+        Ok(())
+    })?;
+
+    info!("{} of {total} taxonomies selected", taxonomies.len());
+
+    if taxonomies.is_empty() {
+        warn!("No taxonomies matched the given clades");
+    }
+
+    // ? -----------------------------------------------------------------------
+    // ? Write the subset sequences
+    // ? -----------------------------------------------------------------------
+
+    if let Some(blast_database_path) = blast_database_path {
+        write_subset_sequences(
+            &taxonomies,
+            &blast_database_path,
+            &output_file_path,
+        )?;
+    }
+
+    // ? -----------------------------------------------------------------------
+    // ? Write the subset database
+    // ? -----------------------------------------------------------------------
+
+    let metadata = taxonomies_database.metadata();
+
+    write_taxonomies_database(
+        TaxonomiesMap {
+            blutils_version: env!("CARGO_PKG_VERSION").to_string(),
+            ignore_taxids: metadata.ignore_taxids.to_owned(),
+            replace_rank: metadata.replace_rank.to_owned(),
+            drop_non_linnaean_taxonomies: metadata.drop_non_linnaean_taxonomies,
+            fill_missing_ranks: metadata.fill_missing_ranks.to_owned(),
+            source_database: metadata.source_database.to_owned(),
+            taxonomies,
+        },
+        &sibling_output_file(
+            &output_file_path,
+            &format!("blutils.{}", output_format.extension()),
+        ),
+        &output_format,
+    )
+}
+
+/// Write the sequences of the subset accessions
+///
+/// Sequences are written with the accession as the header, followed by the
+/// taxid map used by `makeblastdb` to assign the subset taxids.
+fn write_subset_sequences(
+    taxonomies: &[TaxonomyMapUnit],
+    blast_database_path: &PathBuf,
+    output_file_path: &Path,
+) -> Result<(), MappedErrors> {
+    let accession_taxids = taxonomies
+        .iter()
+        .flat_map(|taxonomy| {
+            taxonomy
+                .accessions
+                .iter()
+                .map(|accession| (accession.accession.as_str(), taxonomy.taxid))
+        })
+        .collect::<HashMap<&str, u64>>();
+
+    let accessions_file =
+        sibling_output_file(output_file_path, "accessions.txt");
+    let sequences_file = sibling_output_file(output_file_path, "fasta");
+    let taxid_map_file = sibling_output_file(output_file_path, "taxid_map.txt");
+
+    let mut accessions_writer = create_writer(&accessions_file)?;
+
+    //
+    // Accessions are written sorted to produce reproducible files.
+    //
+    let mut accessions = accession_taxids.keys().collect::<Vec<&&str>>();
+    accessions.sort();
+
+    accessions
+        .iter()
+        .try_for_each(|accession| writeln!(accessions_writer, "{accession}"))
+        .and_then(|_| accessions_writer.flush())
+        .map_err(|err| write_error(&accessions_file, err))?;
+
+    drop(accessions_writer);
+
+    let mut sequences_writer = create_writer(&sequences_file)?;
+    let mut taxid_map_writer = create_writer(&taxid_map_file)?;
+    let mut written = 0;
+
+    stream_blast_sequences_batch(
+        blast_database_path,
+        &accessions_file,
+        |item| {
+            let taxid = match accession_taxids.get(item.accession) {
+                Some(taxid) => taxid,
+                None => return Ok(()),
+            };
+
+            writeln!(
+                sequences_writer,
+                ">{}\n{}",
+                item.accession, item.sequence
+            )
+            .map_err(|err| write_error(&sequences_file, err))?;
+
+            writeln!(taxid_map_writer, "{} {taxid}", item.accession)
+                .map_err(|err| write_error(&taxid_map_file, err))?;
+
+            written += 1;
+
+            Ok(())
+        },
+    )?;
+
+    sequences_writer
+        .flush()
+        .map_err(|err| write_error(&sequences_file, err))?;
+
+    taxid_map_writer
+        .flush()
+        .map_err(|err| write_error(&taxid_map_file, err))?;
+
+    if written < accession_taxids.len() {
+        warn!(
+            "{} accessions of the subset were not found in the blast database",
+            accession_taxids.len() - written
+        );
+    }
+
+    info!("{written} sequences written to {:?}", sequences_file);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::dtos::taxonomies_map::Accession;
+    use std::fs::create_dir_all;
+
+    fn test_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir()
+            .join("blutils-subset-tests")
+            .join(format!("{}-{name}", std::process::id()));
+
+        create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn taxonomy(
+        taxid: u64,
+        numeric_lineage: &str,
+        text_lineage: &str,
+    ) -> TaxonomyMapUnit {
+        TaxonomyMapUnit {
+            taxid,
+            aliases: Vec::new(),
+            rank: "s".to_owned(),
+            numeric_lineage: numeric_lineage.to_owned(),
+            text_lineage: text_lineage.to_owned(),
+            accessions: vec![Accession {
+                accession: format!("NR_{taxid}.1"),
+                oid: taxid.to_string(),
+            }],
+        }
+    }
+
+    fn subtilis() -> TaxonomyMapUnit {
+        taxonomy(
+            1423,
+            "d__2;p__1239;c__91061;o__1385;f__186817;g__1386;s__1423",
+            "d__bacteria;p__bacillota;c__bacilli;o__bacillales;f__bacillaceae;g__bacillus;s__bacillus-subtilis",
+        )
+    }
+
+    fn coli() -> TaxonomyMapUnit {
+        taxonomy(
+            562,
+            "d__2;p__1224;c__1236;o__91347;f__543;g__561;s__562",
+            "d__bacteria;p__pseudomonadota;c__gammaproteobacteria;o__enterobacterales;f__enterobacteriaceae;g__escherichia;s__escherichia-coli",
+        )
+    }
+
+    fn cerevisiae() -> TaxonomyMapUnit {
+        taxonomy(
+            4932,
+            "d__2759;k__4751;p__4890;c__4891;o__4892;f__4893;g__4930;s__4932",
+            "d__eukaryota;k__fungi;p__ascomycota;c__saccharomycetes;o__saccharomycetales;f__saccharomycetaceae;g__saccharomyces;s__saccharomyces-cerevisiae",
+        )
+    }
+
+    #[test]
+    fn clades_are_matched_by_taxid() {
+        assert_eq!(Clade::parse(" 1239 ").unwrap(), Clade::Taxid(1239));
+
+        assert!(Clade::parse("1239").unwrap().contains(&subtilis()));
+        assert!(Clade::parse("1423").unwrap().contains(&subtilis()));
+        assert!(!Clade::parse("1239").unwrap().contains(&coli()));
+    }
+
+    #[test]
+    fn ranked_clades_are_normalized() {
+        let clade = Clade::parse("domain__Bacteria").unwrap();
+
+        assert_eq!(clade, Clade::Ranked("d__bacteria".to_owned()));
+        assert!(clade.contains(&subtilis()));
+        assert!(!clade.contains(&cerevisiae()));
+
+        assert_eq!(
+            Clade::parse("species__Bacillus subtilis").unwrap(),
+            Clade::Ranked("s__bacillus-subtilis".to_owned())
+        );
+    }
+
+    #[test]
+    fn invalid_clades_are_rejected() {
+        for clade in ["bacteria", "d__", "__bacteria", ""] {
+            assert!(Clade::parse(clade).is_err(), "{clade}");
+        }
+    }
+
+    #[test]
+    fn excluded_clades_are_removed_from_included_clades() {
+        let directory = test_directory("include-exclude");
+        let source = directory.join("source.blutils.json");
+
+        write_taxonomies_database(
+            TaxonomiesMap {
+                blutils_version: "8.3.1".to_owned(),
+                ignore_taxids: None,
+                replace_rank: None,
+                drop_non_linnaean_taxonomies: Some(false),
+                fill_missing_ranks: None,
+                source_database: "mock-16S".to_owned(),
+                taxonomies: vec![subtilis(), coli(), cerevisiae()],
+            },
+            &source,
+            &TaxonomiesDatabaseFormat::Json,
+        )
+        .unwrap();
+
+        build_subset_db_from_blutils_db(
+            &source,
+            vec!["d__Bacteria".to_owned()],
+            vec!["1239".to_owned()],
+            None,
+            directory.join("subset"),
+            TaxonomiesDatabaseFormat::Json,
+        )
+        .unwrap();
+
+        let mut taxids = Vec::<u64>::new();
+
+        TaxonomiesDatabase::open(&directory.join("subset.blutils.json"))
+            .unwrap()
+            .for_each(|record| {
+                taxids.push(record.taxid);
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(taxids, vec![562]);
+    }
+}
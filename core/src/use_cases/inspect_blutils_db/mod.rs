@@ -0,0 +1,92 @@
+use crate::domain::dtos::{
+    taxonomies_database::TaxonomiesDatabase,
+    taxonomies_report::{ReportFormat, TaxonomiesReport},
+};
+
+use mycelium_base::utils::errors::{use_case_err, MappedErrors};
+use std::{
+    fs::{remove_file, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+use tracing::warn;
+
+/// Inspect the Blutils taxonomies database
+///
+/// Report the options used to build the database, the taxa and accessions
+/// counts per rank, and the lineage depth distribution. Taxids with non
+/// Linnaean ranks, lineages containing `taxid-<n>` placeholder names, and
+/// duplicate accessions are listed as issues.
+///
+/// The report is printed to the standard output if no output file is given.
+pub fn inspect_blutils_db(
+    taxonomies_database_path: &Path,
+    output_file: Option<PathBuf>,
+    report_format: ReportFormat,
+) -> Result<(), MappedErrors> {
+    // ? -----------------------------------------------------------------------
+    // ? Build the report
+    // ? -----------------------------------------------------------------------
+
+    let taxonomies_database =
+        TaxonomiesDatabase::open(taxonomies_database_path)?;
+
+    let report = TaxonomiesReport::from_database(&taxonomies_database)?;
+
+    if report.issues() > 0 {
+        warn!(
+            "{} issues found in the taxonomies database. See the report for details.",
+            report.issues()
+        );
+    }
+
+    let report_content = match report_format {
+        ReportFormat::Text => report.to_text(),
+        ReportFormat::Json => {
+            serde_json::to_string_pretty(&report).map_err(|err| {
+                use_case_err(format!(
+                    "Unexpected error on serialize report: {err}"
+                ))
+            })?
+        }
+    };
+
+    // ? -----------------------------------------------------------------------
+    // ? Write the report output
+    // ? -----------------------------------------------------------------------
+
+    let output_file = match output_file {
+        Some(mut path) => {
+            match report_format {
+                ReportFormat::Text => path.set_extension("txt"),
+                ReportFormat::Json => path.set_extension("json"),
+            };
+
+            path
+        }
+        None => {
+            println!("{}", report_content.trim_end());
+            return Ok(());
+        }
+    };
+
+    if output_file.exists() {
+        warn!("Output file already exists. Removing it.");
+        remove_file(&output_file).map_err(|err| {
+            use_case_err(format!("Could not remove file given {err}"))
+        })?;
+    }
+
+    let mut file = File::create(&output_file).map_err(|err| {
+        use_case_err(format!(
+            "Error on persist report into {:?}: {err}",
+            output_file
+        ))
+    })?;
+
+    file.write_all(report_content.as_bytes()).map_err(|err| {
+        use_case_err(format!("Unexpected error on write report file: {err}"))
+    })?;
+
+    Ok(())
+}
@@ -1,18 +1,28 @@
 mod build_blutils_db_from_ncbi_files;
+mod build_classifier_db_from_blutils_db;
 mod build_consensus_identities;
+mod build_feature_table;
 mod build_kraken_db_from_ncbi_files;
 mod build_qiime_db_from_blutils_db;
+mod build_subset_db_from_blutils_db;
 mod check_host_requirements;
+mod inspect_blutils_db;
 mod parse_consensus_as_tabular;
 mod run_blast_and_build_consensus;
 mod shared;
+mod summarize_consensus;
 mod write_blutils_output;
 
 pub use build_blutils_db_from_ncbi_files::*;
+pub use build_classifier_db_from_blutils_db::*;
 pub use build_consensus_identities::*;
+pub use build_feature_table::*;
 pub use build_kraken_db_from_ncbi_files::*;
 pub use build_qiime_db_from_blutils_db::*;
+pub use build_subset_db_from_blutils_db::*;
 pub use check_host_requirements::*;
+pub use inspect_blutils_db::*;
 pub use parse_consensus_as_tabular::*;
 pub use run_blast_and_build_consensus::*;
+pub use summarize_consensus::*;
 pub use write_blutils_output::*;
@@ -104,6 +104,8 @@ pub fn parse_consensus_as_tabular(
                 query,
                 taxon,
                 run_id: _,
+                abundance: _,
+                error: _,
             } => match taxon {
                 Some(res) => res,
                 None => {
@@ -0,0 +1,251 @@
+use mycelium_base::utils::errors::{execution_err, MappedErrors};
+use std::{
+    collections::HashMap,
+    fs::{read_dir, remove_dir_all, rename, write, File, OpenOptions},
+    io::{copy, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// Persist the blast results of a single chunk
+///
+/// Results are written to a temporary file and renamed after completion, so
+/// only complete chunks are available in the `chunks_dir`.
+pub(super) fn write_blast_chunk(
+    chunks_dir: &Path,
+    index: usize,
+    content: String,
+) -> Result<(), MappedErrors> {
+    let chunk_file = chunks_dir.join(format!("{index:08}.out"));
+    let temp_file = chunk_file.with_extension("out.tmp");
+
+    write(&temp_file, content).map_err(|err| {
+        execution_err(format!(
+            "Unexpected error on write blast chunk {index}: {err}"
+        ))
+    })?;
+
+    rename(&temp_file, &chunk_file).map_err(|err| {
+        execution_err(format!(
+            "Unexpected error on persist blast chunk {index}: {err}"
+        ))
+    })
+}
+
+/// Append complete chunks of an interrupted run to the blast output
+///
+/// Chunks are appended in any order. The input order is restored when the
+/// resumed run is merged.
+pub(super) fn fold_blast_chunks(
+    chunks_dir: &Path,
+    blast_out_file: &Path,
+) -> Result<(), MappedErrors> {
+    if !chunks_dir.exists() {
+        return Ok(());
+    }
+
+    let mut output = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(blast_out_file)
+        .map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on open blast output file: {err}"
+            ))
+        })?;
+
+    //
+    // Outputs interrupted during the write may end with a truncated row. The
+    // row is terminated before appending the chunks, so the first chunk row
+    // is not joined to the truncated row, which is discarded on resume.
+    //
+    let mut last_byte = [0; 1];
+
+    let ends_with_truncated_row = output
+        .seek(SeekFrom::End(-1))
+        .and_then(|_| output.read_exact(&mut last_byte))
+        .map(|_| last_byte[0] != b'\n')
+        .unwrap_or(false);
+
+    if ends_with_truncated_row {
+        output.write_all(b"\n").map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on write blast output file: {err}"
+            ))
+        })?;
+    }
+
+    for chunk_file in list_blast_chunks(chunks_dir)? {
+        copy_file_content(&chunk_file, &mut output)?;
+    }
+
+    remove_chunks_dir(chunks_dir)
+}
+
+/// Merge the blast chunks into the blast output file in the input order
+///
+/// Chunks are concatenated by index when the blast output is empty, given
+/// chunks are built from consecutive input sequences. Otherwise, results of
+/// resumed runs are grouped by query and written following the `headers`
+/// order. Returns the MD5 checksum of the resulting file.
+pub(super) fn merge_blast_chunks(
+    chunks_dir: &Path,
+    blast_out_file: &Path,
+    headers: &[String],
+    query_index: usize,
+) -> Result<String, MappedErrors> {
+    let chunk_files = match chunks_dir.exists() {
+        true => list_blast_chunks(chunks_dir)?,
+        false => Vec::new(),
+    };
+
+    let has_previous_results = blast_out_file
+        .metadata()
+        .map(|metadata| metadata.len() > 0)
+        .unwrap_or(false);
+
+    let merged_file = blast_out_file.with_extension("out.merge");
+
+    let mut output =
+        BufWriter::new(File::create(&merged_file).map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on create merged blast output: {err}"
+            ))
+        })?);
+
+    match has_previous_results {
+        false => {
+            for chunk_file in &chunk_files {
+                copy_file_content(chunk_file, &mut output)?;
+            }
+        }
+        true => {
+            let mut grouped_lines = HashMap::<String, Vec<String>>::new();
+
+            for file in [blast_out_file.to_path_buf()]
+                .iter()
+                .chain(chunk_files.iter())
+            {
+                let reader =
+                    BufReader::new(File::open(file).map_err(|err| {
+                        execution_err(format!(
+                            "Unexpected error on open blast results: {err}"
+                        ))
+                    })?);
+
+                for line in reader.lines() {
+                    let line = line.map_err(|err| {
+                        execution_err(format!(
+                            "Unexpected error on read blast results: {err}"
+                        ))
+                    })?;
+
+                    if let Some(query) = line.split('\t').nth(query_index) {
+                        grouped_lines
+                            .entry(query.to_string())
+                            .or_default()
+                            .push(line);
+                    }
+                }
+            }
+
+            for header in headers {
+                if let Some(lines) = grouped_lines.remove(header) {
+                    for line in lines {
+                        writeln!(output, "{line}").map_err(|err| {
+                            execution_err(format!(
+                                "Unexpected error on write merged blast output: {err}"
+                            ))
+                        })?;
+                    }
+                }
+            }
+        }
+    }
+
+    output.flush().map_err(|err| {
+        execution_err(format!(
+            "Unexpected error on write merged blast output: {err}"
+        ))
+    })?;
+
+    rename(&merged_file, blast_out_file).map_err(|err| {
+        execution_err(format!(
+            "Unexpected error on replace blast output file: {err}"
+        ))
+    })?;
+
+    if chunks_dir.exists() {
+        remove_chunks_dir(chunks_dir)?;
+    }
+
+    file_checksum(blast_out_file)
+}
+
+/// Calculate the MD5 checksum of a file
+fn file_checksum(path: &Path) -> Result<String, MappedErrors> {
+    let mut reader = BufReader::new(File::open(path).map_err(|err| {
+        execution_err(format!(
+            "Unexpected error on open blast output file: {err}"
+        ))
+    })?);
+
+    let mut context = md5::Context::new();
+    let mut buffer = [0; 8192];
+
+    loop {
+        let size = reader.read(&mut buffer).map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on read blast output file: {err}"
+            ))
+        })?;
+
+        if size == 0 {
+            break;
+        }
+
+        context.consume(&buffer[..size]);
+    }
+
+    Ok(format!("{:x}", context.compute()))
+}
+
+/// List the complete chunk files sorted by index
+fn list_blast_chunks(chunks_dir: &Path) -> Result<Vec<PathBuf>, MappedErrors> {
+    let mut chunk_files = read_dir(chunks_dir)
+        .map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on read blast chunks directory: {err}"
+            ))
+        })?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "out"))
+        .collect::<Vec<PathBuf>>();
+
+    chunk_files.sort();
+
+    Ok(chunk_files)
+}
+
+fn copy_file_content(
+    source: &Path,
+    target: &mut impl Write,
+) -> Result<(), MappedErrors> {
+    let mut reader = File::open(source).map_err(|err| {
+        execution_err(format!("Unexpected error on open blast chunk: {err}"))
+    })?;
+
+    copy(&mut reader, target).map_err(|err| {
+        execution_err(format!("Unexpected error on merge blast chunk: {err}"))
+    })?;
+
+    Ok(())
+}
+
+fn remove_chunks_dir(chunks_dir: &Path) -> Result<(), MappedErrors> {
+    remove_dir_all(chunks_dir).map_err(|err| {
+        execution_err(format!(
+            "Unexpected error on remove blast chunks directory: {err}"
+        ))
+    })
+}
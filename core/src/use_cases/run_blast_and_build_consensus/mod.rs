@@ -1,23 +1,39 @@
+mod merge_blast_chunks;
+mod resume_blast_output;
 mod run_parallel_blast;
 
+use merge_blast_chunks::*;
+use resume_blast_output::*;
 use run_parallel_blast::*;
 
-use super::{build_consensus_identities, write_blutils_output, OutputFormat};
+use super::{
+    build_consensus_identities, stream_consensus_identities,
+    validate_stream_format, write_blutils_output, write_blutils_output_stream,
+    OutputFormat,
+};
 use crate::domain::{
     dtos::{
         blast_builder::BlastBuilder, consensus_strategy::ConsensusStrategy,
-        file_or_stdin::FileOrStdin, taxon::CustomTaxon,
+        cutoff_registry::CutoffRegistry, file_or_stdin::FileOrStdin,
+        taxon::CustomTaxon,
     },
     entities::execute_blastn::ExecuteBlastn,
 };
 
 use mycelium_base::utils::errors::MappedErrors;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 /// Run parallel blast and build taxonomies consensus
 #[tracing::instrument(
     name = "Run Blast with Consensus",
-    skip(blast_execution_repo, blast_config, overwrite, strategy, use_taxid)
+    skip(
+        blast_execution_repo,
+        blast_config,
+        overwrite,
+        resume,
+        strategy,
+        use_taxid
+    )
 )]
 pub fn run_blast_and_build_consensus(
     input_sequences: FileOrStdin,
@@ -27,12 +43,43 @@ pub fn run_blast_and_build_consensus(
     blast_config: BlastBuilder,
     blast_execution_repo: &dyn ExecuteBlastn,
     overwrite: &bool,
+    resume: &bool,
     threads: usize,
     strategy: ConsensusStrategy,
     use_taxid: Option<bool>,
     out_format: OutputFormat,
     custom_taxon_values: Option<CustomTaxon>,
+    accessions_file: Option<PathBuf>,
+    stream_batch_size: Option<usize>,
 ) -> Result<bool, MappedErrors> {
+    if stream_batch_size.is_some() {
+        validate_stream_format(&out_format)?;
+    }
+
+    // ? -----------------------------------------------------------------------
+    // ? Resolve the taxon cutoffs
+    //
+    // Cutoffs are recorded in the output config. Cutoffs not resolved by the
+    // caller are resolved using the registry shipped with Blutils, failing
+    // before blast runs case the taxon is unknown.
+    //
+    // ? -----------------------------------------------------------------------
+
+    let blast_config = match blast_config.taxon_cutoffs {
+        Some(_) => blast_config,
+        None => {
+            let taxon_cutoffs = CutoffRegistry::shipped()?
+                .resolve(&blast_config.taxon, custom_taxon_values)?;
+
+            blast_config.with_taxon_cutoffs(taxon_cutoffs)
+        }
+    };
+
+    let custom_taxon_values = blast_config
+        .taxon_cutoffs
+        .as_ref()
+        .map(|taxon_cutoffs| taxon_cutoffs.cutoffs.to_owned());
+
     // ? -----------------------------------------------------------------------
     // ? Execute parallel blast
     // ? -----------------------------------------------------------------------
@@ -43,13 +90,42 @@ pub fn run_blast_and_build_consensus(
         blast_config.to_owned(),
         blast_execution_repo,
         overwrite,
+        resume,
         threads,
     )?;
 
+    let blast_config = blast_config.with_out_checksum(output.checksum.clone());
+
     // ? -----------------------------------------------------------------------
     // ? Build consensus
     // ? -----------------------------------------------------------------------
 
+    //
+    // The streaming mode writes results of each batch of queries as soon as
+    // they are built, keeping the memory usage bounded for large outputs.
+    //
+    if let Some(batch_size) = stream_batch_size {
+        let stream = stream_consensus_identities(
+            output,
+            Path::new(input_taxonomies),
+            blast_config.taxon.to_owned(),
+            strategy,
+            use_taxid,
+            custom_taxon_values,
+            accessions_file.as_deref(),
+        )?
+        .with_batch_size(batch_size)?;
+
+        write_blutils_output_stream(
+            stream,
+            Some(blast_config),
+            None,
+            blutils_out_file,
+        )?;
+
+        return Ok(true);
+    }
+
     let blast_output = build_consensus_identities(
         output,
         Path::new(input_taxonomies),
@@ -57,11 +133,13 @@ pub fn run_blast_and_build_consensus(
         strategy,
         use_taxid,
         custom_taxon_values,
+        accessions_file.as_deref(),
     )?;
 
     if let Err(err) = write_blutils_output(
         blast_output.to_owned(),
         Some(blast_config),
+        None,
         blutils_out_file,
         out_format,
     ) {
@@ -0,0 +1,150 @@
+use crate::domain::dtos::blast_tabular_format::{
+    BlastField, BlastTabularFormat,
+};
+
+use mycelium_base::utils::errors::{execution_err, MappedErrors};
+use std::{
+    collections::HashSet,
+    fs::{read_to_string, rename, write, File},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+/// Collect the queries already processed by an interrupted blast run
+///
+/// Queries of each finished chunk are registered in the `manifest_file`. Blast
+/// results of queries not registered in the manifest (e.g. results of a chunk
+/// interrupted during the write) are removed from the `blast_out_file`, so
+/// such queries could be submitted again without duplicated results.
+///
+/// Outputs produced without manifest are resumed from the queries with hits,
+/// being queries without hits submitted again.
+pub(super) fn resume_blast_output(
+    blast_out_file: &Path,
+    manifest_file: &Path,
+    columns: &BlastTabularFormat,
+) -> Result<HashSet<String>, MappedErrors> {
+    if !blast_out_file.exists() {
+        return Ok(HashSet::new());
+    }
+
+    let query_index = columns.position(&BlastField::Query).unwrap_or(0);
+
+    let query_of = |line: &str| -> Option<String> {
+        line.split('\t')
+            .nth(query_index)
+            .map(|query| query.to_string())
+    };
+
+    // ? -----------------------------------------------------------------------
+    // ? Resume from blast output only
+    // ? -----------------------------------------------------------------------
+
+    if !manifest_file.exists() {
+        tracing::warn!(
+            "Blast manifest not found. Resuming from queries with hits in {:?}",
+            blast_out_file
+        );
+
+        let content = read_to_string(blast_out_file).map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on read blast output file: {err}"
+            ))
+        })?;
+
+        let completed = content
+            .lines()
+            .filter_map(query_of)
+            .collect::<HashSet<String>>();
+
+        //
+        // The manifest is seeded with the recovered queries to allow further
+        // resumes of the same output.
+        //
+        write(
+            manifest_file,
+            completed
+                .iter()
+                .map(|query| format!("{query}\n"))
+                .collect::<String>(),
+        )
+        .map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on write blast manifest file: {err}"
+            ))
+        })?;
+
+        return Ok(completed);
+    }
+
+    // ? -----------------------------------------------------------------------
+    // ? Resume from manifest
+    // ? -----------------------------------------------------------------------
+
+    let completed = read_to_string(manifest_file)
+        .map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on read blast manifest file: {err}"
+            ))
+        })?
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string())
+        .collect::<HashSet<String>>();
+
+    let resumed_file = blast_out_file.with_extension("out.resume");
+
+    let reader = BufReader::new(File::open(blast_out_file).map_err(|err| {
+        execution_err(format!(
+            "Unexpected error on open blast output file: {err}"
+        ))
+    })?);
+
+    let mut writer =
+        BufWriter::new(File::create(&resumed_file).map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on create resumed blast output file: {err}"
+            ))
+        })?);
+
+    let mut dropped = 0;
+
+    for line in reader.lines() {
+        let line = line.map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on read blast output file: {err}"
+            ))
+        })?;
+
+        match query_of(&line) {
+            Some(query) if completed.contains(&query) => {
+                writeln!(writer, "{line}").map_err(|err| {
+                    execution_err(format!(
+                        "Unexpected error on write resumed blast output: {err}"
+                    ))
+                })?;
+            }
+            _ => dropped += 1,
+        }
+    }
+
+    writer.flush().map_err(|err| {
+        execution_err(format!(
+            "Unexpected error on write resumed blast output: {err}"
+        ))
+    })?;
+
+    rename(&resumed_file, blast_out_file).map_err(|err| {
+        execution_err(format!(
+            "Unexpected error on replace blast output file: {err}"
+        ))
+    })?;
+
+    if dropped > 0 {
+        tracing::warn!(
+            "{dropped} blast results of unfinished chunks were discarded"
+        );
+    }
+
+    Ok(completed)
+}
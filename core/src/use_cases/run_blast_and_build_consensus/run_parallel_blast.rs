@@ -1,7 +1,12 @@
+use super::{
+    fold_blast_chunks, merge_blast_chunks, resume_blast_output,
+    write_blast_chunk,
+};
 use crate::{
     domain::{
         dtos::{
             blast_builder::BlastBuilder,
+            blast_tabular_format::{BlastField, BlastTabularFormat},
             file_or_stdin::{FileOrStdin, Sequence},
             parallel_blast_output::ParallelBlastOutput,
         },
@@ -10,11 +15,15 @@ use crate::{
     use_cases::shared::{validate_blast_database, write_or_append_to_file},
 };
 
-use mycelium_base::utils::errors::MappedErrors;
+use mycelium_base::utils::errors::{
+    execution_err, invalid_arg_err, MappedErrors,
+};
 use rayon::iter::{ParallelBridge, ParallelIterator};
 use std::{
-    fs::{create_dir, remove_file},
+    collections::{HashMap, HashSet},
+    fs::{create_dir_all, remove_dir_all, remove_file},
     path::PathBuf,
+    sync::Mutex,
 };
 
 /// Run blast in parallel mode
@@ -29,6 +38,7 @@ use std::{
         blast_config,
         blast_execution_repo,
         overwrite,
+        resume,
         threads,
     )
 )]
@@ -38,24 +48,29 @@ pub(super) fn run_parallel_blast(
     blast_config: BlastBuilder,
     blast_execution_repo: &dyn ExecuteBlastn,
     overwrite: &bool,
+    resume: &bool,
     threads: usize,
 ) -> Result<ParallelBlastOutput, MappedErrors> {
     // ? ----------------------------------------------------------------------
     // ? Validate blast database
     // ? ----------------------------------------------------------------------
 
-    validate_blast_database(&PathBuf::from(
-        blast_config.subject_reads.to_owned(),
-    ))?;
+    if blast_execution_repo.requires_blast_database() {
+        validate_blast_database(&PathBuf::from(
+            blast_config.subject_reads.to_owned(),
+        ))?;
+    }
 
     // ? ----------------------------------------------------------------------
-    // ? Build thread pool
+    // ? Validate the blast output format
     // ? ----------------------------------------------------------------------
 
-    let pool = rayon::ThreadPoolBuilder::new()
-        .num_threads(threads)
-        .build()
-        .unwrap();
+    let columns = blast_config
+        .out_format
+        .parse::<BlastTabularFormat>()
+        .map_err(invalid_arg_err)?;
+
+    columns.validate()?;
 
     // ? ----------------------------------------------------------------------
     // ? Build output file
@@ -63,106 +78,252 @@ pub(super) fn run_parallel_blast(
 
     let mut out_dir_path = PathBuf::from(blast_out_file);
     out_dir_path.set_extension("out");
-    let out_dir = out_dir_path.parent().unwrap();
-
-    if !out_dir.exists() {
-        let _ = create_dir(out_dir);
+    if let Some(out_dir) = out_dir_path.parent() {
+        if !out_dir.exists() {
+            create_dir_all(out_dir).map_err(|err| {
+                execution_err(format!("Could not create directory given {err}"))
+            })?;
+        }
     }
 
+    let manifest_path = out_dir_path.with_extension("out.manifest");
+    let chunks_dir = out_dir_path.with_extension("out.chunks");
+
     tracing::info!("");
     tracing::info!("Blast output file:");
     tracing::info!("\t{:?}", out_dir_path);
     tracing::info!("");
 
-    if out_dir_path.exists() {
+    //
+    // Queries of previous runs are only reused in resume mode. Otherwise, the
+    // manifest is removed to avoid mixing queries of distinct runs.
+    //
+    let completed_queries = match resume {
+        true => {
+            fold_blast_chunks(&chunks_dir, &out_dir_path)?;
+            resume_blast_output(&out_dir_path, &manifest_path, &columns)?
+        }
+        false => HashSet::new(),
+    };
+
+    if !resume && out_dir_path.exists() {
         if !overwrite {
-            tracing::error!(
-                "Could not overwrite existing file {:?} when overwrite option is `false`.", 
+            return execution_err(format!(
+                "Could not overwrite existing file {:?} when overwrite option is `false`.",
                 out_dir_path
-            );
-
-            std::process::exit(1);
+            ))
+            .as_error();
         }
 
-        match remove_file(out_dir_path.clone()) {
-            Err(err) => panic!("Could not remove file given {err}"),
-            Ok(_) => tracing::warn!("Output file overwritten!"),
-        };
+        remove_file(out_dir_path.clone()).map_err(|err| {
+            execution_err(format!("Could not remove file given {err}"))
+        })?;
+
+        tracing::warn!("Output file overwritten!");
     };
 
+    if !resume && manifest_path.exists() {
+        remove_file(manifest_path.clone()).map_err(|err| {
+            execution_err(format!("Could not remove file given {err}"))
+        })?;
+    }
+
+    if !resume && chunks_dir.exists() {
+        remove_dir_all(chunks_dir.clone()).map_err(|err| {
+            execution_err(format!("Could not remove directory given {err}"))
+        })?;
+    }
+
     // ? ----------------------------------------------------------------------
     // ? Process input sequences
     // ? ----------------------------------------------------------------------
 
-    let chunk_size = 50;
-    let (writer, file) = write_or_append_to_file(out_dir_path.as_path());
+    create_dir_all(chunks_dir.clone()).map_err(|err| {
+        execution_err(format!("Could not create directory given {err}"))
+    })?;
+
+    let (manifest_writer, manifest_file) =
+        write_or_append_to_file(manifest_path.as_path());
     let mut headers: Vec<String> = Vec::new();
+    let mut abundances: HashMap<String, u64> = HashMap::new();
 
     let source_sequences =
         input_sequences.sequence_content().map_err(|err| {
-            panic!("Could not read input sequences: {err}");
+            execution_err(format!("Could not read input sequences: {err}"))
         })?;
 
-    source_sequences
+    //
+    // Headers and abundances are collected from all input sequences, but only
+    // sequences not processed in previous runs are submitted to blast.
+    //
+    let pending_sequences = source_sequences
         .to_owned()
         .into_iter()
         .map(|sequence| {
             headers.push(sequence.blast_header().to_owned());
+
+            if let Some(abundance) = sequence.abundance() {
+                abundances
+                    .insert(sequence.blast_header().to_owned(), abundance);
+            }
+
             sequence
         })
-        .collect::<Vec<Sequence>>()
-        .chunks(chunk_size)
-        .enumerate()
-        .par_bridge()
-        .for_each(|(index, chunk)| {
-            tracing::debug!(
-                "Processing chunk {} of {:?}",
-                index + 1,
-                source_sequences.len() / chunk_size
-            );
-
-            let response = match pool.install(|| {
-                blast_execution_repo.run(
+        .filter(|sequence| !completed_queries.contains(sequence.blast_header()))
+        .collect::<Vec<Sequence>>();
+
+    if *resume {
+        tracing::info!(
+            "Resuming blast run: {} of {} queries already processed",
+            headers.len() - pending_sequences.len(),
+            headers.len()
+        );
+    }
+
+    // ? ----------------------------------------------------------------------
+    // ? Build thread pool
+    // ? ----------------------------------------------------------------------
+
+    //
+    // Threads exceeding the available cores only oversubscribe the host.
+    //
+    let threads = match std::thread::available_parallelism() {
+        Ok(cores) => threads.clamp(1, cores.get()),
+        Err(_) => threads.max(1),
+    };
+
+    let (chunk_size, blast_threads) =
+        blast_config.resolve_schedule(pending_sequences.len(), threads)?;
+
+    //
+    // Each worker runs a single blast process at a time, then the number of
+    // workers is limited to avoid oversubscribe the available threads.
+    //
+    let workers = (threads / blast_threads).max(1);
+
+    tracing::info!(
+        "Running {workers} parallel blast processes with {blast_threads} threads and {chunk_size} queries per chunk"
+    );
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(workers)
+        .build()
+        .map_err(|err| {
+            execution_err(format!("Could not build the thread pool: {err}"))
+        })?;
+
+    let failures = Mutex::new(HashMap::<String, String>::new());
+
+    pool.install(|| {
+        pending_sequences
+            .chunks(chunk_size)
+            .enumerate()
+            .par_bridge()
+            .try_for_each(|(index, chunk)| -> Result<(), MappedErrors> {
+                tracing::debug!(
+                    "Processing chunk {} of {:?}",
+                    index + 1,
+                    pending_sequences.len().div_ceil(chunk_size)
+                );
+
+                let response = blast_execution_repo.run(
                     chunk
-                        .into_iter()
+                        .iter()
                         .map(|i| i.to_fasta())
                         .collect::<Vec<String>>()
                         .join(""),
                     blast_config.clone(),
-                    threads,
-                )
-            }) {
-                Err(err) => {
-                    panic!("Unexpected error detected on execute blast: {err}")
-                }
-                Ok(res) => res,
-            };
+                    blast_threads,
+                );
 
-            match response {
-                ExecutionResponse::Fail(err) => {
-                    panic!("Unexpected error on process chunk {index}: {err}");
-                }
-                ExecutionResponse::Success(res) => {
-                    match writer(
-                        res,
-                        file.try_clone().expect(
-                            "Unexpected error detected on write blast result",
-                        ),
-                    ) {
-                        Err(err) => {
-                            panic!(
-                                "Unexpected error on persist chunk {}: {}",
-                                index, err
-                            )
-                        }
-                        Ok(_) => (),
-                    };
+                //
+                // Blast failures are restricted to the chunk queries, which are
+                // reported as failed. Failed chunks are not registered in the
+                // manifest, then could be submitted again in resume mode.
+                //
+                let reason = match response {
+                    Err(err) => err.msg(),
+                    Ok(ExecutionResponse::Fail(err)) => err,
+                    Ok(ExecutionResponse::Success(res)) => {
+                        write_blast_chunk(&chunks_dir, index, res)?;
+
+                        //
+                        // The chunk is registered in the manifest only after
+                        // its results were persisted.
+                        //
+                        let chunk_headers = chunk
+                            .iter()
+                            .map(|sequence| {
+                                format!("{}\n", sequence.blast_header())
+                            })
+                            .collect::<Vec<String>>()
+                            .join("");
+
+                        let manifest_file =
+                            manifest_file.try_clone().map_err(|err| {
+                                execution_err(format!(
+                                    "Unexpected error on register chunk {index}: {err}"
+                                ))
+                            })?;
+
+                        return manifest_writer(chunk_headers, manifest_file);
+                    }
+                };
+
+                let reason = format!("Blast execution failed: {}", reason.trim());
+
+                tracing::error!("Unexpected error on process chunk {index}: {reason}");
+
+                if let Ok(mut failures) = failures.lock() {
+                    for sequence in chunk {
+                        failures.insert(
+                            sequence.blast_header().to_owned(),
+                            reason.to_owned(),
+                        );
+                    }
                 }
-            };
-        });
+
+                Ok(())
+            })
+    })?;
+
+    let failures = failures.into_inner().unwrap_or_default();
+
+    if !pending_sequences.is_empty()
+        && failures.len() == pending_sequences.len()
+    {
+        return execution_err(format!(
+            "Blast execution failed for all chunks: {}",
+            failures.values().next().cloned().unwrap_or_default()
+        ))
+        .as_error();
+    }
+
+    // ? ----------------------------------------------------------------------
+    // ? Merge chunks in the input order
+    // ? ----------------------------------------------------------------------
+
+    let checksum = merge_blast_chunks(
+        &chunks_dir,
+        &out_dir_path,
+        &headers,
+        columns.position(&BlastField::Query).unwrap_or(0),
+    )?;
+
+    tracing::info!("Blast output checksum (MD5): {checksum}");
 
     Ok(ParallelBlastOutput {
         output_file: out_dir_path.to_path_buf(),
         headers: Some(headers),
+        abundances: match abundances.is_empty() {
+            true => None,
+            false => Some(abundances),
+        },
+        columns,
+        checksum: Some(checksum),
+        failures: match failures.is_empty() {
+            true => None,
+            false => Some(failures),
+        },
     })
 }
@@ -0,0 +1,16 @@
+use mycelium_base::utils::errors::{execution_err, MappedErrors};
+use std::{fs::File, io::BufWriter, path::Path};
+
+/// Create a buffered writer of an output file
+pub(crate) fn create_writer(
+    path: &Path,
+) -> Result<BufWriter<File>, MappedErrors> {
+    File::create(path).map(BufWriter::new).map_err(|err| {
+        execution_err(format!("Unexpected error on create {:?}: {err}", path))
+    })
+}
+
+/// Map a write error of an output file
+pub(crate) fn write_error(path: &Path, err: std::io::Error) -> MappedErrors {
+    execution_err(format!("Unexpected error on write {:?}: {err}", path))
+}
@@ -0,0 +1,37 @@
+use crate::{
+    domain::dtos::{
+        blutils_output::BlutilsOutput,
+        file_or_stdin::{FileOrStdin, Source},
+    },
+    use_cases::OutputFormat,
+};
+
+use mycelium_base::utils::errors::{use_case_err, MappedErrors};
+use std::path::PathBuf;
+
+/// Load a blutils output from a file or from the STDIN
+///
+/// The content is parsed given the format of the blutils output.
+pub(crate) fn load_blutils_output(
+    blutils_result: FileOrStdin,
+    result_format: OutputFormat,
+) -> Result<BlutilsOutput, MappedErrors> {
+    if let Source::Arg(ref file) = blutils_result.source {
+        if !PathBuf::from(file).exists() {
+            return use_case_err(format!("The file `{file}` does not exist."))
+                .as_error();
+        }
+    }
+
+    match result_format {
+        OutputFormat::Json => blutils_result
+            .json_content()
+            .map_err(|e| use_case_err(format!("{e}"))),
+        OutputFormat::Jsonl => blutils_result
+            .json_line_content()
+            .map_err(|e| use_case_err(format!("{e}"))),
+        OutputFormat::Yaml => blutils_result
+            .yaml_content()
+            .map_err(|e| use_case_err(format!("{e}"))),
+    }
+}
@@ -1,7 +1,15 @@
+mod create_writer;
+mod load_blutils_output;
+mod sibling_output_file;
+mod stream_blast_sequences;
 mod validate_blast_database;
 mod write_file_or_stdout;
 mod write_or_append_to_file;
 
+pub(super) use create_writer::*;
+pub(super) use load_blutils_output::*;
+pub(super) use sibling_output_file::*;
+pub(super) use stream_blast_sequences::*;
 pub(super) use validate_blast_database::*;
 pub(super) use write_file_or_stdout::*;
 pub(super) use write_or_append_to_file::*;
@@ -0,0 +1,21 @@
+use std::path::{Path, PathBuf};
+
+/// Build the output path of a file related to the taxonomies database
+///
+/// The file is placed beside the output file path, with the output file stem
+/// followed by the `suffix`.
+pub(crate) fn sibling_output_file(
+    output_file_path: &Path,
+    suffix: &str,
+) -> PathBuf {
+    let mut output_path = output_file_path.to_path_buf();
+    output_path.set_extension("json");
+
+    match output_path.parent() {
+        Some(parent) => parent.join(format!(
+            "{}.{suffix}",
+            output_path.file_stem().unwrap().to_str().unwrap(),
+        )),
+        None => PathBuf::from(suffix),
+    }
+}
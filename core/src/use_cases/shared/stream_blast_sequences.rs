@@ -0,0 +1,98 @@
+use super::validate_blast_database;
+
+use mycelium_base::utils::errors::{execution_err, MappedErrors};
+use std::{
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+use subprocess::Exec;
+
+/// A sequence of the blast database
+pub(crate) struct BlastSequence<'a> {
+    pub(crate) accession: &'a str,
+    pub(crate) taxid: &'a str,
+    pub(crate) oid: &'a str,
+    pub(crate) sequence: &'a str,
+}
+
+/// Stream all sequences of a blast database
+///
+/// Sequences are read from the `blastdbcmd` output and passed to the
+/// `visitor` one at a time, so the database is never fully loaded into
+/// memory.
+pub(crate) fn stream_blast_sequences(
+    blast_database_path: &PathBuf,
+    visitor: impl FnMut(BlastSequence) -> Result<(), MappedErrors>,
+) -> Result<(), MappedErrors> {
+    stream_blast_entries(blast_database_path, None, visitor)
+}
+
+/// Stream the sequences of the accessions listed in the entry batch file
+///
+/// The entry batch file contains one accession per line, as expected by the
+/// `blastdbcmd -entry_batch` option.
+pub(crate) fn stream_blast_sequences_batch(
+    blast_database_path: &PathBuf,
+    entry_batch_path: &Path,
+    visitor: impl FnMut(BlastSequence) -> Result<(), MappedErrors>,
+) -> Result<(), MappedErrors> {
+    stream_blast_entries(blast_database_path, Some(entry_batch_path), visitor)
+}
+
+fn stream_blast_entries(
+    blast_database_path: &PathBuf,
+    entry_batch_path: Option<&Path>,
+    mut visitor: impl FnMut(BlastSequence) -> Result<(), MappedErrors>,
+) -> Result<(), MappedErrors> {
+    validate_blast_database(blast_database_path)?;
+
+    let command = match entry_batch_path {
+        Some(path) => Exec::cmd("blastdbcmd").arg("-entry_batch").arg(path),
+        None => Exec::cmd("blastdbcmd").arg("-entry").arg("all"),
+    };
+
+    let stdout = command
+        .arg("-db")
+        .arg(blast_database_path)
+        .arg("-outfmt")
+        .arg("%a  %T  %o  %s")
+        .stream_stdout()
+        .map_err(|err| {
+            execution_err(format!(
+                "Unexpected error detected on execute blastdbcmd: {err}"
+            ))
+        })?;
+
+    for line in BufReader::new(stdout).lines() {
+        let line = line.map_err(|err| {
+            execution_err(format!(
+                "Unexpected error detected on read blastdbcmd response: {err}"
+            ))
+        })?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut fields = line.split("  ").map(|field| field.trim());
+
+        match (fields.next(), fields.next(), fields.next(), fields.next()) {
+            (Some(accession), Some(taxid), Some(oid), Some(sequence)) => {
+                visitor(BlastSequence {
+                    accession,
+                    taxid,
+                    oid,
+                    sequence,
+                })?
+            }
+            _ => {
+                return execution_err(format!(
+                    "Invalid line detected on blastdbcmd response: {line}"
+                ))
+                .as_error()
+            }
+        }
+    }
+
+    Ok(())
+}
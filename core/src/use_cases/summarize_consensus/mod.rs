@@ -0,0 +1,87 @@
+use super::{shared::load_blutils_output, OutputFormat};
+use crate::domain::dtos::{
+    consensus_summary::{ConsensusSummary, SummaryFormat},
+    file_or_stdin::FileOrStdin,
+};
+
+use mycelium_base::utils::errors::{use_case_err, MappedErrors};
+use std::{
+    fs::{remove_file, File},
+    io::Write,
+    path::PathBuf,
+};
+use tracing::warn;
+
+/// Summarize the community composition of a blutils output
+///
+/// Build per-rank count tables from the consensus results, including the
+/// assigned and unassigned counts, the share of mutated and single match
+/// results, and the mean identity percentage of each taxon.
+pub fn summarize_consensus(
+    blutils_result: FileOrStdin,
+    output_file: Option<PathBuf>,
+    result_format: OutputFormat,
+    summary_format: SummaryFormat,
+) -> Result<(), MappedErrors> {
+    // ? -----------------------------------------------------------------------
+    // ? Load content from Blutils output
+    // ? -----------------------------------------------------------------------
+
+    let content = load_blutils_output(blutils_result, result_format)?;
+
+    // ? -----------------------------------------------------------------------
+    // ? Build the summary
+    // ? -----------------------------------------------------------------------
+
+    let summary = ConsensusSummary::from_results(&content.results);
+
+    let summary_content = match summary_format {
+        SummaryFormat::Tsv => summary.to_tsv(),
+        SummaryFormat::Json => {
+            serde_json::to_string_pretty(&summary).map_err(|err| {
+                use_case_err(format!(
+                    "Unexpected error on serialize summary: {err}"
+                ))
+            })?
+        }
+    };
+
+    // ? -----------------------------------------------------------------------
+    // ? Write the summary output
+    // ? -----------------------------------------------------------------------
+
+    let output_file = match output_file {
+        Some(mut path) => {
+            match summary_format {
+                SummaryFormat::Tsv => path.set_extension("tsv"),
+                SummaryFormat::Json => path.set_extension("json"),
+            };
+
+            path
+        }
+        None => {
+            println!("{}", summary_content.trim_end());
+            return Ok(());
+        }
+    };
+
+    if output_file.exists() {
+        warn!("Output file already exists. Removing it.");
+        remove_file(&output_file).map_err(|err| {
+            use_case_err(format!("Could not remove file given {err}"))
+        })?;
+    }
+
+    let mut file = File::create(&output_file).map_err(|err| {
+        use_case_err(format!(
+            "Error on persist summary into {:?}: {err}",
+            output_file
+        ))
+    })?;
+
+    file.write_all(summary_content.as_bytes()).map_err(|err| {
+        use_case_err(format!("Unexpected error on write summary file: {err}"))
+    })?;
+
+    Ok(())
+}
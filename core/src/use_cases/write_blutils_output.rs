@@ -1,13 +1,17 @@
 use crate::{
     domain::dtos::{
         blast_builder::BlastBuilder,
-        blutils_output::BlutilsOutput,
+        blutils_output::{BlutilsOutput, TaxonCutoffsRecord},
         consensus_result::{ConsensusResult, QueryWithConsensus},
+        cutoff_registry::CutoffPreset,
+        taxon_abundance::TaxonAbundanceFolder,
     },
     use_cases::shared::write_or_append_to_file,
 };
 
-use mycelium_base::utils::errors::MappedErrors;
+use mycelium_base::utils::errors::{
+    execution_err, invalid_arg_err, MappedErrors,
+};
 use serde::{Deserialize, Serialize};
 use std::{
     fs::{remove_file, File},
@@ -30,137 +34,86 @@ pub enum OutputFormat {
     Yaml,
 }
 
+/// Write the consensus results given the output format
+///
+/// The taxon cutoffs are recorded in the config when given. Otherwise, the
+/// `taxon_cutoffs` preset is recorded in the output.
 pub fn write_blutils_output(
     results: Vec<ConsensusResult>,
     config: Option<BlastBuilder>,
+    taxon_cutoffs: Option<CutoffPreset>,
     blutils_out_file: Option<String>,
     out_format: OutputFormat,
 ) -> Result<(), MappedErrors> {
-    let blutils_out_file = match blutils_out_file {
-        Some(file) => {
-            let mut path = PathBuf::from(file);
-            match out_format {
-                OutputFormat::Jsonl => {
-                    path.set_extension("jsonl");
-                }
-                OutputFormat::Json => {
-                    path.set_extension("json");
-                }
-                OutputFormat::Yaml => {
-                    path.set_extension("yaml");
-                }
-            }
-
-            info!("");
-            info!("Blutils output file:");
-            info!("\t{:?}", path);
-            info!("");
-
-            if path.exists() {
-                match remove_file(path.clone()) {
-                    Err(err) => panic!("Could not remove file given {err}"),
-                    Ok(_) => warn!("Output file overwritten!"),
-                };
-            };
-
-            if let Some(parent) = path.parent() {
-                if !parent.exists() {
-                    match std::fs::create_dir_all(parent) {
-                        Err(err) => {
-                            panic!("Could not create directory given {err}")
-                        }
-                        Ok(_) => (),
-                    };
-                }
-            }
-
-            Some(path)
-        }
-        None => None,
-    };
+    let blutils_out_file = prepare_output_file(blutils_out_file, &out_format)?;
 
     let run_id = match config.to_owned() {
         Some(c) => c.run_id,
         None => Uuid::new_v4(),
     };
 
-    let mut consensus_type_results = results.iter().fold(
-        Vec::<QueryWithConsensus>::new(),
-        |mut init, record| {
-            match record {
-                ConsensusResult::NoConsensusFound(res) => {
-                    init.push(QueryWithConsensus {
-                        query: res.query.to_owned(),
-                        taxon: None,
-                        run_id: Some(run_id),
-                    });
-                }
-                ConsensusResult::ConsensusFound(res) => {
-                    init.push(QueryWithConsensus {
-                        query: res.query.to_owned(),
-                        taxon: res.taxon.to_owned(),
-                        run_id: Some(run_id),
-                    })
-                }
-            };
+    let mut consensus_type_results = results
+        .iter()
+        .map(|record| to_query_with_consensus(record, run_id))
+        .collect::<Vec<QueryWithConsensus>>();
 
-            init
-        },
+    consensus_type_results.sort_by(|a, b| a.query.cmp(&b.query));
+
+    warn_failed_queries(
+        consensus_type_results
+            .iter()
+            .filter(|result| result.error.is_some())
+            .count(),
     );
 
-    consensus_type_results.sort_by(|a, b| a.query.cmp(&b.query));
+    if let Some(output_file) = blutils_out_file.to_owned() {
+        let mut abundances = TaxonAbundanceFolder::default();
 
-    let config = match config {
-        Some(config) => Some(BlastBuilder {
-            subject_reads: PathBuf::from(config.subject_reads)
-                .file_name()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string(),
-            ..config
-        }),
-        None => None,
-    };
+        for result in &consensus_type_results {
+            abundances.push(result);
+        }
+
+        write_taxa_abundance_file(abundances, output_file)?;
+    }
+
+    let taxon_cutoffs = output_taxon_cutoffs(&config, taxon_cutoffs);
+    let config = output_config(config);
 
     match out_format {
         OutputFormat::Json => {
             if let Some(output_file) = blutils_out_file {
-                let mut file = match File::create(output_file.to_owned()) {
-                    Err(err) => panic!(
-                        "Error on persist output results into {}: {err}",
-                        output_file.as_os_str().to_str().unwrap()
-                    ),
-                    Ok(res) => res,
-                };
-
-                match file.write_all(
-                    serde_json::to_string_pretty(&BlutilsOutput {
-                        results: consensus_type_results,
-                        config,
-                    })
-                    .unwrap()
-                    .as_bytes(),
-                ) {
-                    Err(err) => panic!(
-                        "Unexpected error on write config to output file: {err}"
-                    ),
-                    Ok(_) => (),
-                };
+                let mut file = create_output_file(&output_file)?;
 
-                Ok(())
+                let content = serde_json::to_string_pretty(&BlutilsOutput {
+                    results: consensus_type_results,
+                    config,
+                    taxon_cutoffs,
+                })
+                .map_err(|err| {
+                    execution_err(format!(
+                        "Unexpected error on serialize JSON output: {err}"
+                    ))
+                })?;
+
+                file.write_all(content.as_bytes()).map_err(|err| {
+                    execution_err(format!(
+                        "Unexpected error on write config to output file: {err}"
+                    ))
+                })
             } else {
-                if let Err(err) = serde_json::to_writer(
+                serde_json::to_writer(
                     std::io::stdout().lock(),
                     &BlutilsOutput {
                         results: consensus_type_results,
                         config,
+                        taxon_cutoffs,
                     },
-                ) {
-                    panic!("Unexpected error on write JSON output: {err}");
-                } else {
-                    Ok(())
-                }
+                )
+                .map_err(|err| {
+                    execution_err(format!(
+                        "Unexpected error on write JSON output: {err}"
+                    ))
+                })
             }
         }
         OutputFormat::Jsonl => {
@@ -169,45 +122,36 @@ pub fn write_blutils_output(
                     write_or_append_to_file(output_file.as_path());
 
                 writer(
-                    serde_json::to_string(&config).unwrap() + "\n",
-                    file.try_clone().expect(
-                        "Unexpected error detected on write blast result",
-                    ),
+                    json_line_header(&config, taxon_cutoffs)?,
+                    clone_file(&file)?,
                 )?;
 
                 for record in &consensus_type_results {
-                    match writer(
-                        serde_json::to_string(&record).unwrap() + "\n",
-                        file.try_clone().expect(
-                            "Unexpected error detected on write blast result",
-                        ),
-                    ) {
-                        Err(err) => {
-                            panic!("Unexpected error on write JSONL output file: {err}",)
-                        }
-                        Ok(_) => (),
-                    }
+                    writer(to_json_line(&record)?, clone_file(&file)?)?;
                 }
 
                 Ok(())
             } else {
-                let mut stdout = std::io::stdout();
-
-                if let Err(err) = serde_json::to_writer(stdout.lock(), &config)
-                {
-                    panic!("Unexpected error on write JSONL output: {err}");
-                }
+                let mut stdout = std::io::stdout().lock();
 
-                stdout.write(b"\n").unwrap();
+                stdout
+                    .write_all(
+                        json_line_header(&config, taxon_cutoffs)?.as_bytes(),
+                    )
+                    .map_err(|err| {
+                        execution_err(format!(
+                            "Unexpected error on write JSONL output: {err}"
+                        ))
+                    })?;
 
                 for record in &consensus_type_results {
-                    if let Err(err) =
-                        serde_json::to_writer(stdout.lock(), &record)
-                    {
-                        panic!("Unexpected error on write JSONL output: {err}");
-                    }
-
-                    stdout.write(b"\n").unwrap();
+                    stdout
+                        .write_all(to_json_line(&record)?.as_bytes())
+                        .map_err(|err| {
+                            execution_err(format!(
+                                "Unexpected error on write JSONL output: {err}"
+                            ))
+                        })?;
                 }
 
                 Ok(())
@@ -215,36 +159,313 @@ pub fn write_blutils_output(
         }
         OutputFormat::Yaml => {
             if let Some(output_file) = blutils_out_file {
-                let file = match File::create(output_file.to_owned()) {
-                    Err(err) => panic!(
-                        "Error on persist output results into {}: {err}",
-                        output_file.as_os_str().to_str().unwrap()
-                    ),
-                    Ok(res) => res,
-                };
+                let file = create_output_file(&output_file)?;
 
                 serde_yaml::to_writer(
                     file,
                     &BlutilsOutput {
                         results: consensus_type_results,
                         config,
+                        taxon_cutoffs,
                     },
                 )
-                .unwrap();
-
-                Ok(())
+                .map_err(|err| {
+                    execution_err(format!(
+                        "Unexpected error on write YAML output: {err}"
+                    ))
+                })
             } else {
                 serde_yaml::to_writer(
                     std::io::stdout().lock(),
                     &BlutilsOutput {
                         results: consensus_type_results,
                         config,
+                        taxon_cutoffs,
                     },
                 )
-                .unwrap();
+                .map_err(|err| {
+                    execution_err(format!(
+                        "Unexpected error on write YAML output: {err}"
+                    ))
+                })
+            }
+        }
+    }
+}
 
-                Ok(())
+/// Write consensus results incrementally as JSONL
+///
+/// Results are written as soon as each batch is produced, so the memory usage
+/// is bounded by the batch size instead of the number of queries. The config
+/// record, or the taxon cutoffs record of outputs without config, is written
+/// first, followed by the results in the batches order.
+/// Errors of any batch interrupt the writing.
+pub fn write_blutils_output_stream(
+    batches: impl Iterator<Item = Result<Vec<ConsensusResult>, MappedErrors>>,
+    config: Option<BlastBuilder>,
+    taxon_cutoffs: Option<CutoffPreset>,
+    blutils_out_file: Option<String>,
+) -> Result<(), MappedErrors> {
+    let blutils_out_file =
+        prepare_output_file(blutils_out_file, &OutputFormat::Jsonl)?;
+
+    let run_id = match config.to_owned() {
+        Some(c) => c.run_id,
+        None => Uuid::new_v4(),
+    };
+
+    let mut output: Box<dyn Write> = match &blutils_out_file {
+        Some(output_file) => Box::new(create_output_file(output_file)?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    let mut write_line = |line: String| -> Result<(), MappedErrors> {
+        output.write_all(line.as_bytes()).map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on write JSONL output: {err}"
+            ))
+        })
+    };
+
+    let taxon_cutoffs = output_taxon_cutoffs(&config, taxon_cutoffs);
+    write_line(json_line_header(&output_config(config), taxon_cutoffs)?)?;
+
+    let mut abundances = TaxonAbundanceFolder::default();
+    let mut failed_queries = 0;
+
+    for batch in batches {
+        for record in batch? {
+            let record = to_query_with_consensus(&record, run_id);
+
+            if record.error.is_some() {
+                failed_queries += 1;
             }
+
+            abundances.push(&record);
+            write_line(to_json_line(&record)?)?;
+        }
+    }
+
+    warn_failed_queries(failed_queries);
+
+    if let Some(output_file) = blutils_out_file {
+        write_taxa_abundance_file(abundances, output_file)?;
+    }
+
+    Ok(())
+}
+
+/// Check if the output format supports the streaming consensus
+pub fn validate_stream_format(
+    out_format: &OutputFormat,
+) -> Result<(), MappedErrors> {
+    match out_format {
+        OutputFormat::Jsonl => Ok(()),
+        _ => invalid_arg_err(
+            "The streaming consensus is only available for the JSONL output format",
+        )
+        .as_error(),
+    }
+}
+
+/// Resolve the output file path given the output format
+///
+/// Existing files are removed and missing parent directories are created.
+fn prepare_output_file(
+    blutils_out_file: Option<String>,
+    out_format: &OutputFormat,
+) -> Result<Option<PathBuf>, MappedErrors> {
+    let file = match blutils_out_file {
+        Some(file) => file,
+        None => return Ok(None),
+    };
+
+    let mut path = PathBuf::from(file);
+    match out_format {
+        OutputFormat::Jsonl => {
+            path.set_extension("jsonl");
+        }
+        OutputFormat::Json => {
+            path.set_extension("json");
+        }
+        OutputFormat::Yaml => {
+            path.set_extension("yaml");
         }
     }
+
+    info!("");
+    info!("Blutils output file:");
+    info!("\t{:?}", path);
+    info!("");
+
+    if path.exists() {
+        remove_file(path.clone()).map_err(|err| {
+            execution_err(format!("Could not remove file given {err}"))
+        })?;
+
+        warn!("Output file overwritten!");
+    };
+
+    if let Some(parent) = path.parent() {
+        if !parent.exists() {
+            std::fs::create_dir_all(parent).map_err(|err| {
+                execution_err(format!("Could not create directory given {err}"))
+            })?;
+        }
+    }
+
+    Ok(Some(path))
+}
+
+/// Build the output config keeping only the name of the subject database
+fn output_config(config: Option<BlastBuilder>) -> Option<BlastBuilder> {
+    config.map(|config| BlastBuilder {
+        subject_reads: PathBuf::from(&config.subject_reads)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or(config.subject_reads.to_owned()),
+        ..config
+    })
+}
+
+/// Keep the taxon cutoffs only if not recorded in the config
+fn output_taxon_cutoffs(
+    config: &Option<BlastBuilder>,
+    taxon_cutoffs: Option<CutoffPreset>,
+) -> Option<CutoffPreset> {
+    match config {
+        Some(_) => None,
+        None => taxon_cutoffs,
+    }
+}
+
+/// Build the first JSONL record, being the config or the taxon cutoffs
+fn json_line_header(
+    config: &Option<BlastBuilder>,
+    taxon_cutoffs: Option<CutoffPreset>,
+) -> Result<String, MappedErrors> {
+    match (config, taxon_cutoffs) {
+        (None, Some(taxon_cutoffs)) => {
+            to_json_line(&TaxonCutoffsRecord { taxon_cutoffs })
+        }
+        _ => to_json_line(config),
+    }
+}
+
+fn to_query_with_consensus(
+    record: &ConsensusResult,
+    run_id: Uuid,
+) -> QueryWithConsensus {
+    match record {
+        ConsensusResult::NoConsensusFound(res) => QueryWithConsensus {
+            query: res.query.to_owned(),
+            taxon: None,
+            run_id: Some(run_id),
+            abundance: res.abundance,
+            error: None,
+        },
+        ConsensusResult::ConsensusFound(res) => QueryWithConsensus {
+            query: res.query.to_owned(),
+            taxon: res.taxon.to_owned(),
+            run_id: Some(run_id),
+            abundance: res.abundance,
+            error: None,
+        },
+        ConsensusResult::Failed(res) => QueryWithConsensus {
+            query: res.query.to_owned(),
+            taxon: None,
+            run_id: Some(run_id),
+            abundance: res.abundance,
+            error: Some(res.reason.to_owned()),
+        },
+    }
+}
+
+fn warn_failed_queries(failed_queries: usize) {
+    if failed_queries > 0 {
+        warn!(
+            "Consensus generation failed for {failed_queries} queries. See the `error` field of such queries for details."
+        );
+    }
+}
+
+/// Write the per-taxon abundance table
+///
+/// The table is written side by side with the blutils output file and only if
+/// at least one query contains an abundance annotation.
+fn write_taxa_abundance_file(
+    abundances: TaxonAbundanceFolder,
+    blutils_out_file: PathBuf,
+) -> Result<(), MappedErrors> {
+    if !abundances.has_annotations() {
+        return Ok(());
+    }
+
+    let mut path = blutils_out_file;
+    path.set_extension("abundance.tsv");
+
+    info!("Blutils abundance file:");
+    info!("\t{:?}", path);
+    info!("");
+
+    if path.exists() {
+        remove_file(path.clone()).map_err(|err| {
+            execution_err(format!("Could not remove file given {err}"))
+        })?;
+
+        warn!("Abundance file overwritten!");
+    };
+
+    let (writer, file) = write_or_append_to_file(path.as_path());
+
+    writer(
+        "rank\tidentifier\ttaxonomy\tqueries\tabundance\n".to_string(),
+        clone_file(&file)?,
+    )?;
+
+    let null = "null";
+
+    for record in abundances.finish() {
+        writer(
+            format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                record
+                    .rank
+                    .map(|rank| rank.as_full_rank_string())
+                    .unwrap_or(null.to_string()),
+                record.identifier.unwrap_or(null.to_string()),
+                record.taxonomy.unwrap_or(null.to_string()),
+                record.queries,
+                record.abundance,
+            ),
+            clone_file(&file)?,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn create_output_file(output_file: &PathBuf) -> Result<File, MappedErrors> {
+    File::create(output_file).map_err(|err| {
+        execution_err(format!(
+            "Error on persist output results into {}: {err}",
+            output_file.display()
+        ))
+    })
+}
+
+fn clone_file(file: &File) -> Result<File, MappedErrors> {
+    file.try_clone().map_err(|err| {
+        execution_err(format!("Unexpected error on access output file: {err}"))
+    })
+}
+
+fn to_json_line<T: Serialize>(record: &T) -> Result<String, MappedErrors> {
+    serde_json::to_string(record)
+        .map(|line| line + "\n")
+        .map_err(|err| {
+            execution_err(format!(
+                "Unexpected error on serialize JSONL output: {err}"
+            ))
+        })
 }
@@ -0,0 +1,115 @@
+//! Tests of the consensus built from existing blast outputs
+//!
+//! The canned blast output of the `test/mock/input` queries is used as the
+//! external blast output, as given to the `build-consensus` command.
+
+use blul_core::{
+    domain::dtos::{
+        blast_tabular_format::BlastTabularFormat,
+        blutils_output::BlutilsOutput,
+        consensus_strategy::ConsensusStrategy,
+        cutoff_registry::{CutoffPreset, CutoffRegistry},
+        file_or_stdin::FileOrStdin,
+        parallel_blast_output::ParallelBlastOutput,
+        taxon::Taxon,
+    },
+    use_cases::{
+        build_consensus_identities, write_blutils_output, OutputFormat,
+    },
+};
+use std::{
+    fs::{create_dir_all, read_to_string},
+    path::{Path, PathBuf},
+};
+
+fn mock_path(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../test/mock")
+        .join(path)
+}
+
+fn output_path(name: &str) -> PathBuf {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("build_consensus_identities")
+        .join(name);
+
+    create_dir_all(&path).expect("Output directory should be created");
+    path
+}
+
+fn assert_preset(preset: Option<CutoffPreset>, expected: &CutoffPreset) {
+    let preset = preset.expect("The cutoff preset should be recorded");
+
+    assert_eq!(preset.name, expected.name);
+    assert_eq!(preset.registry_version, expected.registry_version);
+    assert_eq!(
+        serde_json::to_value(&preset.cutoffs).unwrap(),
+        serde_json::to_value(&expected.cutoffs).unwrap()
+    );
+}
+
+#[test]
+fn outputs_without_config_record_the_cutoff_preset() {
+    let taxon = "16s-v4".parse::<Taxon>().expect("Taxon should be parsed");
+
+    let preset = CutoffRegistry::shipped()
+        .and_then(|registry| registry.resolve(&taxon, None))
+        .expect("The shipped preset should be resolved");
+
+    let results = build_consensus_identities(
+        ParallelBlastOutput {
+            output_file: mock_path("input/blast/query.blast.tsv"),
+            headers: None,
+            abundances: None,
+            columns: BlastTabularFormat::default(),
+            checksum: None,
+            failures: None,
+        },
+        &mock_path("input/ref_databases/mock-16S.blutils.json"),
+        taxon,
+        ConsensusStrategy::Cautious,
+        Some(false),
+        Some(preset.cutoffs.to_owned()),
+        None,
+    )
+    .expect("Consensus should be built");
+
+    let output_directory = output_path("preset");
+
+    for out_format in [OutputFormat::Json, OutputFormat::Jsonl] {
+        let output_file = output_directory.join("blutils");
+
+        let extension = match out_format {
+            OutputFormat::Jsonl => "jsonl",
+            _ => "json",
+        };
+
+        write_blutils_output(
+            results.to_owned(),
+            None,
+            Some(preset.to_owned()),
+            Some(output_file.to_string_lossy().to_string()),
+            out_format,
+        )
+        .expect("Output should be written");
+
+        let output_file = output_file.with_extension(extension);
+
+        let output = match extension {
+            "json" => serde_json::from_str::<BlutilsOutput>(
+                &read_to_string(&output_file).expect("Output should be read"),
+            )
+            .expect("Output should be a Blutils output"),
+            _ => output_file
+                .to_string_lossy()
+                .parse::<FileOrStdin>()
+                .expect("Output path should be parsed")
+                .json_line_content()
+                .expect("Output should be a Blutils JSONL output"),
+        };
+
+        assert!(output.config.is_none());
+        assert!(!output.results.is_empty());
+        assert_preset(output.taxon_cutoffs, &preset);
+    }
+}
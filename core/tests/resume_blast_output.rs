@@ -0,0 +1,243 @@
+//! Tests of resumed blast runs
+//!
+//! Interrupted runs of the mock adapter are resumed, and the blast output is
+//! compared byte by byte with the output of a clean run of the same queries.
+//! Each query is submitted in its own chunk.
+
+use blul_core::{
+    domain::{
+        dtos::{
+            blast_builder::{BlastBuilder, ScheduleSetting},
+            blutils_output::BlutilsOutput,
+            consensus_strategy::ConsensusStrategy,
+            file_or_stdin::FileOrStdin,
+            taxon::Taxon,
+        },
+        entities::execute_blastn::{ExecuteBlastn, ExecutionResponse},
+    },
+    use_cases::{run_blast_and_build_consensus, OutputFormat},
+};
+use blul_mock::execute_blast::ExecuteBlastnMockRepository;
+use mycelium_base::utils::errors::MappedErrors;
+use std::{
+    fs::{create_dir_all, read, read_to_string, remove_dir_all, write},
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+fn mock_path(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../test/mock")
+        .join(path)
+}
+
+fn output_path(name: &str) -> PathBuf {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("resume_blast_output")
+        .join(name);
+
+    if path.exists() {
+        remove_dir_all(&path).expect("Output directory should be removed");
+    }
+
+    create_dir_all(&path).expect("Output directory should be created");
+    path
+}
+
+fn mock_repository() -> ExecuteBlastnMockRepository {
+    ExecuteBlastnMockRepository::from_blast_output(&mock_path(
+        "input/blast/query.blast.tsv",
+    ))
+    .expect("Canned blast output should be loaded")
+}
+
+/// A mock blastn killed after processing a number of chunks
+///
+/// The panic unwinds through the blast thread pool, leaving the chunks and
+/// the manifest as an interrupted process would.
+#[derive(Debug)]
+struct InterruptedRepository {
+    repository: ExecuteBlastnMockRepository,
+    remaining_chunks: AtomicUsize,
+}
+
+impl ExecuteBlastn for InterruptedRepository {
+    fn requires_blast_database(&self) -> bool {
+        false
+    }
+
+    fn run(
+        &self,
+        query_sequences: String,
+        blast_config: BlastBuilder,
+        threads: usize,
+    ) -> Result<ExecutionResponse, MappedErrors> {
+        if self
+            .remaining_chunks
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |remaining| {
+                remaining.checked_sub(1)
+            })
+            .is_err()
+        {
+            panic!("Blast interrupted");
+        }
+
+        self.repository.run(query_sequences, blast_config, threads)
+    }
+}
+
+/// Run the pipeline returning the Blutils output
+fn run_mock_blast(
+    directory: &Path,
+    repository: &dyn ExecuteBlastn,
+    resume: bool,
+) -> Result<BlutilsOutput, MappedErrors> {
+    let blutils_out_file = directory.join("blutils.json");
+
+    run_blast_and_build_consensus(
+        mock_path("input/query/query.fna")
+            .to_string_lossy()
+            .parse::<FileOrStdin>()
+            .expect("Query path should be parsed"),
+        &mock_path("input/ref_databases/mock-16S.blutils.json")
+            .to_string_lossy(),
+        &directory.join("blast.out").to_string_lossy(),
+        Some(blutils_out_file.to_string_lossy().to_string()),
+        BlastBuilder::default(
+            &mock_path("input/ref_databases/mock-16S.fna").to_string_lossy(),
+            Taxon::Bacteria,
+        )
+        .with_chunk_size(ScheduleSetting::Fixed(1)),
+        repository,
+        &true,
+        &resume,
+        1,
+        ConsensusStrategy::Cautious,
+        Some(false),
+        OutputFormat::Json,
+        None,
+        None,
+        None,
+    )?;
+
+    Ok(serde_json::from_str(
+        &read_to_string(&blutils_out_file).expect("Output should be written"),
+    )
+    .expect("Output should be a Blutils output"))
+}
+
+/// Check the resumed output against a clean run, including the recorded
+/// checksum
+fn assert_same_as_clean_run(directory: &Path, output: &BlutilsOutput) {
+    let clean_directory = directory.join("clean");
+    create_dir_all(&clean_directory).unwrap();
+
+    let clean_output =
+        run_mock_blast(&clean_directory, &mock_repository(), false)
+            .expect("Clean run should succeed");
+
+    let resumed = read(directory.join("blast.out")).unwrap();
+    let clean = read(clean_directory.join("blast.out")).unwrap();
+
+    assert!(!clean.is_empty());
+    assert!(resumed == clean, "Resumed blast output differs");
+
+    let checksum = |output: &BlutilsOutput| {
+        output
+            .config
+            .as_ref()
+            .and_then(|config| config.out_checksum.to_owned())
+            .expect("Checksum should be recorded")
+    };
+
+    assert_eq!(checksum(output), format!("{:x}", md5::compute(&resumed)));
+    assert_eq!(checksum(output), checksum(&clean_output));
+
+    assert!(!directory.join("blast.out.chunks").exists());
+}
+
+#[test]
+fn runs_interrupted_after_some_chunks_are_resumed() {
+    let directory = output_path("interrupted");
+
+    let interrupted = catch_unwind(AssertUnwindSafe(|| {
+        run_mock_blast(
+            &directory,
+            &InterruptedRepository {
+                repository: mock_repository(),
+                remaining_chunks: AtomicUsize::new(4),
+            },
+            false,
+        )
+    }));
+
+    assert!(interrupted.is_err());
+
+    let manifest = read_to_string(directory.join("blast.out.manifest"))
+        .expect("Manifest should be written");
+
+    assert_eq!(manifest.lines().count(), 4);
+
+    //
+    // A row of a query not registered in the manifest, as written by a
+    // process interrupted during the write, should be discarded on resume.
+    //
+    let unregistered = read_to_string(mock_path("input/blast/query.blast.tsv"))
+        .unwrap()
+        .lines()
+        .find(|row| {
+            !manifest
+                .lines()
+                .any(|query| row.starts_with(&format!("{query}\t")))
+        })
+        .map(|row| row[..row.len() / 2].to_owned())
+        .expect("Unregistered query should be found");
+
+    write(directory.join("blast.out"), unregistered).unwrap();
+
+    let output = run_mock_blast(&directory, &mock_repository(), true)
+        .expect("Resumed run should succeed");
+
+    assert_same_as_clean_run(&directory, &output);
+}
+
+#[test]
+fn failed_chunks_are_submitted_again_on_resume() {
+    let directory = output_path("failed");
+
+    let failing_queries = read_to_string(mock_path("input/query/query.fna"))
+        .unwrap()
+        .lines()
+        .filter_map(|line| line.strip_prefix('>'))
+        .map(|header| header.split_whitespace().next().unwrap().to_owned())
+        .step_by(2)
+        .collect::<Vec<String>>();
+
+    let failed = run_mock_blast(
+        &directory,
+        &mock_repository().with_failing_queries(failing_queries.to_owned()),
+        false,
+    )
+    .expect("Partially failed run should succeed");
+
+    assert_eq!(
+        failed
+            .results
+            .iter()
+            .filter(|result| result.error.is_some())
+            .count(),
+        failing_queries.len()
+    );
+
+    //
+    // The previous results are merged with the results of the resubmitted
+    // queries in the input order.
+    //
+    let output = run_mock_blast(&directory, &mock_repository(), true)
+        .expect("Resumed run should succeed");
+
+    assert!(output.results.iter().all(|result| result.error.is_none()));
+
+    assert_same_as_clean_run(&directory, &output);
+}
@@ -0,0 +1,297 @@
+//! End to end tests of the blast and consensus pipeline
+//!
+//! Blast is replaced by the mock adapter, replaying the canned blast output
+//! of the `test/mock/input` queries against the mock 16S reference. The
+//! consensus of each taxon cutoffs is compared with the golden Blutils
+//! outputs of `test/mock/output/consensus`. The lineages of the mock Blutils
+//! database are named after the pruned taxdump names of
+//! `test/mock/input/taxdump/names.dmp`.
+//!
+//! Golden files should be regenerated after intended consensus changes by
+//! running the tests with the `BLUTILS_UPDATE_GOLDEN` environment variable
+//! set, and reviewed before commit.
+
+use blul_core::{
+    domain::dtos::{
+        blast_builder::{BlastBuilder, ScheduleSetting},
+        blutils_output::BlutilsOutput,
+        consensus_strategy::ConsensusStrategy,
+        file_or_stdin::FileOrStdin,
+        taxon::{CustomTaxon, Taxon},
+    },
+    use_cases::{run_blast_and_build_consensus, OutputFormat},
+};
+use blul_mock::execute_blast::ExecuteBlastnMockRepository;
+use serde_json::Value;
+use std::{
+    fs::{create_dir_all, read_to_string, write},
+    path::{Path, PathBuf},
+};
+
+fn mock_path(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../test/mock")
+        .join(path)
+}
+
+fn output_path(name: &str) -> PathBuf {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("run_blast_and_build_consensus")
+        .join(name);
+
+    create_dir_all(&path).expect("Output directory should be created");
+    path
+}
+
+fn mock_repository() -> ExecuteBlastnMockRepository {
+    ExecuteBlastnMockRepository::from_blast_output(&mock_path(
+        "input/blast/query.blast.tsv",
+    ))
+    .expect("Canned blast output should be loaded")
+}
+
+/// Run the pipeline against the mock inputs returning the Blutils output
+fn run_mock_consensus(
+    name: &str,
+    blast_config: BlastBuilder,
+    repository: &ExecuteBlastnMockRepository,
+    use_taxid: bool,
+    custom_taxon: Option<CustomTaxon>,
+) -> BlutilsOutput {
+    let output_directory = output_path(name);
+    let blutils_out_file = output_directory.join("blutils.json");
+
+    run_blast_and_build_consensus(
+        mock_path("input/query/query.fna")
+            .to_string_lossy()
+            .parse::<FileOrStdin>()
+            .expect("Query path should be parsed"),
+        &mock_path("input/ref_databases/mock-16S.blutils.json")
+            .to_string_lossy(),
+        &output_directory.join("blast.out").to_string_lossy(),
+        Some(blutils_out_file.to_string_lossy().to_string()),
+        blast_config,
+        repository,
+        &true,
+        &false,
+        1,
+        ConsensusStrategy::Cautious,
+        Some(use_taxid),
+        OutputFormat::Json,
+        custom_taxon,
+        None,
+        None,
+    )
+    .expect("Pipeline should run");
+
+    serde_json::from_str(
+        &read_to_string(&blutils_out_file).expect("Output should be written"),
+    )
+    .expect("Output should be a Blutils output")
+}
+
+fn blast_config(taxon: Taxon) -> BlastBuilder {
+    BlastBuilder::default(
+        &mock_path("input/ref_databases/mock-16S.fna").to_string_lossy(),
+        taxon,
+    )
+}
+
+/// Remove the fields changing between runs and releases
+fn normalize(output: &BlutilsOutput) -> Value {
+    let mut value =
+        serde_json::to_value(output).expect("Output should be serialized");
+
+    if let Some(results) = value["results"].as_array_mut() {
+        for result in results {
+            result["runId"] = Value::Null;
+        }
+    }
+
+    if let Some(config) = value["config"].as_object_mut() {
+        config.remove("runId");
+        config.remove("blutilsVersion");
+    }
+
+    value
+}
+
+/// Compare the output with the golden file, or update the golden file if
+/// the `BLUTILS_UPDATE_GOLDEN` environment variable is set
+fn assert_golden(name: &str, output: &BlutilsOutput) {
+    let golden_path =
+        mock_path(&format!("output/consensus/{name}.blutils.json"));
+    let output = normalize(output);
+
+    if std::env::var_os("BLUTILS_UPDATE_GOLDEN").is_some() {
+        create_dir_all(golden_path.parent().unwrap())
+            .expect("Golden directory should be created");
+
+        write(
+            &golden_path,
+            serde_json::to_string_pretty(&output).unwrap() + "\n",
+        )
+        .expect("Golden file should be written");
+    }
+
+    let golden: Value = serde_json::from_str(
+        &read_to_string(&golden_path).unwrap_or_else(|err| {
+            panic!("Golden file {:?} should be readable: {err}", golden_path)
+        }),
+    )
+    .expect("Golden file should be a JSON file");
+
+    assert_eq!(
+        output, golden,
+        "Output differs from the golden file {:?}",
+        golden_path
+    );
+}
+
+#[test]
+fn bacteria_consensus_matches_golden_output() {
+    let output = run_mock_consensus(
+        "bacteria",
+        blast_config(Taxon::Bacteria),
+        &mock_repository(),
+        false,
+        None,
+    );
+
+    assert_golden("bacteria", &output);
+}
+
+#[test]
+fn fungi_consensus_matches_golden_output() {
+    let output = run_mock_consensus(
+        "fungi",
+        blast_config(Taxon::Fungi),
+        &mock_repository(),
+        false,
+        None,
+    );
+
+    assert_golden("fungi", &output);
+}
+
+#[test]
+fn custom_consensus_matches_golden_output() {
+    let output = run_mock_consensus(
+        "custom",
+        blast_config(Taxon::Custom),
+        &mock_repository(),
+        true,
+        Some(CustomTaxon::from_file(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../assets/custom-taxon-cutoffs-bacteria-16S.yaml"),
+        )),
+    );
+
+    assert_golden("custom", &output);
+}
+
+#[test]
+fn marker_preset_consensus_matches_golden_output() {
+    let output = run_mock_consensus(
+        "16s-v4",
+        blast_config("16S V4".parse::<Taxon>().unwrap()),
+        &mock_repository(),
+        false,
+        None,
+    );
+
+    assert_golden("16s-v4", &output);
+}
+
+#[test]
+fn failed_chunks_are_reported_without_consensus() {
+    let failing_query = "NR114924.257984.Bac".to_string();
+
+    let output = run_mock_consensus(
+        "failed-chunks",
+        blast_config(Taxon::Bacteria)
+            .with_chunk_size(ScheduleSetting::Fixed(1)),
+        &mock_repository().with_failing_queries(vec![failing_query.clone()]),
+        false,
+        None,
+    );
+
+    let failed = output
+        .results
+        .iter()
+        .filter(|result| result.error.is_some())
+        .collect::<Vec<_>>();
+
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].query, failing_query);
+    assert!(failed[0].taxon.is_none());
+
+    assert!(output
+        .results
+        .iter()
+        .any(|result| result.error.is_none() && result.taxon.is_some()));
+}
+
+/// Replace a cell of the first row of the query in the canned blast output
+/// and assert only the query is reported as failed
+fn assert_malformed_cell_fails_query(name: &str, column: usize, value: &str) {
+    let malformed_query = "NR114924.257984.Bac";
+
+    let content = read_to_string(mock_path("input/blast/query.blast.tsv"))
+        .expect("Canned blast output should be read");
+
+    let mut malformed = false;
+    let rows = content
+        .lines()
+        .map(|row| {
+            if !malformed && row.starts_with(&format!("{malformed_query}\t")) {
+                malformed = true;
+
+                let mut cells = row.split('\t').collect::<Vec<&str>>();
+                cells[column] = value;
+                return cells.join("\t");
+            }
+
+            row.to_owned()
+        })
+        .collect::<Vec<String>>();
+
+    let output = run_mock_consensus(
+        name,
+        blast_config(Taxon::Bacteria),
+        &ExecuteBlastnMockRepository::default()
+            .with_rows(rows.iter().map(String::as_str)),
+        false,
+        None,
+    );
+
+    let failed = output
+        .results
+        .iter()
+        .filter(|result| result.error.is_some())
+        .collect::<Vec<_>>();
+
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].query, malformed_query);
+    assert!(failed[0].taxon.is_none());
+
+    assert!(output
+        .results
+        .iter()
+        .any(|result| result.error.is_none() && result.taxon.is_some()));
+}
+
+#[test]
+fn malformed_rows_are_reported_as_failed_queries() {
+    assert_malformed_cell_fails_query("malformed-empty-bit-score", 12, "");
+}
+
+#[test]
+fn non_numeric_bit_scores_are_reported_as_failed_queries() {
+    assert_malformed_cell_fails_query("malformed-bit-score", 12, "abc");
+}
+
+#[test]
+fn non_numeric_identities_are_reported_as_failed_queries() {
+    assert_malformed_cell_fails_query("malformed-identity", 3, "abc");
+}
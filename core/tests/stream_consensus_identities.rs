@@ -0,0 +1,169 @@
+//! Tests of the streaming consensus
+//!
+//! The streamed JSONL output of the mock pipeline is compared with the golden
+//! Blutils output of the non-streaming consensus, using batches smaller than
+//! the number of queries.
+
+use blul_core::{
+    domain::dtos::{
+        blast_builder::BlastBuilder, blast_tabular_format::BlastTabularFormat,
+        blutils_output::BlutilsOutput, consensus_strategy::ConsensusStrategy,
+        file_or_stdin::FileOrStdin, parallel_blast_output::ParallelBlastOutput,
+        taxon::Taxon,
+    },
+    use_cases::{
+        run_blast_and_build_consensus, stream_consensus_identities,
+        OutputFormat,
+    },
+};
+use blul_mock::execute_blast::ExecuteBlastnMockRepository;
+use serde_json::Value;
+use std::{
+    fs::{create_dir_all, read_to_string, write},
+    path::{Path, PathBuf},
+};
+
+fn mock_path(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../test/mock")
+        .join(path)
+}
+
+fn output_path(name: &str) -> PathBuf {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("stream_consensus_identities")
+        .join(name);
+
+    create_dir_all(&path).expect("Output directory should be created");
+    path
+}
+
+/// Remove the fields changing between runs and releases, sorting results by
+/// query as the golden outputs
+fn normalize(output: &BlutilsOutput) -> Value {
+    let mut value =
+        serde_json::to_value(output).expect("Output should be serialized");
+
+    if let Some(results) = value["results"].as_array_mut() {
+        for result in results.iter_mut() {
+            result["runId"] = Value::Null;
+        }
+
+        results.sort_by_key(|result| result["query"].to_string());
+    }
+
+    if let Some(config) = value["config"].as_object_mut() {
+        config.remove("runId");
+        config.remove("blutilsVersion");
+    }
+
+    value
+}
+
+#[test]
+fn streamed_consensus_matches_golden_output() {
+    let output_directory = output_path("bacteria");
+    let blutils_out_file = output_directory.join("blutils.jsonl");
+
+    run_blast_and_build_consensus(
+        mock_path("input/query/query.fna")
+            .to_string_lossy()
+            .parse::<FileOrStdin>()
+            .expect("Query path should be parsed"),
+        &mock_path("input/ref_databases/mock-16S.blutils.json")
+            .to_string_lossy(),
+        &output_directory.join("blast.out").to_string_lossy(),
+        Some(blutils_out_file.to_string_lossy().to_string()),
+        BlastBuilder::default(
+            &mock_path("input/ref_databases/mock-16S.fna").to_string_lossy(),
+            Taxon::Bacteria,
+        ),
+        &ExecuteBlastnMockRepository::from_blast_output(&mock_path(
+            "input/blast/query.blast.tsv",
+        ))
+        .expect("Canned blast output should be loaded"),
+        &true,
+        &false,
+        1,
+        ConsensusStrategy::Cautious,
+        Some(false),
+        OutputFormat::Jsonl,
+        None,
+        None,
+        Some(3),
+    )
+    .expect("Pipeline should run");
+
+    let output = blutils_out_file
+        .to_string_lossy()
+        .parse::<FileOrStdin>()
+        .expect("Output path should be parsed")
+        .json_line_content()
+        .expect("Output should be a Blutils JSONL output");
+
+    //
+    // Queries without blast results are produced after the blast output is
+    // consumed.
+    //
+    assert_eq!(
+        output.results.last().map(|result| result.query.as_str()),
+        Some("INVALID_SEQUENCE")
+    );
+
+    let golden: Value = serde_json::from_str(
+        &read_to_string(mock_path("output/consensus/bacteria.blutils.json"))
+            .expect("Golden file should be readable"),
+    )
+    .expect("Golden file should be a JSON file");
+
+    assert_eq!(normalize(&output), golden);
+}
+
+#[test]
+fn non_contiguous_query_results_are_rejected() {
+    let output_directory = output_path("non-contiguous");
+    let blast_out_file = output_directory.join("blast.out");
+
+    //
+    // The first row is moved to the end of the blast output, splitting the
+    // results of the first query.
+    //
+    let content = read_to_string(mock_path("input/blast/query.blast.tsv"))
+        .expect("Canned blast output should be read");
+
+    let mut rows = content.lines().collect::<Vec<&str>>();
+    rows.rotate_left(1);
+
+    write(&blast_out_file, rows.join("\n") + "\n")
+        .expect("Blast output should be written");
+
+    let stream = stream_consensus_identities(
+        ParallelBlastOutput {
+            output_file: blast_out_file,
+            headers: None,
+            abundances: None,
+            columns: BlastTabularFormat::default(),
+            checksum: None,
+            failures: None,
+        },
+        &mock_path("input/ref_databases/mock-16S.blutils.json"),
+        Taxon::Bacteria,
+        ConsensusStrategy::Cautious,
+        Some(false),
+        None,
+        None,
+    )
+    .expect("Stream should be created")
+    .with_batch_size(2)
+    .expect("Batch size should be valid");
+
+    let batches = stream.collect::<Vec<_>>();
+
+    let error = batches
+        .last()
+        .and_then(|batch| batch.as_ref().err())
+        .expect("Stream should end with an error");
+
+    assert!(error.msg().contains("are not contiguous"));
+    assert!(batches[..batches.len() - 1].iter().all(Result::is_ok));
+}
@@ -1,9 +1,17 @@
 pub(crate) use blul_core::domain::dtos::{
-    blast_builder::Strand, consensus_strategy::ConsensusStrategy, taxon::Taxon,
+    blast_builder::{ScheduleSetting, Strand, DEFAULT_BLAST_OUT_FORMAT},
+    blast_tabular_format::BlastTabularFormat,
+    consensus_strategy::ConsensusStrategy,
+    consensus_summary::SummaryFormat,
+    feature_table::FeatureTableFormat,
+    linnaean_ranks::LinnaeanRank,
+    taxon::Taxon,
 };
 
+use crate::models::aligner::Aligner;
 use blul_core::{
-    domain::dtos::file_or_stdin::FileOrStdin, use_cases::OutputFormat,
+    domain::dtos::file_or_stdin::FileOrStdin,
+    use_cases::{OutputFormat, DEFAULT_STREAM_BATCH_SIZE},
 };
 use clap::Parser;
 use std::path::PathBuf;
@@ -24,19 +32,40 @@ pub(crate) enum Commands {
 
     /// Build tabular output.
     BuildTabular(BuildTabularArguments),
+
+    /// Summarize the community composition by taxonomic rank.
+    Summarize(SummarizeArguments),
+
+    /// Build a taxon-by-sample feature table from multiple blutils outputs.
+    BuildFeatureTable(BuildFeatureTableArguments),
 }
 
 #[derive(Parser, Debug)]
 pub(crate) struct RunBlastAndBuildConsensusArguments {
-    /// If the value is "-", the STDIN will be used and this command will expect
-    /// to receive the blutils output from the STDIN.
+    /// The query sequences in fasta or fastq format, optionally compressed
+    /// with gzip, bzip2, or zstd. If the value is "-", the STDIN will be used
+    /// and this command will expect to receive the query sequences from the
+    /// STDIN.
     #[clap(default_value = "-")]
     pub(super) query: FileOrStdin,
 
     /// The reference sequences system file path
+    ///
+    /// A blast database for the `blastn` aligner, a FASTA file for the
+    /// `native` aligner, or a FASTA or UDB file for the `vsearch` aligner.
     #[arg(short, long)]
     pub(super) database: String,
 
+    /// The aligner used to search the query sequences
+    ///
+    /// The `native` aligner runs without ncbi-blast+ against a FASTA
+    /// reference. Taxids are read from the `.taxid_map.txt` file beside the
+    /// reference, or resolved from the accessions of the taxonomy file. The
+    /// `vsearch` aligner runs `vsearch --usearch_global`, reading taxids from
+    /// the reference headers.
+    #[arg(long, default_value = "blastn")]
+    pub(super) aligner: Aligner,
+
     /// The taxonomy system file path
     #[arg(short, long)]
     pub(super) tax_file: String,
@@ -55,12 +84,25 @@ pub(crate) struct RunBlastAndBuildConsensusArguments {
 
     /// This option checks the higher taxon which the consensus search should be
     /// based
+    ///
+    /// The identity cutoffs of each rank are the registry preset of the same
+    /// name or alias. Shipped presets: fungi, bacteria, eukaryotes, its (or
+    /// its1 and its2), 16s-v4, 18s, coi, and rbcl. Use `custom` to provide the
+    /// cutoffs with the `--custom-taxon-cutoff-file` option.
     #[arg(long)]
     pub(super) taxon: Taxon,
 
     #[arg(long, short)]
     pub(super) custom_taxon_cutoff_file: Option<PathBuf>,
 
+    /// A cutoff registry file extending the shipped presets
+    ///
+    /// A YAML or JSON file with the layout of the shipped registry. Presets
+    /// override the shipped presets of the same name. Could be used multiple
+    /// times.
+    #[arg(long)]
+    pub(super) cutoff_registry: Vec<PathBuf>,
+
     /// The strategy to be used
     #[arg(long)]
     pub(super) strategy: ConsensusStrategy,
@@ -77,6 +119,14 @@ pub(crate) struct RunBlastAndBuildConsensusArguments {
     #[arg(short, long, default_value = "false")]
     pub(super) force_overwrite: bool,
 
+    /// Resume an interrupted blast run
+    ///
+    /// Queries already processed in the existing blast output file are not
+    /// submitted again. Finished chunks are registered in a manifest file
+    /// created alongside the blast output.
+    #[arg(long, default_value = "false", conflicts_with = "force_overwrite")]
+    pub(super) resume: bool,
+
     /// The max target sequences to be used. Default is 10.
     #[arg(short, long)]
     pub(super) max_target_seqs: Option<i32>,
@@ -94,12 +144,59 @@ pub(crate) struct RunBlastAndBuildConsensusArguments {
     pub(super) strand: Option<Strand>,
 
     /// The e-value to be used. Default is 0.001.
+    ///
+    /// Not supported by the `vsearch` aligner.
     #[arg(short, long)]
     pub(super) e_value: Option<f32>,
 
     /// The word size to be used. Default is 15.
     #[arg(short, long)]
     pub(super) word_size: Option<i32>,
+
+    /// The BLAST tabular output format
+    ///
+    /// The format is passed to the blastn `-outfmt` argument. The `qseqid`,
+    /// `saccver` (or `sacc`), `pident`, and `bitscore` fields are
+    /// required. Example: --blast-columns "6 qseqid saccver pident bitscore"
+    #[arg(long)]
+    pub(super) blast_columns: Option<BlastTabularFormat>,
+
+    /// The number of query sequences submitted to each blast process
+    ///
+    /// Use `auto` to pick the chunk size from the number of queries and the
+    /// available threads. Default is 50.
+    #[arg(long)]
+    pub(super) chunk_size: Option<ScheduleSetting>,
+
+    /// The number of threads used by each blast process
+    ///
+    /// The number of concurrent blast processes is the global threads divided
+    /// by this value. Default is `auto`, which splits the global threads among
+    /// the chunks that could run concurrently.
+    #[arg(long)]
+    pub(super) blast_threads: Option<ScheduleSetting>,
+
+    /// An accession to taxid mapping file
+    ///
+    /// Used to resolve subjects reported without taxid (or with taxid zero),
+    /// like in blast databases built without the `-taxid_map` option. Accepts
+    /// the NCBI `accession2taxid` format or a two columns tab separated file.
+    /// Accessions stored in the taxonomy file are always used as fallback.
+    #[arg(long)]
+    pub(super) accession_map: Option<PathBuf>,
+
+    /// Build consensus in batches of queries writing results incrementally
+    ///
+    /// Reduces the memory usage for large blast outputs. Requires the `jsonl`
+    /// output format and the blast results grouped by query. Results are
+    /// written in the blast output order, followed by queries without blast
+    /// results.
+    #[arg(long, default_value = "false")]
+    pub(super) stream: bool,
+
+    /// The number of queries of each streaming batch
+    #[arg(long, default_value_t = DEFAULT_STREAM_BATCH_SIZE, requires = "stream")]
+    pub(super) stream_batch_size: usize,
 }
 
 #[derive(Parser, Debug)]
@@ -117,12 +214,25 @@ pub(crate) struct BuildConsensusArguments {
 
     /// This option checks the higher taxon which the consensus search should be
     /// based
+    ///
+    /// The identity cutoffs of each rank are the registry preset of the same
+    /// name or alias. Shipped presets: fungi, bacteria, eukaryotes, its (or
+    /// its1 and its2), 16s-v4, 18s, coi, and rbcl. Use `custom` to provide the
+    /// cutoffs with the `--custom-taxon-cutoff-file` option.
     #[arg(long)]
     pub(super) taxon: Taxon,
 
     #[arg(long, short)]
     pub(super) custom_taxon_cutoff_file: Option<PathBuf>,
 
+    /// A cutoff registry file extending the shipped presets
+    ///
+    /// A YAML or JSON file with the layout of the shipped registry. Presets
+    /// override the shipped presets of the same name. Could be used multiple
+    /// times.
+    #[arg(long)]
+    pub(super) cutoff_registry: Vec<PathBuf>,
+
     /// The strategy to be used
     ///
     /// cautious: Select the shortest taxonomic path to find consensus from.
@@ -140,6 +250,36 @@ pub(crate) struct BuildConsensusArguments {
     /// The output file format
     #[arg(long, default_value = "json")]
     pub(super) out_format: OutputFormat,
+
+    /// The BLAST tabular output format
+    ///
+    /// The same format used with the blastn `-outfmt` argument to produce the
+    /// blast output. Columns are mapped by name. The `qseqid`, `saccver` (or
+    /// `sacc`), `pident`, and `bitscore` fields are required.
+    #[arg(long, default_value = DEFAULT_BLAST_OUT_FORMAT)]
+    pub(super) blast_columns: BlastTabularFormat,
+
+    /// An accession to taxid mapping file
+    ///
+    /// Used to resolve subjects reported without taxid (or with taxid zero),
+    /// like in blast databases built without the `-taxid_map` option. Accepts
+    /// the NCBI `accession2taxid` format or a two columns tab separated file.
+    /// Accessions stored in the taxonomy file are always used as fallback.
+    #[arg(long)]
+    pub(super) accession_map: Option<PathBuf>,
+
+    /// Build consensus in batches of queries writing results incrementally
+    ///
+    /// Reduces the memory usage for large blast outputs. Requires the `jsonl`
+    /// output format and the blast results grouped by query. Results are
+    /// written in the blast output order, followed by queries without blast
+    /// results.
+    #[arg(long, default_value = "false")]
+    pub(super) stream: bool,
+
+    /// The number of queries of each streaming batch
+    #[arg(long, default_value_t = DEFAULT_STREAM_BATCH_SIZE, requires = "stream")]
+    pub(super) stream_batch_size: usize,
 }
 
 #[derive(Parser, Debug)]
@@ -159,3 +299,61 @@ pub(crate) struct BuildTabularArguments {
     #[arg(short, long, default_value = "json")]
     pub(super) input_format: OutputFormat,
 }
+
+#[derive(Parser, Debug)]
+pub(crate) struct SummarizeArguments {
+    /// The blutils output file path or STDIN
+    ///
+    /// If the value is "-", the STDIN will be used and this command will expect
+    /// to receive the blutils output from the STDIN.
+    #[clap(default_value = "-")]
+    pub(super) blu_result: FileOrStdin,
+
+    /// The summary output file
+    #[arg(short, long)]
+    pub(super) output_file: Option<String>,
+
+    /// The input file format
+    #[arg(short, long, default_value = "json")]
+    pub(super) input_format: OutputFormat,
+
+    /// The summary output format
+    #[arg(short, long, default_value = "tsv")]
+    pub(super) summary_format: SummaryFormat,
+}
+
+#[derive(Parser, Debug)]
+pub(crate) struct BuildFeatureTableArguments {
+    /// The blutils output files
+    ///
+    /// Each file is included as a sample column of the feature table. If the
+    /// value is "-", the STDIN will be used as one of the samples.
+    #[clap(required = true, num_args = 1..)]
+    pub(super) blu_results: Vec<FileOrStdin>,
+
+    /// The taxonomic rank used to build the feature table rows
+    ///
+    /// Consensus taxonomies are truncated at the selected rank. Queries which
+    /// consensus not reach the rank are grouped as `Unassigned`. Example:
+    /// --rank genus
+    #[arg(short, long)]
+    pub(super) rank: LinnaeanRank,
+
+    /// Use the blutils run ID as the sample name
+    ///
+    /// If false, the input file name is used as the sample name.
+    #[arg(short, long, default_value = "false")]
+    pub(super) use_run_id: bool,
+
+    /// The feature table output file
+    #[arg(short, long)]
+    pub(super) output_file: Option<String>,
+
+    /// The input files format
+    #[arg(short, long, default_value = "json")]
+    pub(super) input_format: OutputFormat,
+
+    /// The feature table output format
+    #[arg(short, long, default_value = "tsv")]
+    pub(super) table_format: FeatureTableFormat,
+}
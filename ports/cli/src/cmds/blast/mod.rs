@@ -1,24 +1,38 @@
 mod commands;
 
-use self::commands::BuildTabularArguments;
+use self::commands::{
+    BuildFeatureTableArguments, BuildTabularArguments, SummarizeArguments,
+};
 pub(crate) use commands::{
     Arguments, BuildConsensusArguments, Commands,
     RunBlastAndBuildConsensusArguments,
 };
 
+use crate::models::aligner::Aligner;
+
 use blul_core::{
-    domain::dtos::{
-        blast_builder::BlastBuilder,
-        parallel_blast_output::ParallelBlastOutput,
-        taxon::{CustomTaxon, Taxon},
+    domain::{
+        dtos::{
+            blast_builder::BlastBuilder,
+            cutoff_registry::{CutoffPreset, CutoffRegistry},
+            parallel_blast_output::ParallelBlastOutput,
+            taxon::{CustomTaxon, Taxon},
+        },
+        entities::execute_blastn::ExecuteBlastn,
     },
     use_cases::{
-        build_consensus_identities, check_host_requirements,
-        parse_consensus_as_tabular, run_blast_and_build_consensus,
-        write_blutils_output,
+        build_consensus_identities, build_feature_table,
+        check_host_requirements, parse_consensus_as_tabular,
+        run_blast_and_build_consensus, stream_consensus_identities,
+        summarize_consensus, validate_stream_format, write_blutils_output,
+        write_blutils_output_stream,
     },
 };
-use blul_proc::execute_blast::ExecuteBlastnProcRepository;
+use blul_native::execute_blast::ExecuteBlastnNativeRepository;
+use blul_proc::{
+    execute_blast::ExecuteBlastnProcRepository,
+    execute_vsearch::ExecuteVsearchProcRepository,
+};
 use std::path::{Path, PathBuf};
 
 pub(crate) fn run_blast_and_build_consensus_cmd(
@@ -31,12 +45,30 @@ pub(crate) fn run_blast_and_build_consensus_cmd(
         std::env::set_var("RUST_LOG", "none");
     }
 
-    // Execute system checks before running the blast
-    if let Err(err) = check_host_requirements(Some("debug")) {
-        panic!("{err}");
-    }
+    let repo: Box<dyn ExecuteBlastn> = match args.aligner {
+        Aligner::Blastn => {
+            // Execute system checks before running the blast
+            if let Err(err) = check_host_requirements(Some("debug")) {
+                panic!("{err}");
+            }
+
+            Box::new(ExecuteBlastnProcRepository {})
+        }
+        Aligner::Native => Box::new(ExecuteBlastnNativeRepository::default()),
+        Aligner::Vsearch => {
+            //
+            // vsearch e-values are computed using the target length as the
+            // search space, so the blast e-value threshold is not comparable.
+            //
+            if args.e_value.is_some() {
+                panic!(
+                    "The `--e-value` option is not supported by the vsearch aligner"
+                );
+            }
 
-    let repo = ExecuteBlastnProcRepository {};
+            Box::new(ExecuteVsearchProcRepository {})
+        }
+    };
 
     // Create configuration DTO
     let mut config =
@@ -66,22 +98,31 @@ pub(crate) fn run_blast_and_build_consensus_cmd(
         config = config.with_word_size(args.word_size.unwrap());
     }
 
+    if let Some(blast_columns) = args.blast_columns {
+        config = config.with_out_format(blast_columns);
+    }
+
+    if let Some(chunk_size) = args.chunk_size {
+        config = config.with_chunk_size(chunk_size);
+    }
+
+    if let Some(blast_threads) = args.blast_threads {
+        config = config.with_blast_threads(blast_threads);
+    }
+
     // Set the default number of threads
     let threads = match threads {
         Some(n) => n,
         None => 1,
     };
 
-    let custom_taxon = match args.custom_taxon_cutoff_file {
-        Some(file) => Some(CustomTaxon::from_file(file)),
-        None => {
-            if let Taxon::Custom = args.taxon {
-                panic!("Custom taxon values are required when the custom taxon option is selected.");
-            }
+    let taxon_cutoffs = resolve_taxon_cutoffs(
+        &args.taxon,
+        args.custom_taxon_cutoff_file,
+        args.cutoff_registry,
+    );
 
-            None
-        }
-    };
+    let config = config.with_taxon_cutoffs(taxon_cutoffs.to_owned());
 
     if let Err(err) = run_blast_and_build_consensus(
         args.query,
@@ -89,13 +130,16 @@ pub(crate) fn run_blast_and_build_consensus_cmd(
         &args.blast_out_file,
         args.blutils_out_file,
         config,
-        &repo,
+        repo.as_ref(),
         &args.force_overwrite,
+        &args.resume,
         threads,
         args.strategy,
         Some(args.use_taxid),
         args.out_format,
-        custom_taxon,
+        Some(taxon_cutoffs.cutoffs),
+        args.accession_map,
+        args.stream.then_some(args.stream_batch_size),
     ) {
         panic!("{err}")
     };
@@ -108,27 +152,61 @@ pub(crate) fn build_consensus_cmd(args: BuildConsensusArguments) {
         std::env::set_var("RUST_LOG", "none");
     }
 
-    let custom_taxon = match args.custom_taxon_cutoff_file {
-        Some(file) => Some(CustomTaxon::from_file(file)),
-        None => {
-            if let Taxon::Custom = args.taxon {
-                panic!("Custom taxon values are required when the custom taxon option is selected.");
-            }
+    let taxon_cutoffs = resolve_taxon_cutoffs(
+        &args.taxon,
+        args.custom_taxon_cutoff_file,
+        args.cutoff_registry,
+    );
 
-            None
-        }
+    let blast_output = ParallelBlastOutput {
+        output_file: PathBuf::from(args.blast_out),
+        headers: None,
+        abundances: None,
+        columns: args.blast_columns,
+        checksum: None,
+        failures: None,
     };
 
+    if args.stream {
+        if let Err(err) = validate_stream_format(&args.out_format) {
+            panic!("{err}");
+        }
+
+        let stream = match stream_consensus_identities(
+            blast_output,
+            Path::new(&args.tax_file),
+            args.taxon,
+            args.strategy,
+            Some(args.use_taxid),
+            Some(taxon_cutoffs.cutoffs.to_owned()),
+            args.accession_map.as_deref(),
+        )
+        .and_then(|stream| stream.with_batch_size(args.stream_batch_size))
+        {
+            Ok(stream) => stream,
+            Err(err) => panic!("{err}"),
+        };
+
+        if let Err(err) = write_blutils_output_stream(
+            stream,
+            None,
+            Some(taxon_cutoffs),
+            args.blutils_out_file,
+        ) {
+            panic!("{err}");
+        };
+
+        return;
+    }
+
     let blast_output = match build_consensus_identities(
-        ParallelBlastOutput {
-            output_file: PathBuf::from(args.blast_out),
-            headers: None,
-        },
+        blast_output,
         Path::new(&args.tax_file),
         args.taxon,
         args.strategy,
         Some(args.use_taxid),
-        custom_taxon,
+        Some(taxon_cutoffs.cutoffs.to_owned()),
+        args.accession_map.as_deref(),
     ) {
         Ok(results) => results,
         Err(err) => panic!("{err}"),
@@ -137,6 +215,7 @@ pub(crate) fn build_consensus_cmd(args: BuildConsensusArguments) {
     if let Err(err) = write_blutils_output(
         blast_output.to_owned(),
         None,
+        Some(taxon_cutoffs),
         args.blutils_out_file,
         args.out_format,
     ) {
@@ -144,6 +223,35 @@ pub(crate) fn build_consensus_cmd(args: BuildConsensusArguments) {
     };
 }
 
+/// Resolve the taxon cutoffs using the shipped registry extended by the user
+/// registries
+fn resolve_taxon_cutoffs(
+    taxon: &Taxon,
+    custom_taxon_cutoff_file: Option<PathBuf>,
+    cutoff_registries: Vec<PathBuf>,
+) -> CutoffPreset {
+    let mut registry = match CutoffRegistry::shipped() {
+        Ok(registry) => registry.to_owned(),
+        Err(err) => panic!("{err}"),
+    };
+
+    for file in cutoff_registries {
+        match CutoffRegistry::from_file(&file) {
+            Ok(user_registry) => {
+                registry = registry.with_registry(user_registry)
+            }
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    match registry
+        .resolve(taxon, custom_taxon_cutoff_file.map(CustomTaxon::from_file))
+    {
+        Ok(taxon_cutoffs) => taxon_cutoffs,
+        Err(err) => panic!("{err}"),
+    }
+}
+
 pub(crate) fn build_tabular_cmd(args: BuildTabularArguments) {
     // If output_file the output will be redirect to stdout. Than, the
     // RUST_LOG environment variable will be set to none.
@@ -153,11 +261,46 @@ pub(crate) fn build_tabular_cmd(args: BuildTabularArguments) {
 
     match parse_consensus_as_tabular(
         args.blu_result,
-        match args.output_file {
-            Some(file) => Some(PathBuf::from(file)),
-            None => None,
-        },
+        args.output_file.map(PathBuf::from),
+        args.input_format,
+    ) {
+        Ok(_) => (),
+        Err(err) => panic!("{err}"),
+    };
+}
+
+pub(crate) fn summarize_cmd(args: SummarizeArguments) {
+    // If output_file the output will be redirect to stdout. Than, the
+    // RUST_LOG environment variable will be set to none.
+    if let None = args.output_file {
+        std::env::set_var("RUST_LOG", "none");
+    }
+
+    match summarize_consensus(
+        args.blu_result,
+        args.output_file.map(PathBuf::from),
+        args.input_format,
+        args.summary_format,
+    ) {
+        Ok(_) => (),
+        Err(err) => panic!("{err}"),
+    };
+}
+
+pub(crate) fn build_feature_table_cmd(args: BuildFeatureTableArguments) {
+    // If output_file the output will be redirect to stdout. Than, the
+    // RUST_LOG environment variable will be set to none.
+    if let None = args.output_file {
+        std::env::set_var("RUST_LOG", "none");
+    }
+
+    match build_feature_table(
+        args.blu_results,
+        args.rank,
+        args.use_run_id,
+        args.output_file.map(PathBuf::from),
         args.input_format,
+        args.table_format,
     ) {
         Ok(_) => (),
         Err(err) => panic!("{err}"),
@@ -1,3 +1,7 @@
+use blul_core::domain::dtos::{
+    taxonomies_database::TaxonomiesDatabaseFormat,
+    taxonomies_map::RankFillingStrategy, taxonomies_report::ReportFormat,
+};
 use clap::{ArgAction, Parser};
 use std::path::PathBuf;
 
@@ -12,11 +16,32 @@ pub(crate) enum Commands {
     /// Build the Blutils database.
     Blu(BuildBlutilsDatabaseArguments),
 
+    /// Update the Blutils database from a new taxdump.
+    Update(UpdateBlutilsDatabaseArguments),
+
+    /// Subset the Blutils database by taxonomic clades.
+    Subset(SubsetBlutilsDatabaseArguments),
+
+    /// Report statistics and issues of the Blutils database.
+    Inspect(InspectBlutilsDatabaseArguments),
+
     /// Build QIIME database from the Blutils database.
     Qiime2(BuildQiimeDatabaseArguments),
 
     /// Build Kraken2 database from the Blutils database.
     Kraken2(BuildKraken2DatabaseArguments),
+
+    /// Build vsearch/usearch SINTAX database from the Blutils database.
+    Sintax(BuildClassifierDatabaseArguments),
+
+    /// Build usearch UTAX database from the Blutils database.
+    Utax(BuildClassifierDatabaseArguments),
+
+    /// Build DADA2 assignTaxonomy database from the Blutils database.
+    Dada2(BuildClassifierDatabaseArguments),
+
+    /// Build mothur classify.seqs database from the Blutils database.
+    Mothur(BuildClassifierDatabaseArguments),
 }
 
 #[derive(Parser, Debug)]
@@ -41,9 +66,19 @@ pub(crate) struct BuildBlutilsDatabaseArguments {
 
     /// The path where the output file will be saved
     ///
-    /// The output file is a JSON file that contains the taxonomies database.
+    /// The output file contains the taxonomies database. It is saved with the
+    /// `.blutils.bin` or `.blutils.json` suffix given the output format.
     pub(super) output_file_path: PathBuf,
 
+    /// The taxonomies database format
+    ///
+    /// The binary format is indexed by taxid and memory-mapped when used, so
+    /// only the taxonomies of the blast subjects are loaded. The JSON format
+    /// is fully loaded into memory. Both formats are accepted by all commands
+    /// that read the taxonomies database.
+    #[arg(long, default_value = "json")]
+    pub(super) output_format: TaxonomiesDatabaseFormat,
+
     /// Drop non Linnaean taxonomies
     ///
     /// If this option is set, the non Linnaean taxonomies will be dropped from
@@ -54,6 +89,21 @@ pub(crate) struct BuildBlutilsDatabaseArguments {
     #[arg(short, long, action=ArgAction::SetTrue)]
     pub(super) drop_non_linnaean_taxonomies: Option<bool>,
 
+    /// Fill the Linnaean ranks missing from lineages
+    ///
+    /// If this option is set, every lineage will contain exactly the domain,
+    /// kingdom, phylum, class, order, family, genus, and species ranks. Missing
+    /// ranks will be filled with a placeholder derived from the closest
+    /// ancestor, and non Linnaean ranks will be removed from lineages. The
+    /// option is disabled by default.
+    ///
+    /// Placeholders of numeric lineages are not taxids, as
+    /// `c__unclassified-1239` or `d__unclassified`. Tools reading numeric
+    /// lineages, as the Kraken2 export and the subset by taxid, skip them.
+    ///
+    #[arg(long)]
+    pub(super) fill_missing_ranks: Option<RankFillingStrategy>,
+
     /// Specify taxids to be skipped
     /// Example: --skip-taxid 131567
     ///
@@ -75,6 +125,92 @@ pub(crate) struct BuildBlutilsDatabaseArguments {
     pub(super) replace_rank: Option<Vec<String>>,
 }
 
+#[derive(Parser, Debug)]
+pub(crate) struct UpdateBlutilsDatabaseArguments {
+    /// The path to the existing blutils taxonomy database
+    pub(super) taxonomies_database_path: PathBuf,
+
+    /// The path to the new taxdump directory
+    ///
+    /// The taxdump `merged.dmp` and `delnodes.dmp` files are used to remap or
+    /// drop the changed taxids, and the names, nodes, and lineages files are
+    /// used to rebuild the lineages that changed.
+    ///
+    pub(super) taxdump_directory_path: PathBuf,
+
+    /// The path where the output file will be saved
+    ///
+    /// The updated taxonomies database is saved with the `.blutils.bin` or
+    /// `.blutils.json` suffix given the output format. The list of changed
+    /// taxids is saved with the `.changelog.tsv` suffix.
+    pub(super) output_file_path: PathBuf,
+
+    /// The taxonomies database format
+    #[arg(long, default_value = "json")]
+    pub(super) output_format: TaxonomiesDatabaseFormat,
+}
+
+#[derive(Parser, Debug)]
+pub(crate) struct SubsetBlutilsDatabaseArguments {
+    /// The path to the blutils taxonomy database
+    pub(super) taxonomies_database_path: PathBuf,
+
+    /// The path where the output file will be saved
+    ///
+    /// The subset taxonomies database is saved with the `.blutils.bin` or
+    /// `.blutils.json` suffix given the output format.
+    pub(super) output_file_path: PathBuf,
+
+    /// Clades to be included in the subset
+    /// Example: --include 'k__fungi'
+    ///
+    /// Clades are given as taxids or as `rank__name` strings. Taxonomies
+    /// containing any of the included clades in the lineage are kept. If no
+    /// clades are included, all taxonomies not excluded are kept.
+    /// Example: --include 2 --include 'k__fungi'
+    ///
+    #[arg(short, long)]
+    pub(super) include: Option<Vec<String>>,
+
+    /// Clades to be excluded from the subset
+    /// Example: --exclude 'p__pseudomonadota'
+    ///
+    /// Clades are given as taxids or as `rank__name` strings. Taxonomies
+    /// containing any of the excluded clades in the lineage are dropped.
+    ///
+    #[arg(short, long)]
+    pub(super) exclude: Option<Vec<String>>,
+
+    /// The path to the blast database
+    ///
+    /// If specified, the sequences of the subset accessions are pulled from
+    /// the blast database and saved with the `.fasta` suffix, together with
+    /// the `.taxid_map.txt` file expected by the `makeblastdb -taxid_map`
+    /// option.
+    #[arg(short, long)]
+    pub(super) blast_database_path: Option<PathBuf>,
+
+    /// The taxonomies database format
+    #[arg(long, default_value = "json")]
+    pub(super) output_format: TaxonomiesDatabaseFormat,
+}
+
+#[derive(Parser, Debug)]
+pub(crate) struct InspectBlutilsDatabaseArguments {
+    /// The path to the blutils taxonomy database
+    pub(super) taxonomies_database_path: PathBuf,
+
+    /// The report output file
+    ///
+    /// If not specified, the report is printed to the standard output.
+    #[arg(short, long)]
+    pub(super) output_file: Option<PathBuf>,
+
+    /// The report output format
+    #[arg(short, long, default_value = "text")]
+    pub(super) report_format: ReportFormat,
+}
+
 #[derive(Parser, Debug)]
 pub(crate) struct BuildQiimeDatabaseArguments {
     /// The path to the blutils taxonomy database
@@ -97,6 +233,28 @@ pub(crate) struct BuildQiimeDatabaseArguments {
     pub(super) use_taxid: bool,
 }
 
+#[derive(Parser, Debug)]
+pub(crate) struct BuildClassifierDatabaseArguments {
+    /// The path to the blutils taxonomy database
+    pub(super) taxonomies_database_path: PathBuf,
+
+    /// The path to the blast database
+    pub(super) blast_database_path: PathBuf,
+
+    /// The path to the output files
+    ///
+    /// Sequences are saved with the `.fasta` extension. The mothur taxonomy
+    /// file is saved with the `.tax` extension.
+    pub(super) output_file_path: PathBuf,
+
+    /// Use taxid instead of taxonomy
+    ///
+    /// If true, the numeric lineages (taxids) will be exported instead of the
+    /// taxa names.
+    #[arg(short, long, default_value = "false")]
+    pub(super) use_taxid: bool,
+}
+
 #[derive(Parser, Debug)]
 pub(crate) struct BuildKraken2DatabaseArguments {
     /// The path to the blast database
@@ -105,4 +263,25 @@ pub(crate) struct BuildKraken2DatabaseArguments {
     /// The path to the QIIME output taxonomies file
     #[arg(short, long)]
     pub(super) output_directory: PathBuf,
+
+    /// The path to the taxdump directory
+    ///
+    /// If specified, the `taxonomy` directory expected by `kraken2-build` is
+    /// written to the output directory. It contains the `names.dmp` and
+    /// `nodes.dmp` files of the taxdump pruned to the taxids of the blast
+    /// database and their ancestors. Use the taxdump the Blutils database was
+    /// built from.
+    #[arg(short, long)]
+    pub(super) taxdump_directory_path: Option<PathBuf>,
+
+    /// The path to the Blutils database
+    ///
+    /// If specified, the Blutils database is used as the source of truth of
+    /// the exported library. Sequences whose taxid was ignored or dropped
+    /// while building the Blutils database are skipped, and the `taxonomy`
+    /// directory is built from the Blutils lineages, reflecting the replaced
+    /// ranks. Names are copied from the taxdump if the taxdump directory is
+    /// also specified.
+    #[arg(short = 'b', long)]
+    pub(super) taxonomies_database_path: Option<PathBuf>,
 }
@@ -3,11 +3,18 @@ mod commands;
 use self::commands::BuildKraken2DatabaseArguments;
 
 use blul_core::use_cases::{
-    build_kraken_db_from_ncbi_files, build_qiime_db_from_blutils_db,
-    build_ref_db_from_ncbi_files, check_host_requirements,
+    build_classifier_db_from_blutils_db, build_kraken_db_from_ncbi_files,
+    build_qiime_db_from_blutils_db, build_ref_db_from_ncbi_files,
+    build_subset_db_from_blutils_db, check_host_requirements,
+    inspect_blutils_db, update_ref_db_from_ncbi_files,
+    ClassifierDatabaseFormat,
 };
-use commands::BuildQiimeDatabaseArguments;
 pub(crate) use commands::{Arguments, BuildBlutilsDatabaseArguments, Commands};
+use commands::{
+    BuildClassifierDatabaseArguments, BuildQiimeDatabaseArguments,
+    InspectBlutilsDatabaseArguments, SubsetBlutilsDatabaseArguments,
+    UpdateBlutilsDatabaseArguments,
+};
 
 pub(crate) fn run_blast_and_build_consensus_cmd(
     args: BuildBlutilsDatabaseArguments,
@@ -37,13 +44,69 @@ pub(crate) fn run_blast_and_build_consensus_cmd(
             None => None,
         },
         args.drop_non_linnaean_taxonomies,
+        args.fill_missing_ranks,
+        args.output_file_path,
+        args.output_format,
+    ) {
+        Err(err) => panic!("{err}"),
+        Ok(_) => (),
+    };
+}
+
+pub(crate) fn update_ref_db_from_ncbi_files_cmd(
+    args: UpdateBlutilsDatabaseArguments,
+) {
+    match update_ref_db_from_ncbi_files(
+        &args.taxonomies_database_path,
+        args.taxdump_directory_path,
+        args.output_file_path,
+        args.output_format,
+    ) {
+        Err(err) => panic!("{err}"),
+        Ok(_) => (),
+    };
+}
+
+pub(crate) fn build_subset_db_from_blutils_db_cmd(
+    args: SubsetBlutilsDatabaseArguments,
+) {
+    // Execute system checks before running blastdbcmd
+    if args.blast_database_path.is_some() {
+        if let Err(err) = check_host_requirements(Some("debug")) {
+            panic!("{err}");
+        }
+    }
+
+    match build_subset_db_from_blutils_db(
+        &args.taxonomies_database_path,
+        args.include.unwrap_or_default(),
+        args.exclude.unwrap_or_default(),
+        args.blast_database_path,
         args.output_file_path,
+        args.output_format,
     ) {
         Err(err) => panic!("{err}"),
         Ok(_) => (),
     };
 }
 
+pub(crate) fn inspect_blutils_db_cmd(args: InspectBlutilsDatabaseArguments) {
+    // If output_file the output will be redirect to stdout. Than, the
+    // RUST_LOG environment variable will be set to none.
+    if let None = args.output_file {
+        std::env::set_var("RUST_LOG", "none");
+    }
+
+    match inspect_blutils_db(
+        &args.taxonomies_database_path,
+        args.output_file,
+        args.report_format,
+    ) {
+        Ok(_) => (),
+        Err(err) => panic!("{err}"),
+    };
+}
+
 pub(crate) fn build_qiime_db_from_blutils_db_cmd(
     args: BuildQiimeDatabaseArguments,
 ) {
@@ -64,6 +127,27 @@ pub(crate) fn build_qiime_db_from_blutils_db_cmd(
     };
 }
 
+pub(crate) fn build_classifier_db_from_blutils_db_cmd(
+    args: BuildClassifierDatabaseArguments,
+    format: ClassifierDatabaseFormat,
+) {
+    // Execute system checks before running the blast
+    if let Err(err) = check_host_requirements(Some("debug")) {
+        panic!("{err}");
+    }
+
+    match build_classifier_db_from_blutils_db(
+        &args.taxonomies_database_path,
+        &args.blast_database_path,
+        args.output_file_path,
+        format,
+        Some(args.use_taxid),
+    ) {
+        Err(err) => panic!("{err}"),
+        Ok(_) => (),
+    };
+}
+
 pub(crate) fn build_kraken_db_from_ncbi_files_cmd(
     args: BuildKraken2DatabaseArguments,
 ) {
@@ -75,6 +159,8 @@ pub(crate) fn build_kraken_db_from_ncbi_files_cmd(
     match build_kraken_db_from_ncbi_files(
         &args.blast_database_path,
         args.output_directory,
+        args.taxdump_directory_path,
+        args.taxonomies_database_path,
     ) {
         Err(err) => panic!("{err}"),
         Ok(_) => (),
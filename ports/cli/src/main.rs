@@ -2,6 +2,7 @@ mod cmds;
 mod models;
 
 use anyhow::Result;
+use blul_core::use_cases::ClassifierDatabaseFormat;
 use clap::Subcommand;
 use cmds::{blast, check, db_builder};
 use models::{cli_launcher::CliLauncher, log_format::LogFormat};
@@ -108,12 +109,45 @@ fn main() -> Result<()> {
             db_builder::Commands::Blu(args) => {
                 db_builder::run_blast_and_build_consensus_cmd(args)
             }
+            db_builder::Commands::Update(args) => {
+                db_builder::update_ref_db_from_ncbi_files_cmd(args)
+            }
+            db_builder::Commands::Subset(args) => {
+                db_builder::build_subset_db_from_blutils_db_cmd(args)
+            }
+            db_builder::Commands::Inspect(args) => {
+                db_builder::inspect_blutils_db_cmd(args)
+            }
             db_builder::Commands::Qiime2(args) => {
                 db_builder::build_qiime_db_from_blutils_db_cmd(args)
             }
             db_builder::Commands::Kraken2(args) => {
                 db_builder::build_kraken_db_from_ncbi_files_cmd(args)
             }
+            db_builder::Commands::Sintax(args) => {
+                db_builder::build_classifier_db_from_blutils_db_cmd(
+                    args,
+                    ClassifierDatabaseFormat::Sintax,
+                )
+            }
+            db_builder::Commands::Utax(args) => {
+                db_builder::build_classifier_db_from_blutils_db_cmd(
+                    args,
+                    ClassifierDatabaseFormat::Utax,
+                )
+            }
+            db_builder::Commands::Dada2(args) => {
+                db_builder::build_classifier_db_from_blutils_db_cmd(
+                    args,
+                    ClassifierDatabaseFormat::Dada2,
+                )
+            }
+            db_builder::Commands::Mothur(args) => {
+                db_builder::build_classifier_db_from_blutils_db_cmd(
+                    args,
+                    ClassifierDatabaseFormat::Mothur,
+                )
+            }
         },
         Opts::Blastn(blast_args) => {
             match blast_args.run_blast {
@@ -129,6 +163,10 @@ fn main() -> Result<()> {
                 blast::Commands::BuildTabular(args) => {
                     blast::build_tabular_cmd(args)
                 }
+                blast::Commands::Summarize(args) => blast::summarize_cmd(args),
+                blast::Commands::BuildFeatureTable(args) => {
+                    blast::build_feature_table_cmd(args)
+                }
             };
         }
         Opts::Check(check_args) => {
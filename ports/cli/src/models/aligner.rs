@@ -0,0 +1,21 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "camelCase")]
+pub enum Aligner {
+    /// NCBI blastn
+    ///
+    /// Requires ncbi-blast+ installed and a blast database as reference.
+    Blastn,
+
+    /// Pure Rust aligner
+    ///
+    /// Runs in-process against a FASTA reference, without ncbi-blast+.
+    Native,
+
+    /// vsearch global alignment
+    ///
+    /// Requires vsearch installed and a FASTA or UDB file as reference.
+    Vsearch,
+}
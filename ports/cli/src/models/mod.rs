@@ -1,2 +1,3 @@
+pub(crate) mod aligner;
 pub(crate) mod cli_launcher;
 pub(crate) mod log_format;